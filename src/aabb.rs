@@ -46,4 +46,24 @@ impl Aabb {
         let t_max = ffmin(tmax, t0.max(&t1).min_elem());
         t_max > t_min
     }
+
+    // The part of the ray's range inside the box, if any.
+    pub fn hit_range(&self, ray: &Ray, tmin: f32, tmax: f32) -> Option<(f32, f32)> {
+        let (mut t0, mut t1) = (tmin, tmax);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction.get(axis);
+            let mut near = (self.min.get(axis) - ray.origin.get(axis)) * inverse;
+            let mut far = (self.max.get(axis) - ray.origin.get(axis)) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from a zero direction with the origin on a slab leaves t alone.
+            if near > t0 { t0 = near; }
+            if far < t1 { t1 = far; }
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
 }
@@ -0,0 +1,150 @@
+use crate::film::{Film, Sample};
+use crate::imageio;
+use crate::vec3::Vec3;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+// Number of lights that can be split out into passes of their own.
+pub const MAX_LIGHT_AOVS : usize = 8;
+
+// Arbitrary output variables, extra passes rendered alongside the beauty image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aov {
+    /// Distance along the camera ray to the first hit.
+    Depth,
+    /// World space shading normal at the first hit.
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    Uv,
+    Emission,
+    /// Emission reached after exactly one bounce. There's no light sampling, so
+    /// this is the one bounce part of the path traced estimate rather than
+    /// direct lighting computed towards the lights.
+    Direct,
+    /// Emission reached after two or more bounces.
+    Indirect,
+    /// Contribution of the light whose object id is in this slot of the render's
+    /// light list, the slot has to be below MAX_LIGHT_AOVS.
+    Light(usize),
+}
+
+pub enum AovOutput {
+    /// One PFM per pass, named `<prefix>_<pass>.pfm`.
+    Separate(String),
+    /// A single multi-layer EXR, with the linear beauty image as R, G, B.
+    Exr(String),
+}
+
+impl Aov {
+    pub fn name(&self) -> String {
+        match self {
+            Aov::Depth => String::from("depth"),
+            Aov::Normal => String::from("normal"),
+            Aov::Albedo => String::from("albedo"),
+            Aov::ObjectId => String::from("objectid"),
+            Aov::MaterialId => String::from("materialid"),
+            Aov::Uv => String::from("uv"),
+            Aov::Emission => String::from("emission"),
+            Aov::Direct => String::from("direct"),
+            Aov::Indirect => String::from("indirect"),
+            Aov::Light(index) => format!("light{}", index),
+        }
+    }
+
+    // Inverse of `name`.
+    pub fn from_name(name : &str) -> Option<Aov> {
+        let aov = match name {
+            "depth" => Aov::Depth,
+            "normal" => Aov::Normal,
+            "albedo" => Aov::Albedo,
+            "objectid" => Aov::ObjectId,
+            "materialid" => Aov::MaterialId,
+            "uv" => Aov::Uv,
+            "emission" => Aov::Emission,
+            "direct" => Aov::Direct,
+            "indirect" => Aov::Indirect,
+            _ => Aov::Light(name.strip_prefix("light")?.parse().ok().filter(|&slot| slot < MAX_LIGHT_AOVS)?),
+        };
+        Some(aov)
+    }
+
+    fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Uv => &["U", "V"],
+            Aov::Normal => &["X", "Y", "Z"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    fn values(&self, sample : &Sample) -> [f32; 3] {
+        let v = match self {
+            Aov::Depth => return [sample.depth, 0.0, 0.0],
+            Aov::ObjectId => return [sample.object_id as f32, 0.0, 0.0],
+            Aov::MaterialId => return [sample.material_id as f32, 0.0, 0.0],
+            Aov::Normal => sample.normal,
+            Aov::Albedo => sample.albedo,
+            Aov::Uv => sample.uv,
+            Aov::Emission => sample.emission,
+            Aov::Direct => sample.direct,
+            Aov::Indirect => sample.indirect,
+            Aov::Light(index) => sample.lights[*index],
+        };
+        [*v.x(), *v.y(), *v.z()]
+    }
+
+    // One (full channel name, values) entry per channel of the pass.
+    fn channels(&self, samples : &[Sample]) -> Vec<(String, Vec<f32>)> {
+        self.channel_names()
+            .iter()
+            .enumerate()
+            .map(|(channel, suffix)| {
+                let values = samples.iter().map(|sample| self.values(sample)[channel]).collect();
+                (format!("{}.{}", self.name(), suffix), values)
+            })
+            .collect()
+    }
+}
+
+pub fn write_aovs(output : &AovOutput, film : &Film, aovs : &[Aov]) -> io::Result<()> {
+    if let Some(aov) = aovs.iter().find(|aov| matches!(aov, Aov::Light(slot) if *slot >= MAX_LIGHT_AOVS)) {
+        let message = format!("{} is past the last of the {} light passes", aov.name(), MAX_LIGHT_AOVS);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    let samples = film.resolve_samples();
+    let (nx, ny) = (film.nx, film.ny);
+    match output {
+        AovOutput::Separate(prefix) => {
+            for aov in aovs {
+                let mut out = BufWriter::new(File::create(format!("{}_{}.pfm", prefix, aov.name()))?);
+                let mut channels = aov.channels(&samples);
+                if channels.len() == 1 {
+                    imageio::write_pfm_mono(&mut out, nx, ny, &channels[0].1)?;
+                } else {
+                    // PFM only does one or three channels, pad the two channel ones
+                    channels.resize(3, (String::new(), vec![0.0; nx * ny]));
+                    let pixels : Vec<_> = (0..nx * ny)
+                        .map(|i| Vec3::from(channels[0].1[i], channels[1].1[i], channels[2].1[i]))
+                        .collect();
+                    imageio::write_pfm(&mut out, nx, ny, &pixels)?;
+                }
+            }
+            Ok(())
+        }
+        AovOutput::Exr(path) => {
+            let mut channels : Vec<(String, Vec<f32>)> = vec![
+                (String::from("R"), samples.iter().map(|sample| *sample.colour.r()).collect()),
+                (String::from("G"), samples.iter().map(|sample| *sample.colour.g()).collect()),
+                (String::from("B"), samples.iter().map(|sample| *sample.colour.b()).collect()),
+            ];
+            for aov in aovs {
+                channels.append(&mut aov.channels(&samples));
+            }
+            imageio::write_exr(&mut BufWriter::new(File::create(path)?), nx, ny, &channels)
+        }
+    }
+}
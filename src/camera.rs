@@ -1,11 +1,233 @@
+use crate::imageio;
 use crate::ray::Ray;
 use crate::rng::Random;
+use crate::texture::TextureError;
 use crate::vec3::*;
 
 use std::f32;
+use std::io;
 
+// (s, t) is the position on the image, both in [0,1] with t going up.
+// Cameras that can block rays (lens housings, apertures) return None for
+// those, and the sample counts as black.
+pub trait Camera: Send + Sync {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay>;
+    // Earliest and latest time a ray can be sent out at, for building the BVH.
+    fn time_range(&self) -> (f32, f32);
+}
+
+// The radiance along `ray` is scaled by `weight`, for vignetting and exposure.
 #[derive(Copy, Clone)]
-pub struct Camera {
+pub struct CameraRay {
+    pub ray: Ray,
+    pub weight: f32,
+}
+
+impl CameraRay {
+    pub fn unweighted(ray: Ray) -> Option<CameraRay> {
+        Some(CameraRay { ray, weight: 1.0 })
+    }
+}
+
+// Orthonormal camera frame: u to the right, v up and w pointing backwards.
+pub fn camera_frame(look_from: &Vec3, look_at: &Vec3, camera_up: &Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).make_normalised();
+    let u = cross(camera_up, &w).make_normalised();
+    let v = cross(&w, &u);
+    (u, v, w)
+}
+
+// How far open the shutter is over the exposure, as a piecewise linear
+// curve of (fraction of the exposure, efficiency) points. Ray times are
+// sampled in proportion to the efficiency, so the curve shapes motion blur
+// trails without changing the overall brightness.
+#[derive(Clone)]
+pub struct ShutterCurve {
+    points: Vec<(f32, f32)>,
+    // Running area under the curve up to the end of each segment.
+    areas: Vec<f32>,
+}
+
+impl ShutterCurve {
+    // Instantly open for the whole exposure.
+    pub fn box_curve() -> Self {
+        ShutterCurve::from_points(vec![(0.0, 1.0), (1.0, 1.0)])
+    }
+
+    // Opens over the first `open_ramp` and closes over the last `close_ramp`
+    // fraction of the exposure.
+    pub fn trapezoid(open_ramp: f32, close_ramp: f32) -> Self {
+        let open_ramp = open_ramp.clamp(0.0, 1.0);
+        let close_ramp = close_ramp.clamp(0.0, 1.0 - open_ramp);
+        ShutterCurve::from_points(vec![(0.0, 0.0), (open_ramp, 1.0), (1.0 - close_ramp, 1.0), (1.0, 0.0)])
+    }
+
+    // Points are sorted by time, which runs from 0 to 1 over the exposure.
+    // Falls back to the box curve if the points don't enclose any area.
+    pub fn from_points(mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut total = 0.0;
+        let mut areas = Vec::with_capacity(points.len());
+        for segment in points.windows(2) {
+            let ((t0, e0), (t1, e1)) = (segment[0], segment[1]);
+            total += 0.5 * (e0.max(0.0) + e1.max(0.0)) * (t1 - t0);
+            areas.push(total);
+        }
+        if total <= 0.0 || !total.is_finite() {
+            return ShutterCurve::box_curve();
+        }
+        ShutterCurve { points, areas }
+    }
+
+    // Fraction of the exposure in [0,1], distributed like the efficiency.
+    fn sample(&self, rnd: &mut Random) -> f32 {
+        let total = self.areas[self.areas.len() - 1];
+        let target = rnd.gen() * total;
+        let segment = self.areas.partition_point(|&a| a <= target).min(self.areas.len() - 1);
+        let start = if segment == 0 { 0.0 } else { self.areas[segment - 1] };
+        let ((t0, e0), (t1, e1)) = (self.points[segment], self.points[segment + 1]);
+        let (e0, e1) = (e0.max(0.0), e1.max(0.0));
+        // Invert the area under the line e0 + (e1 - e0) x over the segment.
+        let width = t1 - t0;
+        let area = (target - start) / width.max(f32::MIN_POSITIVE);
+        let slope = e1 - e0;
+        let x = if slope.abs() < 1e-6 {
+            area / e0.max(f32::MIN_POSITIVE)
+        } else {
+            (-e0 + (e0 * e0 + 2.0 * slope * area).max(0.0).sqrt()) / slope
+        };
+        t0 + x.clamp(0.0, 1.0) * width
+    }
+}
+
+// When rays are sent out. Each exposure runs from `open` to `close` following
+// the shutter curve. With a rolling shutter the sensor is read out one
+// scanline at a time from the top, so a row's exposure starts
+// `readout_time` times its distance from the top of the image later.
+#[derive(Clone)]
+pub struct Shutter {
+    pub open: f32,
+    pub close: f32,
+    curve: ShutterCurve,
+    readout_time: f32,
+}
+
+impl Shutter {
+    pub fn build(open: f32, close: f32) -> Self {
+        Shutter { open, close, curve: ShutterCurve::box_curve(), readout_time: 0.0 }
+    }
+
+    pub fn with_curve(mut self, curve: ShutterCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    // Time between the top and the bottom row starting their exposure.
+    pub fn with_rolling(mut self, readout_time: f32) -> Self {
+        self.readout_time = readout_time;
+        self
+    }
+
+    // `t` is the image position the ray is for, 1 at the top.
+    pub fn sample(&self, t: f32, rnd: &mut Random) -> f32 {
+        let row_delay = (1.0 - t) * self.readout_time;
+        self.open + row_delay + self.curve.sample(rnd) * (self.close - self.open)
+    }
+
+    // The bottom row closes `readout_time` after `close`.
+    pub fn time_range(&self) -> (f32, f32) {
+        (self.open, self.close + self.readout_time)
+    }
+}
+
+// Shape of the opening the defocus rays pass through, this is what gives
+// out of focus highlights (bokeh) their shape.
+#[derive(Clone)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon with its corners on the lens radius, like the blades
+    /// of a stopped down iris. The rotation is in degrees.
+    Polygon { blades: u32, rotation: f32 },
+    Mask(ApertureMask),
+}
+
+// Arbitrary aperture shape from a grayscale image, brighter pixels let more
+// light through. The image is stretched over the square around the lens disk.
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    // Running sum of the pixel values, top row first, for picking pixels in
+    // proportion to their value.
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    // Any format ImageTexture can read. The stored values are used as they
+    // are, colour images are averaged to grey and alpha is ignored.
+    pub fn load(path: &str) -> Result<ApertureMask, TextureError> {
+        let (image, _) = imageio::read_image(path).map_err(|err| TextureError::from_io(path, err))?;
+        let grey_channels = if image.channels >= 3 { 3 } else { 1 };
+        let values = image.data.chunks(image.channels.max(1)).map(|pixel| {
+            pixel.iter().take(grey_channels).sum::<f32>() / grey_channels as f32
+        }).collect();
+        ApertureMask::from_values(image.nx, image.ny, values)
+            .map_err(|err| TextureError::Unsupported { path: path.to_string(), reason: err.to_string() })
+    }
+
+    // `values` holds width * height transmissions, top row first.
+    pub fn from_values(width: usize, height: usize, values: Vec<f32>) -> io::Result<ApertureMask> {
+        if width == 0 || height == 0 || values.len() != width * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "aperture mask size doesn't match its data"));
+        }
+        let mut total = 0.0;
+        let cdf: Vec<f32> = values.iter().map(|v| { total += v.max(0.0); total }).collect();
+        if total <= 0.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "aperture mask is completely black"));
+        }
+        Ok(ApertureMask { width, height, cdf })
+    }
+
+    // Point in [-1,1]^2 distributed like the mask.
+    fn sample(&self, rnd: &mut Random) -> (f32, f32) {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = rnd.gen() * total;
+        let index = self.cdf.partition_point(|&c| c <= target).min(self.cdf.len() - 1);
+        let (i, j) = (index % self.width, index / self.width);
+        let x = (i as f32 + rnd.gen()) / self.width as f32;
+        let y = (j as f32 + rnd.gen()) / self.height as f32;
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
+
+impl Aperture {
+    // Point on the aperture, scaled so the lens radius is 1.
+    fn sample(&self, rnd: &mut Random) -> (f32, f32) {
+        match self {
+            Aperture::Circle => {
+                let p = random_in_unit_disk(rnd);
+                (*p.x(), *p.y())
+            },
+            Aperture::Polygon { blades, rotation } => {
+                // Equal triangle fans around the centre, pick one and sample it uniformly.
+                let blades = (*blades).max(3) as f32;
+                let side = (rnd.gen() * blades).floor().min(blades - 1.0);
+                let start = rotation * f32::consts::PI / 180.0 + side * 2.0 * f32::consts::PI / blades;
+                let end = start + 2.0 * f32::consts::PI / blades;
+                let (mut a, mut b) = (rnd.gen(), rnd.gen());
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                (a * start.cos() + b * end.cos(), a * start.sin() + b * end.sin())
+            },
+            Aperture::Mask(mask) => mask.sample(rnd),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PerspectiveCamera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
@@ -13,11 +235,13 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f32,
-    time_open: f32,
-    time_close: f32,
+    aperture: Aperture,
+    cat_eye: f32,
+    shutter: Shutter,
 }
 
-impl Camera {
+impl PerspectiveCamera {
+    #[allow(clippy::too_many_arguments)]
     pub fn build(
         look_from: &Vec3,
         look_at: &Vec3,
@@ -33,9 +257,7 @@ impl Camera {
         let theta = vertical_fov_degrees * f32::consts::PI / 180.0; // switch to radians
         let half_height = (theta / 2.0).tan();
         let half_width = aspect_ratio * half_height;
-        let w = (look_from - look_at).make_normalised();
-        let u = cross(camera_up, &w).make_normalised();
-        let v = cross(&w, &u);
+        let (u, v, w) = camera_frame(look_from, look_at, camera_up);
         let lower_left_corner = look_from
             - &(&u * (focus_distance * half_width))
             - &v * (focus_distance * half_height)
@@ -43,7 +265,7 @@ impl Camera {
         let horizontal = &u * (2.0 * half_width * focus_distance);
         let vertical = &v * (2.0 * half_height * focus_distance);
 
-        Camera {
+        PerspectiveCamera {
             origin: *look_from,
             lower_left_corner,
             horizontal,
@@ -51,23 +273,335 @@ impl Camera {
             u,
             v,
             lens_radius,
-            time_open,
-            time_close
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            shutter: Shutter::build(time_open, time_close)
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Ray {
-        let rd = &random_in_unit_disk(rnd) * self.lens_radius;
-        let offset = &self.u * *rd.x() + &self.v * *rd.y();
-        let time = self.time_open + rnd.gen() * (self.time_close - self.time_open);
-        Ray {
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Optical vignetting: the lens barrel cuts into the aperture away from
+    // the image centre, so bokeh turns into cat's eyes towards the corners and
+    // the corners get darker. 0 turns it off, at 1 the edge of the barrel passes
+    // through the middle of the aperture at the middle of the image edges.
+    pub fn with_cat_eye(mut self, amount: f32) -> Self {
+        self.cat_eye = amount;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        let (x, y) = self.aperture.sample(rnd);
+        if self.cat_eye > 0.0 {
+            // The barrel opening is a unit disk shifted towards the image position.
+            let (bx, by) = (x - self.cat_eye * (2.0 * s - 1.0), y - self.cat_eye * (2.0 * t - 1.0));
+            if bx * bx + by * by > 1.0 {
+                return None;
+            }
+        }
+        let offset = &self.u * (x * self.lens_radius) + &self.v * (y * self.lens_radius);
+        let time = self.shutter.sample(t, rnd);
+        CameraRay::unweighted(Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + &self.horizontal * s + &self.vertical * t
                 - self.origin
                 - offset,
             time
+        })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.shutter.time_range()
+    }
+}
+
+// Settings of a real camera body and lens. They set the field of view,
+// depth of field, the motion blur interval and the exposure together, the
+// same way they would on a photograph.
+#[derive(Copy, Clone)]
+pub struct PhysicalSettings {
+    pub focal_length_mm: f32,
+    pub sensor_width_mm: f32,
+    pub sensor_height_mm: f32,
+    pub f_number: f32,
+    /// Seconds, scene time is taken to be in seconds as well.
+    pub shutter_time: f32,
+    pub iso: f32,
+}
+
+impl Default for PhysicalSettings {
+    // 50mm on a full frame sensor, f/8, 1/60s at ISO 100.
+    fn default() -> Self {
+        PhysicalSettings {
+            focal_length_mm: 50.0,
+            sensor_width_mm: 36.0,
+            sensor_height_mm: 24.0,
+            f_number: 8.0,
+            shutter_time: 1.0 / 60.0,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalSettings {
+    // Exposure value at ISO 100, higher means less light gets recorded.
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    // Scale from scene luminance (emission values taken as cd/m^2) to image
+    // values, using the saturation based sensitivity so a luminance that
+    // would just clip the sensor comes out as 1.
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+}
+
+// Thin lens camera set up from `PhysicalSettings`. The sensor is cropped to
+// the image aspect ratio, and the field of view narrows slightly when focused
+// close as the lens moves away from the sensor. `units_per_mm` says how big a
+// millimetre is in the scene.
+#[derive(Clone)]
+pub struct PhysicalCamera {
+    lens: PerspectiveCamera,
+    exposure: f32,
+}
+
+impl PhysicalCamera {
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        look_from: &Vec3,
+        look_at: &Vec3,
+        camera_up: &Vec3,
+        settings: &PhysicalSettings,
+        aspect_ratio: f32,
+        focus_distance: f32,
+        units_per_mm: f32,
+        shutter_open: f32
+    ) -> Self {
+        let focal_length = settings.focal_length_mm;
+        let focus_mm = (focus_distance / units_per_mm).max(focal_length * 1.001);
+        let image_distance = focal_length * focus_mm / (focus_mm - focal_length);
+        let sensor_height = settings.sensor_height_mm.min(settings.sensor_width_mm / aspect_ratio);
+        let vertical_fov_degrees = 2.0 * (0.5 * sensor_height / image_distance).atan() * 180.0 / f32::consts::PI;
+        let aperture = focal_length / settings.f_number * units_per_mm;
+
+        PhysicalCamera {
+            lens: PerspectiveCamera::build(
+                look_from,
+                look_at,
+                camera_up,
+                vertical_fov_degrees,
+                aspect_ratio,
+                aperture,
+                focus_distance,
+                shutter_open,
+                shutter_open + settings.shutter_time
+            ),
+            exposure: settings.exposure(),
+        }
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.lens = self.lens.with_aperture(aperture);
+        self
+    }
+
+    pub fn with_cat_eye(mut self, amount: f32) -> Self {
+        self.lens = self.lens.with_cat_eye(amount);
+        self
+    }
+
+    // Keeps the exposure, the shutter time only sets the motion blur here.
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.lens = self.lens.with_shutter(shutter);
+        self
+    }
+}
+
+impl Camera for PhysicalCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        self.lens.get_ray(s, t, rnd).map(|r| CameraRay { weight: r.weight * self.exposure, ..r })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.lens.time_range()
+    }
+}
+
+// Parallel rays, `view_height` is the height of the view volume in world units.
+#[derive(Clone)]
+pub struct OrthographicCamera {
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+    shutter: Shutter,
+}
+
+impl OrthographicCamera {
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        look_from: &Vec3,
+        look_at: &Vec3,
+        camera_up: &Vec3,
+        view_height: f32,
+        aspect_ratio: f32,
+        time_open: f32,
+        time_close: f32
+    ) -> Self {
+        let (u, v, w) = camera_frame(look_from, look_at, camera_up);
+        let horizontal = &u * (view_height * aspect_ratio);
+        let vertical = &v * view_height;
+        let lower_left_corner = look_from - &(&horizontal * 0.5) - &vertical * 0.5;
+
+        OrthographicCamera {
+            lower_left_corner,
+            horizontal,
+            vertical,
+            direction: &w * -1.0,
+            shutter: Shutter::build(time_open, time_close)
         }
     }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        CameraRay::unweighted(Ray {
+            origin: self.lower_left_corner + &self.horizontal * s + &self.vertical * t,
+            direction: self.direction,
+            time: self.shutter.sample(t, rnd)
+        })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.shutter.time_range()
+    }
+}
+
+// Equidistant fisheye: the angle off the view axis grows linearly with the
+// distance from the image centre, reaching half of `fov_degrees` at the top
+// and bottom edges. Angles past 180 degrees are clamped to straight behind.
+#[derive(Clone)]
+pub struct FisheyeCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    half_fov: f32,
+    aspect_ratio: f32,
+    shutter: Shutter,
+}
+
+impl FisheyeCamera {
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        look_from: &Vec3,
+        look_at: &Vec3,
+        camera_up: &Vec3,
+        fov_degrees: f32,
+        aspect_ratio: f32,
+        time_open: f32,
+        time_close: f32
+    ) -> Self {
+        let (u, v, w) = camera_frame(look_from, look_at, camera_up);
+        FisheyeCamera {
+            origin: *look_from,
+            u,
+            v,
+            w,
+            half_fov: 0.5 * fov_degrees * f32::consts::PI / 180.0,
+            aspect_ratio,
+            shutter: Shutter::build(time_open, time_close)
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let r = (x * x + y * y).sqrt();
+        let theta = (r * self.half_fov).min(f32::consts::PI);
+        let phi = y.atan2(x);
+        let sin_theta = theta.sin();
+        let direction = &self.u * (sin_theta * phi.cos())
+            + &self.v * (sin_theta * phi.sin())
+            - &self.w * theta.cos();
+        CameraRay::unweighted(Ray {
+            origin: self.origin,
+            direction,
+            time: self.shutter.sample(t, rnd)
+        })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.shutter.time_range()
+    }
+}
+
+// Equirectangular 360 degree panorama. s covers longitude from -180 to 180
+// degrees with the look direction in the middle, t covers latitude from
+// straight down to straight up. Use a 2:1 image.
+#[derive(Clone)]
+pub struct PanoramaCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    shutter: Shutter,
+}
+
+impl PanoramaCamera {
+    pub fn build(look_from: &Vec3, look_at: &Vec3, camera_up: &Vec3, time_open: f32, time_close: f32) -> Self {
+        let (u, v, w) = camera_frame(look_from, look_at, camera_up);
+        PanoramaCamera { origin: *look_from, u, v, w, shutter: Shutter::build(time_open, time_close) }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for PanoramaCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        let longitude = (s - 0.5) * 2.0 * f32::consts::PI;
+        let latitude = (t - 0.5) * f32::consts::PI;
+        let cos_latitude = latitude.cos();
+        let direction = &self.u * (cos_latitude * longitude.sin())
+            + &self.v * latitude.sin()
+            - &self.w * (cos_latitude * longitude.cos());
+        CameraRay::unweighted(Ray {
+            origin: self.origin,
+            direction,
+            time: self.shutter.sample(t, rnd)
+        })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.shutter.time_range()
+    }
 }
 
 fn random_in_unit_disk(rnd: &mut Random) -> Vec3 {
@@ -78,3 +612,132 @@ fn random_in_unit_disk(rnd: &mut Random) -> Vec3 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction_at(camera: &dyn Camera, s: f32, t: f32) -> Vec3 {
+        let mut rnd = Random::create_with_seed(1);
+        camera.get_ray(s, t, &mut rnd).expect("ray should leave the camera").ray.direction.make_normalised()
+    }
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn projections_look_along_the_view_axis() {
+        let (from, at, up) = (Vec3::from(1.0, 2.0, 3.0), Vec3::from(1.0, 2.0, -7.0), Vec3::from(0.0, 1.0, 0.0));
+        let forward = Vec3::from(0.0, 0.0, -1.0);
+        let orthographic = OrthographicCamera::build(&from, &at, &up, 2.0, 2.0, 0.0, 1.0);
+        let fisheye = FisheyeCamera::build(&from, &at, &up, 180.0, 1.0, 0.0, 1.0);
+        let panorama = PanoramaCamera::build(&from, &at, &up, 0.0, 1.0);
+        for camera in [&orthographic as &dyn Camera, &fisheye, &panorama].iter() {
+            assert_close(&direction_at(*camera, 0.5, 0.5), &forward);
+        }
+        // Orthographic rays stay parallel, and start spread over the view.
+        assert_close(&direction_at(&orthographic, 0.0, 1.0), &forward);
+        let mut rnd = Random::create_with_seed(1);
+        let corner = orthographic.get_ray(0.0, 1.0, &mut rnd).unwrap().ray.origin;
+        assert_close(&corner, &Vec3::from(-1.0, 3.0, 3.0));
+    }
+
+    #[test]
+    fn wide_projections_reach_the_sides() {
+        let (from, at, up) = (Vec3::default(), Vec3::from(0.0, 0.0, -1.0), Vec3::from(0.0, 1.0, 0.0));
+        // Half of a 180 degree fisheye at the top edge, straight up.
+        let fisheye = FisheyeCamera::build(&from, &at, &up, 180.0, 1.0, 0.0, 1.0);
+        assert_close(&direction_at(&fisheye, 0.5, 1.0), &Vec3::from(0.0, 1.0, 0.0));
+        // The panorama wraps round, its left and right edges look straight back.
+        let panorama = PanoramaCamera::build(&from, &at, &up, 0.0, 1.0);
+        assert_close(&direction_at(&panorama, 0.0, 0.5), &Vec3::from(0.0, 0.0, 1.0));
+        assert_close(&direction_at(&panorama, 0.75, 0.5), &Vec3::from(1.0, 0.0, 0.0));
+        assert_close(&direction_at(&panorama, 0.5, 0.0), &Vec3::from(0.0, -1.0, 0.0));
+    }
+
+    // Fraction of `count` shutter samples that land before `time`.
+    fn fraction_before(curve: &ShutterCurve, time: f32, count: usize) -> f32 {
+        let mut rnd = Random::create_with_seed(5);
+        (0..count).filter(|_| curve.sample(&mut rnd) < time).count() as f32 / count as f32
+    }
+
+    #[test]
+    fn shutter_curves_sample_their_efficiency() {
+        let box_curve = ShutterCurve::box_curve();
+        assert!((fraction_before(&box_curve, 0.25, 20000) - 0.25).abs() < 0.01);
+        // Opening and closing over half the exposure each is a triangle, a
+        // quarter of the way in it has let through an eighth of the light.
+        let triangle = ShutterCurve::trapezoid(0.5, 0.5);
+        assert!((fraction_before(&triangle, 0.25, 20000) - 0.125).abs() < 0.01);
+        assert!((fraction_before(&triangle, 0.5, 20000) - 0.5).abs() < 0.01);
+        // Only open over the second half.
+        let late = ShutterCurve::from_points(vec![(1.0, 1.0), (0.0, 0.0), (0.5, 0.0), (0.5, 1.0)]);
+        assert_eq!(fraction_before(&late, 0.5, 1000), 0.0);
+        // Nothing let through at all is taken as a box.
+        let closed = ShutterCurve::from_points(vec![(0.0, 0.0), (1.0, 0.0)]);
+        assert!((fraction_before(&closed, 0.5, 20000) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn rolling_shutters_delay_lower_rows() {
+        let shutter = Shutter::build(1.0, 2.0).with_rolling(0.5);
+        assert_eq!(shutter.time_range(), (1.0, 2.5));
+        let mut rnd = Random::create_with_seed(7);
+        for _ in 0..100 {
+            let top = shutter.sample(1.0, &mut rnd);
+            let bottom = shutter.sample(0.0, &mut rnd);
+            assert!((1.0..=2.0).contains(&top));
+            assert!((1.5..=2.5).contains(&bottom));
+        }
+    }
+
+    #[test]
+    fn physical_settings_set_exposure_and_view() {
+        // f/1 for a second at ISO 100 is EV 0, every stop after that halves the exposure.
+        let settings = PhysicalSettings { f_number: 1.0, shutter_time: 1.0, ..PhysicalSettings::default() };
+        assert!(settings.ev100().abs() < 1e-5);
+        let darker = PhysicalSettings { shutter_time: 0.5, ..settings };
+        assert!((darker.ev100() - 1.0).abs() < 1e-5);
+        assert!((settings.exposure() - 2.0 * darker.exposure()).abs() < 1e-5);
+        let brighter = PhysicalSettings { iso: 200.0, ..darker };
+        assert!(brighter.ev100().abs() < 1e-5);
+
+        // A pinhole sized aperture, the full frame sensor is 24mm high behind a 50mm lens
+        // focused far away, so the top edge of the image is atan(12 / 50) off axis.
+        let pinhole = PhysicalSettings { f_number: 1e6, ..PhysicalSettings::default() };
+        let (from, at, up) = (Vec3::default(), Vec3::from(0.0, 0.0, -1.0), Vec3::from(0.0, 1.0, 0.0));
+        let camera = PhysicalCamera::build(&from, &at, &up, &pinhole, 1.5, 1e6, 1.0, 0.0);
+        let top = direction_at(&camera, 0.5, 1.0);
+        assert!((top.y().atan2(-top.z()) - (12.0f32 / 50.0).atan()).abs() < 1e-3);
+        // The sensor gets cropped to the image's aspect ratio.
+        let square = PhysicalCamera::build(&from, &at, &up, &pinhole, 1.0, 1e6, 1.0, 0.0);
+        let right = direction_at(&square, 1.0, 0.5);
+        assert!((right.x().atan2(-right.z()) - (12.0f32 / 50.0).atan()).abs() < 1e-3);
+        assert_eq!(camera.time_range(), (0.0, pinhole.shutter_time));
+    }
+
+    #[test]
+    fn apertures_stay_on_the_lens() {
+        let mut rnd = Random::create_with_seed(3);
+        let hexagon = Aperture::Polygon { blades: 6, rotation: 15.0 };
+        for _ in 0..1000 {
+            let (x, y) = hexagon.sample(&mut rnd);
+            assert!(x * x + y * y <= 1.0 + 1e-5);
+        }
+        // Only the right half of this mask lets light through.
+        let mask = ApertureMask::from_values(2, 2, vec![0.0, 1.0, 0.0, 1.0]).unwrap();
+        for _ in 0..1000 {
+            let (x, y) = mask.sample(&mut rnd);
+            assert!((0.0..=1.0).contains(&x) && (-1.0..=1.0).contains(&y));
+        }
+        assert!(ApertureMask::from_values(2, 2, vec![0.0; 4]).is_err());
+        assert!(ApertureMask::from_values(2, 2, vec![1.0; 3]).is_err());
+    }
+
+    #[test]
+    fn aperture_masks_load_like_textures() {
+        assert!(ApertureMask::load("textures/aperture_star.png").is_ok());
+        assert!(matches!(ApertureMask::load("textures/no_such_mask.png"), Err(TextureError::NotFound(_))));
+    }
+}
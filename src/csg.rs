@@ -0,0 +1,213 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+use std::f32;
+
+// Constructive solid geometry on two closed shapes. The children's normals
+// have to point out of them, as they do for spheres, boxes and the capped
+// quadrics; a crossing whose normal faces the ray is an entry, otherwise an
+// exit. Csg nodes report their own normals the same way, so they nest.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CsgOperation {
+    // Inside either.
+    Union,
+    // Inside both.
+    Intersection,
+    // Inside the left but not the right; the right's surfaces are turned
+    // inside out where they bound the result.
+    Difference
+}
+
+impl CsgOperation {
+    fn inside(&self, left : bool, right : bool) -> bool {
+        match self {
+            CsgOperation::Union => left || right,
+            CsgOperation::Intersection => left && right,
+            CsgOperation::Difference => left && !right
+        }
+    }
+}
+
+pub struct Csg {
+    operation : CsgOperation,
+    left : Box<dyn Hitable>,
+    right : Box<dyn Hitable>
+}
+
+impl Csg {
+    pub fn union(left : Box<dyn Hitable>, right : Box<dyn Hitable>) -> Box<Csg> {
+        Box::new(Csg { operation : CsgOperation::Union, left, right })
+    }
+
+    pub fn intersection(left : Box<dyn Hitable>, right : Box<dyn Hitable>) -> Box<Csg> {
+        Box::new(Csg { operation : CsgOperation::Intersection, left, right })
+    }
+
+    pub fn difference(left : Box<dyn Hitable>, right : Box<dyn Hitable>) -> Box<Csg> {
+        Box::new(Csg { operation : CsgOperation::Difference, left, right })
+    }
+}
+
+fn is_entry(ray : &Ray, rec : &HitRecord) -> bool {
+    dot(&ray.direction, &rec.normal) < 0.0
+}
+
+impl Csg {
+    // Crossings of the combined shape's surface between t_min and t_max,
+    // nearest first, stopping after `limit` of them. Both children are asked
+    // for all their crossings once and merged in order of t.
+    fn boundaries(&self, ray : &Ray, t_min : f32, t_max : f32, limit : usize) -> Vec<HitRecord<'_>> {
+        // Crossings past t_max are still wanted, a shape with none in range
+        // may yet have the ray inside it, and the first crossing tells.
+        let left = self.left.all_hits(ray, t_min, f32::MAX);
+        let right = self.right.all_hits(ray, t_min, f32::MAX);
+        let mut in_left = left.first().is_some_and(|rec| !is_entry(ray, rec));
+        let mut in_right = right.first().is_some_and(|rec| !is_entry(ray, rec));
+        let mut inside = self.operation.inside(in_left, in_right);
+
+        let mut boundaries = Vec::new();
+        let (mut l, mut r) = (0, 0);
+        while boundaries.len() < limit {
+            let from_left = match (left.get(l), right.get(r)) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break
+            };
+            let rec = if from_left { &left[l] } else { &right[r] };
+            if rec.t >= t_max {
+                break;
+            }
+            if from_left {
+                in_left = is_entry(ray, rec);
+                l += 1;
+            } else {
+                in_right = is_entry(ray, rec);
+                r += 1;
+            }
+
+            let now_inside = self.operation.inside(in_left, in_right);
+            if now_inside != inside {
+                let flip = !from_left && self.operation == CsgOperation::Difference;
+                let normal = if flip { &rec.normal * -1.0 } else { rec.normal };
+                boundaries.push(HitRecord { normal, ..*rec });
+            }
+            inside = now_inside;
+        }
+        boundaries
+    }
+}
+
+impl Hitable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.boundaries(ray, t_min, t_max, 1).pop()
+    }
+
+    fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb {
+        let left = self.left.bounding_box(time0, time1);
+        let right = self.right.bounding_box(time0, time1);
+        match self.operation {
+            CsgOperation::Union => Aabb::surrounding_box(&left, &right),
+            CsgOperation::Intersection => {
+                let min = left.min.max(&right.min);
+                let max = left.max.min(&right.max);
+                // Disjoint boxes leave nothing, keep the box well formed anyway.
+                Aabb::build(min, max.max(&min))
+            },
+            CsgOperation::Difference => left
+        }
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        self.boundaries(ray, t_min, t_max, usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::texture::ConstantTexture;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn ball(x : f32, radius : f32) -> Box<dyn Hitable> {
+        Box::new(Sphere { center : Vec3::from(x, 0.0, 0.0), radius, material : grey() })
+    }
+
+    // Two unit spheres overlapping between x = -0.5 and 0.5.
+    fn pair(operation : CsgOperation) -> Box<Csg> {
+        Box::new(Csg { operation, left : ball(-0.5, 1.0), right : ball(0.5, 1.0) })
+    }
+
+    fn along_x(x : f32) -> Ray {
+        Ray { origin : Vec3::from(x, 0.0, 0.0), direction : Vec3::from(1.0, 0.0, 0.0), time : 0.0 }
+    }
+
+    // Each crossing as its t and whether it enters, checking `hit` agrees
+    // with the first.
+    fn crossings(shape : &dyn Hitable, ray : &Ray) -> Vec<(f32, bool)> {
+        let all : Vec<(f32, bool)> = shape.all_hits(ray, 0.001, f32::MAX).iter().map(|rec| (rec.t, is_entry(ray, rec))).collect();
+        assert_eq!(shape.hit(ray, 0.001, f32::MAX).map(|rec| (rec.t, is_entry(ray, &rec))), all.first().copied());
+        all
+    }
+
+    fn assert_crossings(actual : &[(f32, bool)], expected : &[(f32, bool)]) {
+        assert!(actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, b)| (a.0 - b.0).abs() < 1e-4 && a.1 == b.1),
+            "crossings {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn operations_keep_their_own_boundaries() {
+        let from_outside = along_x(-5.0);
+        assert_crossings(&crossings(&*pair(CsgOperation::Union), &from_outside), &[(3.5, true), (6.5, false)]);
+        assert_crossings(&crossings(&*pair(CsgOperation::Intersection), &from_outside), &[(4.5, true), (5.5, false)]);
+        // Where the right sphere bounds a difference its normal is turned round.
+        assert_crossings(&crossings(&*pair(CsgOperation::Difference), &from_outside), &[(3.5, true), (4.5, false)]);
+        let reversed = Csg::difference(ball(0.5, 1.0), ball(-0.5, 1.0));
+        assert_crossings(&crossings(&*reversed, &from_outside), &[(5.5, true), (6.5, false)]);
+    }
+
+    #[test]
+    fn rays_starting_inside_see_only_the_way_out() {
+        let from_middle = along_x(0.0);
+        assert_crossings(&crossings(&*pair(CsgOperation::Union), &from_middle), &[(1.5, false)]);
+        assert_crossings(&crossings(&*pair(CsgOperation::Intersection), &from_middle), &[(0.5, false)]);
+        // In both spheres the difference is empty until the left one is left behind.
+        assert!(crossings(&*pair(CsgOperation::Difference), &from_middle).is_empty());
+        let reversed = Csg::difference(ball(0.5, 1.0), ball(-0.5, 1.0));
+        assert_crossings(&crossings(&*reversed, &from_middle), &[(0.5, true), (1.5, false)]);
+    }
+
+    #[test]
+    fn hits_stop_at_t_max() {
+        let both = pair(CsgOperation::Intersection);
+        let ray = along_x(-5.0);
+        assert!((both.hit(&ray, 0.001, 5.0).unwrap().t - 4.5).abs() < 1e-4);
+        assert!(both.hit(&ray, 0.001, 4.0).is_none());
+        assert_eq!(both.all_hits(&ray, 0.001, 5.0).len(), 1);
+    }
+
+    #[test]
+    fn nodes_nest_and_take_any_closed_shape() {
+        let hollowed = Csg::difference(pair(CsgOperation::Union), ball(0.0, 0.25));
+        assert_crossings(&crossings(&*hollowed, &along_x(-5.0)), &[(3.5, true), (4.75, false), (5.25, true), (6.5, false)]);
+        let cube = BoxShape::new_from(&Vec3::from(-1.0, -1.0, -1.0), &Vec3::from(1.0, 1.0, 1.0), grey());
+        let drilled = Csg::difference(cube, ball(0.0, 0.5));
+        assert_crossings(&crossings(&*drilled, &along_x(-5.0)), &[(4.0, true), (4.5, false), (5.5, true), (6.0, false)]);
+    }
+
+    #[test]
+    fn bounds_follow_the_operation() {
+        let overlap = pair(CsgOperation::Intersection).bounding_box(0.0, 1.0);
+        assert!((overlap.min - Vec3::from(-0.5, -1.0, -1.0)).length() < 1e-4 && (overlap.max - Vec3::from(0.5, 1.0, 1.0)).length() < 1e-4);
+        let both = pair(CsgOperation::Union).bounding_box(0.0, 1.0);
+        assert!((both.min - Vec3::from(-1.5, -1.0, -1.0)).length() < 1e-4 && (both.max - Vec3::from(1.5, 1.0, 1.0)).length() < 1e-4);
+        let left = pair(CsgOperation::Difference).bounding_box(0.0, 1.0);
+        assert!((left.max - Vec3::from(0.5, 1.0, 1.0)).length() < 1e-4);
+    }
+}
@@ -0,0 +1,449 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::rng::Random;
+use crate::vec3::*;
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+// Cubic Bézier with a width varying linearly from one end to the other.
+#[derive(Copy, Clone, Debug)]
+pub struct Bezier {
+    pub points : [Vec3; 4],
+    pub widths : (f32, f32)
+}
+
+// How a curve presents itself to rays. Both are intersected as a flat
+// ribbon facing the ray; `Cylinder` bends the normal across the width so it
+// shades like a tube, which suits thicker strands under ordinary materials.
+// `Hair` works out its own frame and is happy with either.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CurveShape {
+    Ribbon,
+    Cylinder
+}
+
+fn lerp(t : f32, a : &Vec3, b : &Vec3) -> Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+impl Bezier {
+    pub fn build(points : [Vec3; 4], widths : (f32, f32)) -> Bezier {
+        Bezier { points, widths }
+    }
+
+    fn width(&self, u : f32) -> f32 {
+        self.widths.0 * (1.0 - u) + self.widths.1 * u
+    }
+
+    fn max_width(&self) -> f32 {
+        self.widths.0.max(self.widths.1)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let bounds = padded_bounds(&self.points);
+        let pad = Vec3::from(0.5, 0.5, 0.5);
+        let pad = &pad * self.max_width();
+        Aabb::build(bounds.min - pad, bounds.max + pad)
+    }
+
+    // Point and derivative at u by de Casteljau.
+    fn evaluate(points : &[Vec3; 4], u : f32) -> (Vec3, Vec3) {
+        let a = [lerp(u, &points[0], &points[1]), lerp(u, &points[1], &points[2]), lerp(u, &points[2], &points[3])];
+        let b = [lerp(u, &a[0], &a[1]), lerp(u, &a[1], &a[2])];
+        let derivative = &(b[1] - b[0]) * 3.0;
+        // Coincident control points leave the ends without a derivative.
+        let derivative = if derivative.square_length() > 0.0 { derivative } else { points[3] - points[0] };
+        (lerp(u, &b[0], &b[1]), derivative)
+    }
+
+    // The two halves either side of u = 0.5, sharing the middle point.
+    fn split(points : &[Vec3; 4]) -> [Vec3; 7] {
+        let [p0, p1, p2, p3] = *points;
+        [
+            p0,
+            &(p0 + p1) * 0.5,
+            &(p0 + &p1 * 2.0 + p2) * 0.25,
+            &(p0 + &p1 * 3.0 + &p2 * 3.0 + p3) * 0.125,
+            &(p1 + &p2 * 2.0 + p3) * 0.25,
+            &(p2 + p3) * 0.5,
+            p3
+        ]
+    }
+
+    // Nearest crossing as (t, u). The control points are moved into a frame
+    // with the ray down +z from the origin, then the curve is split in half
+    // until each piece is nearly straight, throwing away pieces whose bounds
+    // miss the ray. What's left is tested as a line segment of the curve's
+    // width, the way pbrt does it.
+    pub fn intersect(&self, ray : &Ray, t_min : f32, t_max : f32) -> Option<(f32, f32)> {
+        let length = ray.direction.length();
+        if length == 0.0 {
+            return None;
+        }
+        let direction = &ray.direction / length;
+        let (x_axis, y_axis) = orthonormal_basis(&direction);
+        let local = self.points.map(|p| {
+            let offset = p - ray.origin;
+            Vec3::from(dot(&offset, &x_axis), dot(&offset, &y_axis), dot(&offset, &direction))
+        });
+
+        // Enough splits to bring the flattest piece within 5% of the width.
+        let mut flatness : f32 = 0.0;
+        for i in 0..2 {
+            let second = local[i] - &local[i + 1] * 2.0 + local[i + 2];
+            flatness = flatness.max(second.x().abs()).max(second.y().abs()).max(second.z().abs());
+        }
+        let epsilon = self.max_width() * 0.05;
+        let depth = ((2.0f32.sqrt() * 6.0 * flatness / (8.0 * epsilon)).log2() * 0.5).round();
+        let depth = if depth.is_nan() { 0 } else { depth.clamp(0.0, 10.0) as usize };
+
+        let mut nearest = None;
+        self.recurse(&local, (0.0, 1.0), depth, (t_min * length, t_max * length), &mut nearest);
+        nearest.map(|(z, u)| (z / length, u))
+    }
+
+    fn recurse(&self, points : &[Vec3; 4], range : (f32, f32), depth : usize, z_range : (f32, f32), nearest : &mut Option<(f32, f32)>) {
+        let z_max = nearest.map_or(z_range.1, |(z, _)| z);
+        let half_width = 0.5 * self.width(range.0).max(self.width(range.1));
+        let (mut min, mut max) = (points[0], points[0]);
+        for point in &points[1..] {
+            min = min.min(point);
+            max = max.max(point);
+        }
+        if *max.x() + half_width < 0.0 || *min.x() - half_width > 0.0
+            || *max.y() + half_width < 0.0 || *min.y() - half_width > 0.0
+            || *max.z() + half_width < z_range.0 || *min.z() - half_width > z_max {
+            return;
+        }
+
+        if depth > 0 {
+            let halves = Bezier::split(points);
+            let middle = 0.5 * (range.0 + range.1);
+            self.recurse(&[halves[0], halves[1], halves[2], halves[3]], (range.0, middle), depth - 1, z_range, nearest);
+            self.recurse(&[halves[3], halves[4], halves[5], halves[6]], (middle, range.1), depth - 1, z_range, nearest);
+            return;
+        }
+
+        // The ray has to pass between the lines square to the curve at
+        // either end, or neighbouring pieces would both claim it.
+        let start = (*points[1].y() - *points[0].y()) * -*points[0].y() + *points[0].x() * (*points[0].x() - *points[1].x());
+        let end = (*points[2].y() - *points[3].y()) * -*points[3].y() + *points[3].x() * (*points[3].x() - *points[2].x());
+        if start < 0.0 || end < 0.0 {
+            return;
+        }
+
+        // Closest point to the ray along the piece's chord.
+        let (chord_x, chord_y) = (*points[3].x() - *points[0].x(), *points[3].y() - *points[0].y());
+        let chord_length = chord_x * chord_x + chord_y * chord_y;
+        if chord_length == 0.0 {
+            return;
+        }
+        let w = ((-*points[0].x() * chord_x - *points[0].y() * chord_y) / chord_length).clamp(0.0, 1.0);
+        let u = range.0 + (range.1 - range.0) * w;
+        let width = self.width(u);
+        let (point, _) = Bezier::evaluate(points, w);
+        if point.x() * point.x() + point.y() * point.y() > 0.25 * width * width {
+            return;
+        }
+        let z = *point.z();
+        if z <= z_range.0 || z >= z_max {
+            return;
+        }
+        // The ribbon turns to face every ray, so one leaving the curve would
+        // cross it again straight away. Rays starting inside the strand go
+        // out through it instead; `Hair` moves its rays clear of the curve.
+        if point.square_length() <= 0.25 * width * width {
+            return;
+        }
+        *nearest = Some((z, u));
+    }
+
+    // Surface details at a crossing found by `intersect`. v runs across the
+    // curve as seen along the ray, 0.5 on its centre line.
+    fn record<'a>(&self, shape : CurveShape, ray : &Ray, t : f32, u : f32, material : &'a dyn Material) -> HitRecord<'a> {
+        let p = ray.point_at_parameter(t);
+        let (centre, dpdu) = Bezier::evaluate(&self.points, u);
+        let tangent = &dpdu / dpdu.length();
+        let (facing, side) = ribbon_frame(&tangent, &ray.direction);
+        let width = self.width(u);
+        let v = if width > 0.0 { (0.5 + dot(&(p - centre), &side) / width).clamp(0.0, 1.0) } else { 0.5 };
+        let normal = match shape {
+            CurveShape::Ribbon => facing,
+            CurveShape::Cylinder => {
+                let h = 2.0 * v - 1.0;
+                &facing * (1.0 - h * h).max(0.0).sqrt() + &side * h
+            }
+        };
+        HitRecord::build(ray, t, normal, material)
+            .with_uv(u, v)
+            .with_derivatives(dpdu, &side * width)
+    }
+}
+
+// Normal of a ribbon along `tangent` turned to face back along `direction`,
+// and the direction across it in which v increases.
+pub(crate) fn ribbon_frame(tangent : &Vec3, direction : &Vec3) -> (Vec3, Vec3) {
+    let towards = &(Vec3::default() - *direction) / direction.length();
+    let facing = towards - tangent * dot(&towards, tangent);
+    let length = facing.length();
+    // Looking straight down the curve any perpendicular will do.
+    let facing = if length > 1.0e-6 { &facing / length } else { orthonormal_basis(tangent).0 };
+    let side = cross(tangent, &facing);
+    (facing, side)
+}
+
+// A single curve segment.
+pub struct Curve {
+    pub bezier : Bezier,
+    pub shape : CurveShape,
+    pub material : Box<dyn Material>
+}
+
+impl Curve {
+    pub fn build(points : [Vec3; 4], widths : (f32, f32), material : Box<dyn Material>) -> Box<Curve> {
+        Box::new(Curve { bezier : Bezier::build(points, widths), shape : CurveShape::Ribbon, material })
+    }
+
+    pub fn with_shape(mut self : Box<Self>, shape : CurveShape) -> Box<Self> {
+        self.shape = shape;
+        self
+    }
+}
+
+impl Hitable for Curve {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u) = self.bezier.intersect(ray, t_min, t_max)?;
+        Some(self.bezier.record(self.shape, ray, t, u, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.bezier.bounds()
+    }
+}
+
+// Many curves sharing one material, such as the strands of a groom. Like
+// `Mesh`, each segment becomes a small hitable pointing back at the whole
+// so they can go into a `BvhTree`.
+pub struct Groom {
+    pub curves : Vec<Bezier>,
+    pub shape : CurveShape,
+    pub material : Box<dyn Material>
+}
+
+impl Groom {
+    // Strands given as points with a width at each. They're joined up with
+    // Catmull-Rom splines, so the curves pass through every point.
+    pub fn build(strands : &[Vec<(Vec3, f32)>], material : Box<dyn Material>) -> Groom {
+        let mut curves = Vec::new();
+        for strand in strands {
+            for i in 0..strand.len().saturating_sub(1) {
+                let (p1, w1) = strand[i];
+                let (p2, w2) = strand[i + 1];
+                let p0 = if i > 0 { strand[i - 1].0 } else { p1 };
+                let p3 = if i + 2 < strand.len() { strand[i + 2].0 } else { p2 };
+                let points = [p1, p1 + &(p2 - p0) / 6.0, p2 - &(p3 - p1) / 6.0, p2];
+                curves.push(Bezier::build(points, (w1, w2)));
+            }
+        }
+        Groom { curves, shape : CurveShape::Ribbon, material }
+    }
+
+    pub fn with_shape(mut self, shape : CurveShape) -> Groom {
+        self.shape = shape;
+        self
+    }
+
+    // Plain text strands: one point per line as `x y z [width]`, with a blank
+    // line between strands. Points without a width use the last `width <w>`
+    // line, or 0.01 before there is one. `#` starts a comment.
+    pub fn load(path : &str, material : Box<dyn Material>) -> io::Result<Groom> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |line : usize, msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, line, msg));
+        let mut strands = Vec::new();
+        let mut strand : Vec<(Vec3, f32)> = Vec::new();
+        let mut width = 0.01;
+        let mut finish = |strand : &mut Vec<(Vec3, f32)>, line : usize| -> io::Result<()> {
+            match strand.len() {
+                0 => Ok(()),
+                1 => Err(invalid(line, "strands need at least two points")),
+                _ => { strands.push(std::mem::take(strand)); Ok(()) }
+            }
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words : Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                finish(&mut strand, number)?;
+                continue;
+            }
+            if words[0] == "width" {
+                width = words.get(1).and_then(|w| w.parse::<f32>().ok()).filter(|w| *w >= 0.0 && words.len() == 2)
+                    .ok_or_else(|| invalid(number + 1, "expected width <w>"))?;
+                continue;
+            }
+            let values : Result<Vec<f32>, _> = words.iter().map(|w| w.parse::<f32>()).collect();
+            match values {
+                Ok(v) if v.len() == 3 => strand.push((Vec3::from(v[0], v[1], v[2]), width)),
+                Ok(v) if v.len() == 4 && v[3] >= 0.0 => strand.push((Vec3::from(v[0], v[1], v[2]), v[3])),
+                _ => return Err(invalid(number + 1, "expected x y z [width]")),
+            }
+        }
+        finish(&mut strand, text.lines().count())?;
+
+        if strands.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no strands", path)));
+        }
+        Ok(Groom::build(&strands, material))
+    }
+
+    // One hitable per curve segment, to add to the scene's list.
+    pub fn into_hitables(self) -> Vec<Box<dyn Hitable>> {
+        let count = self.curves.len();
+        let groom = Arc::new(self);
+        (0..count).map(|index| Box::new(GroomCurve { groom : groom.clone(), index }) as Box<dyn Hitable>).collect()
+    }
+
+    // The curves under a BVH of their own, as a single hitable.
+    pub fn into_bvh(self, rnd : &mut Random) -> Box<dyn Hitable> {
+        let mut hitables = self.into_hitables();
+        if hitables.is_empty() {
+            return Box::new(EmptyGroom);
+        }
+        BvhTree::build(&mut hitables, rnd, 0.0, 1.0).root
+    }
+}
+
+struct GroomCurve {
+    groom : Arc<Groom>,
+    index : usize
+}
+
+impl Hitable for GroomCurve {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let bezier = &self.groom.curves[self.index];
+        let (t, u) = bezier.intersect(ray, t_min, t_max)?;
+        Some(bezier.record(self.groom.shape, ray, t, u, &*self.groom.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.groom.curves[self.index].bounds()
+    }
+}
+
+struct EmptyGroom;
+
+impl Hitable for EmptyGroom {
+    fn hit(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        Aabb::build(Vec3::default(), Vec3::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    // Straight along x from -1 to 1, control points evenly spaced so u is
+    // proportional to distance.
+    fn straight(widths : (f32, f32)) -> Box<Curve> {
+        Curve::build([Vec3::from(-1.0, 0.0, 0.0), Vec3::from(-1.0 / 3.0, 0.0, 0.0), Vec3::from(1.0 / 3.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0)], widths, grey())
+    }
+
+    fn towards(x : f32, y : f32) -> Ray {
+        Ray { origin : Vec3::from(x, y, 5.0), direction : Vec3::from(0.0, 0.0, -1.0), time : 0.0 }
+    }
+
+    fn load_text(name : &str, text : &str) -> io::Result<Groom> {
+        let path = std::env::temp_dir().join(format!("raytracer-{}-{}.txt", std::process::id(), name));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, text).unwrap();
+        let groom = Groom::load(&path, grey());
+        fs::remove_file(&path).unwrap();
+        groom
+    }
+
+    #[test]
+    fn ribbons_face_the_ray() {
+        let curve = straight((0.2, 0.2));
+        let rec = curve.hit(&towards(0.5, 0.0), 0.001, f32::MAX).expect("ray should hit the centre line");
+        assert!((rec.t - 5.0).abs() < 1e-3);
+        assert!((rec.u - 0.75).abs() < 1e-3 && (rec.v - 0.5).abs() < 1e-3);
+        assert!((rec.normal - Vec3::from(0.0, 0.0, 1.0)).length() < 1e-3);
+        // A quarter of the width off the centre line is a quarter of the way across.
+        let rec = curve.hit(&towards(0.5, 0.05), 0.001, f32::MAX).unwrap();
+        assert!(((rec.v - 0.5).abs() - 0.25).abs() < 1e-3);
+        assert!(curve.hit(&towards(0.5, 0.15), 0.001, f32::MAX).is_none());
+        assert!(curve.hit(&towards(1.2, 0.0), 0.001, f32::MAX).is_none());
+        assert!(curve.hit(&towards(0.5, 0.0), 0.001, 4.0).is_none());
+    }
+
+    #[test]
+    fn cylinders_bend_the_normal_across_the_width() {
+        let tube = straight((0.2, 0.2)).with_shape(CurveShape::Cylinder);
+        let rec = tube.hit(&towards(0.5, 0.05), 0.001, f32::MAX).unwrap();
+        assert!((rec.normal.length() - 1.0).abs() < 1e-4);
+        assert!((rec.normal.z() - 0.75f32.sqrt()).abs() < 1e-3);
+        assert!((rec.normal.y().abs() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn widths_taper_along_the_curve() {
+        // 0.05 wide three quarters of the way along.
+        let tapered = straight((0.2, 0.0));
+        assert!(tapered.hit(&towards(0.5, 0.02), 0.001, f32::MAX).is_some());
+        assert!(tapered.hit(&towards(0.5, 0.04), 0.001, f32::MAX).is_none());
+        assert!(tapered.hit(&towards(-0.5, 0.04), 0.001, f32::MAX).is_some());
+    }
+
+    #[test]
+    fn grooms_pass_through_every_point() {
+        let strand = vec![(Vec3::from(0.0, 0.0, 0.0), 0.02), (Vec3::from(0.0, 1.0, 0.0), 0.01), (Vec3::from(0.5, 1.5, 0.0), 0.005)];
+        let groom = Groom::build(std::slice::from_ref(&strand), grey());
+        assert_eq!(groom.curves.len(), 2);
+        for (curve, ends) in groom.curves.iter().zip(strand.windows(2)) {
+            assert!((curve.points[0] - ends[0].0).length() == 0.0 && (curve.points[3] - ends[1].0).length() == 0.0);
+            assert_eq!(curve.widths, (ends[0].1, ends[1].1));
+        }
+    }
+
+    #[test]
+    fn grooms_load_from_text() {
+        let mut rnd = Random::create_with_seed(1);
+        let tuft = Groom::load("models/hair_tuft.txt", grey()).expect("the tuft fixture should load");
+        // 48 strands of four points each.
+        assert_eq!(tuft.curves.len(), 48 * 3);
+        assert!((tuft.curves[0].points[0] - Vec3::from(-0.052, 0.0, 0.104)).length() < 1e-6);
+        assert_eq!(tuft.curves[0].widths, (0.02, 0.015));
+        assert_eq!(tuft.with_shape(CurveShape::Cylinder).shape, CurveShape::Cylinder);
+
+        // Widths fall back on the last width line, then the default.
+        let groom = load_text("widths", "0 0 0\n0 1 0\n\nwidth 0.5\n1 0 0\n1 1 0 0.25\n").unwrap();
+        assert_eq!(groom.curves.iter().map(|c| c.widths).collect::<Vec<_>>(), vec![(0.01, 0.01), (0.5, 0.25)]);
+        let strands = groom.into_bvh(&mut rnd);
+        let rec = strands.hit(&Ray { origin : Vec3::from(1.0, 0.5, 5.0), direction : Vec3::from(0.0, 0.0, -1.0), time : 0.0 }, 0.001, f32::MAX);
+        assert!((rec.expect("ray should hit the second strand").t - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bad_grooms_are_invalid_data() {
+        for (name, text) in [("single", "0 0 0\n\n0 0 0\n0 1 0\n"), ("width", "width -1\n0 0 0\n0 1 0\n"), ("short", "0 0\n0 1\n"), ("empty", "# nothing\n")].iter() {
+            let err = load_text(name, text).err().expect(name);
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", name);
+        }
+        assert_eq!(Groom::load("models/no_such_groom.txt", grey()).err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::film::{luminance, FilmBuffers};
+use crate::vec3::*;
+
+// Edge-aware à-trous wavelet denoiser, along the lines of SVGF without the
+// temporal part. Lighting is demodulated by the albedo, filtered with a
+// widening 5x5 B3-spline kernel whose weights are stopped at normal, depth,
+// albedo and (variance normalised) luminance edges, then remodulated.
+#[derive(Copy, Clone)]
+pub struct Denoiser {
+    pub iterations : usize,
+    pub sigma_luminance : f32,
+    pub sigma_normal : f32,
+    /// Depth tolerance relative to the pixel's own depth, per unit of filter step.
+    pub sigma_depth : f32,
+    pub sigma_albedo : f32,
+}
+
+const KERNEL : [f32; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const EPSILON : f32 = 1e-4;
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations : 5,
+            sigma_luminance : 4.0,
+            sigma_normal : 128.0,
+            sigma_depth : 0.02,
+            sigma_albedo : 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn denoise(&self, buffers : &FilmBuffers) -> Vec<Vec3> {
+        let albedo : Vec<Vec3> = buffers.albedo.iter().map(|a| a.max(&Vec3::from(EPSILON, EPSILON, EPSILON))).collect();
+        let mut irradiance : Vec<Vec3> = buffers.colour.iter().zip(albedo.iter())
+            .map(|(c, a)| c.direct_product(&a.invert_elems()))
+            .collect();
+        // Luminance variance scales with the squared demodulation.
+        let mut variance : Vec<f32> = buffers.variance.iter().zip(albedo.iter())
+            .map(|(v, a)| { let l = luminance(a); v / (l * l) })
+            .collect();
+        variance = self.blur_variance(buffers, &variance);
+
+        for iteration in 0..self.iterations {
+            let (filtered, filtered_variance) = self.a_trous_step(buffers, &irradiance, &variance, 1 << iteration);
+            irradiance = filtered;
+            variance = filtered_variance;
+        }
+
+        irradiance.iter().zip(albedo.iter()).map(|(e, a)| e.direct_product(a)).collect()
+    }
+
+    fn a_trous_step(&self, buffers : &FilmBuffers, irradiance : &[Vec3], variance : &[f32], step : usize) -> (Vec<Vec3>, Vec<f32>) {
+        let (nx, ny) = (buffers.nx as i64, buffers.ny as i64);
+        let mut out = Vec::with_capacity(irradiance.len());
+        let mut out_variance = Vec::with_capacity(variance.len());
+
+        for y in 0..ny {
+            for x in 0..nx {
+                let p = (y * nx + x) as usize;
+                let luminance_p = luminance(&irradiance[p]);
+                let luminance_scale = self.sigma_luminance * variance[p].sqrt() + EPSILON;
+                let depth_scale = self.sigma_depth * step as f32 * buffers.depth[p].abs().max(EPSILON);
+
+                let mut sum = Vec3::from(0.0, 0.0, 0.0);
+                let mut sum_variance = 0.0;
+                let mut total = 0.0;
+                for dy in -2i64..=2 {
+                    let qy = y + dy * step as i64;
+                    if qy < 0 || qy >= ny {
+                        continue;
+                    }
+                    for dx in -2i64..=2 {
+                        let qx = x + dx * step as i64;
+                        if qx < 0 || qx >= nx {
+                            continue;
+                        }
+                        let q = (qy * nx + qx) as usize;
+                        let kernel = KERNEL[dx.unsigned_abs() as usize] * KERNEL[dy.unsigned_abs() as usize];
+
+                        let w_normal = dot(&buffers.normal[p], &buffers.normal[q]).max(0.0).powf(self.sigma_normal);
+                        let w_depth = (-(buffers.depth[p] - buffers.depth[q]).abs() / depth_scale).exp();
+                        let albedo_difference = (buffers.albedo[p] - buffers.albedo[q]).square_length();
+                        let w_albedo = (-albedo_difference / (self.sigma_albedo * self.sigma_albedo)).exp();
+                        let w_luminance = (-(luminance_p - luminance(&irradiance[q])).abs() / luminance_scale).exp();
+                        // Pixels that saw nothing have no normal, let them blend with each other.
+                        let w_normal = if buffers.normal[p].square_length() == 0.0 && buffers.normal[q].square_length() == 0.0 { 1.0 } else { w_normal };
+
+                        let weight = kernel * w_normal * w_depth * w_albedo * w_luminance;
+                        sum += &(&irradiance[q] * weight);
+                        sum_variance += weight * weight * variance[q];
+                        total += weight;
+                    }
+                }
+
+                if total > 0.0 {
+                    out.push(&sum / total);
+                    out_variance.push(sum_variance / (total * total));
+                } else {
+                    out.push(irradiance[p]);
+                    out_variance.push(variance[p]);
+                }
+            }
+        }
+
+        (out, out_variance)
+    }
+
+    // A single pass variance estimate is very noisy, a small 3x3 blur stops
+    // the luminance edge-stopping function from locking onto noise.
+    fn blur_variance(&self, buffers : &FilmBuffers, variance : &[f32]) -> Vec<f32> {
+        let (nx, ny) = (buffers.nx as i64, buffers.ny as i64);
+        let weights = [0.25, 0.5, 0.25];
+        let mut out = Vec::with_capacity(variance.len());
+        for y in 0..ny {
+            for x in 0..nx {
+                let mut sum = 0.0;
+                let mut total = 0.0;
+                for dy in -1i64..=1 {
+                    for dx in -1i64..=1 {
+                        let (qx, qy) = (x + dx, y + dy);
+                        if qx >= 0 && qx < nx && qy >= 0 && qy < ny {
+                            let w = weights[(dx + 1) as usize] * weights[(dy + 1) as usize];
+                            sum += w * variance[(qy * nx + qx) as usize];
+                            total += w;
+                        }
+                    }
+                }
+                out.push(sum / total);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Random;
+
+    fn buffers(colour : Vec<Vec3>, variance : f32) -> FilmBuffers {
+        let size = colour.len();
+        FilmBuffers {
+            nx : 16,
+            ny : size / 16,
+            colour,
+            albedo : vec![Vec3::from(0.5, 0.6, 0.7); size],
+            normal : vec![Vec3::from(0.0, 0.0, 1.0); size],
+            depth : vec![3.0; size],
+            variance : vec![variance; size],
+        }
+    }
+
+    #[test]
+    fn flat_image_is_unchanged() {
+        let colour = Vec3::from(0.2, 0.3, 0.4);
+        let flat = buffers(vec![colour; 16 * 12], 0.0);
+        for pixel in Denoiser::default().denoise(&flat) {
+            assert!((pixel - colour).length() < 1e-4, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn noise_is_smoothed_out() {
+        let mut rnd = Random::create_with_seed(7);
+        let colour = Vec3::from(0.2, 0.3, 0.4);
+        let noisy : Vec<Vec3> = (0..16 * 12).map(|_| &colour * (0.5 + rnd.gen())).collect();
+        let error = |image : &[Vec3]| image.iter().map(|pixel| (*pixel - colour).square_length()).sum::<f32>();
+        let denoised = Denoiser::default().denoise(&buffers(noisy.clone(), 0.01));
+        assert!(error(&denoised) < 0.25 * error(&noisy));
+    }
+}
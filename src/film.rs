@@ -0,0 +1,389 @@
+use crate::aov::MAX_LIGHT_AOVS;
+use crate::filter::Filter;
+use crate::imageio;
+use crate::vec3::Vec3;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+// What a single camera ray brings back: the radiance plus features of the
+// first surface hit, which feed the denoiser and the AOV passes.
+//
+// colour = emission + direct + indirect, and the light passes split the same
+// radiance up by the object id of the light it came from.
+#[derive(Copy, Clone)]
+pub struct Sample {
+    pub colour : Vec3,
+    pub albedo : Vec3,
+    pub normal : Vec3,
+    pub depth : f32,
+    pub uv : Vec3,
+    pub object_id : u32,
+    pub material_id : u32,
+    /// Light seen directly by the camera.
+    pub emission : Vec3,
+    /// Light reaching the camera after a single bounce. Only found by the path
+    /// hitting an emitter, it isn't sampled towards the lights.
+    pub direct : Vec3,
+    /// Light reaching the camera after two or more bounces.
+    pub indirect : Vec3,
+    pub lights : [Vec3; MAX_LIGHT_AOVS],
+}
+
+impl Default for Sample {
+    fn default() -> Self {
+        Sample {
+            colour : Vec3::from(0.0, 0.0, 0.0),
+            albedo : Vec3::from(0.0, 0.0, 0.0),
+            normal : Vec3::from(0.0, 0.0, 0.0),
+            depth : 0.0,
+            uv : Vec3::from(0.0, 0.0, 0.0),
+            object_id : 0,
+            material_id : 0,
+            emission : Vec3::from(0.0, 0.0, 0.0),
+            direct : Vec3::from(0.0, 0.0, 0.0),
+            indirect : Vec3::from(0.0, 0.0, 0.0),
+            lights : [Vec3::from(0.0, 0.0, 0.0); MAX_LIGHT_AOVS],
+        }
+    }
+}
+
+impl Sample {
+    // Scales the light carried by the sample, features are left as they are.
+    pub fn weighted(&self, weight : f32) -> Sample {
+        let mut sample = Sample { albedo : self.albedo, normal : self.normal, depth : self.depth, uv : self.uv,
+            object_id : self.object_id, material_id : self.material_id, ..Sample::default() };
+        sample.add_lighting(self, weight);
+        sample
+    }
+
+    fn add_lighting(&mut self, other : &Sample, weight : f32) {
+        self.colour += &(&other.colour * weight);
+        self.emission += &(&other.emission * weight);
+        self.direct += &(&other.direct * weight);
+        self.indirect += &(&other.indirect * weight);
+        for (light, other_light) in self.lights.iter_mut().zip(other.lights.iter()) {
+            *light += &(other_light * weight);
+        }
+    }
+
+    // Ids can't be blended, they're left alone here and picked per pixel instead.
+    fn add_features(&mut self, other : &Sample, weight : f32) {
+        self.albedo += &(&other.albedo * weight);
+        self.normal += &(&other.normal * weight);
+        self.depth += other.depth * weight;
+        self.uv += &(&other.uv * weight);
+    }
+}
+
+// Weighted accumulation buffer. Lighting is splatted into every pixel the
+// reconstruction filter covers, and the image is the weighted average.
+// Surface features (depth, normals...) would ring or extrapolate under filters
+// with negative lobes, so they are box filtered within their own pixel.
+//
+// Only the colour is always kept. The lighting split for the AOV passes and
+// the features for the denoiser are stored once asked for with `with_passes`
+// and `with_features`, and are left at zero in the resolved samples otherwise.
+//
+// Pixels are addressed top row first. A film can be a horizontal band of the
+// full image (see `tile`), so worker threads can splat without sharing memory;
+// bands include the filter margin and get merged back into the full film.
+pub struct Film {
+    pub nx : usize,
+    pub ny : usize,
+    pub filter : Filter,
+    row_start : usize,
+    row_end : usize,
+    colour : Vec<Vec3>,
+    weights : Vec<f32>,
+    // Filtered sums of the whole sample, for the emission, direct, indirect and light passes.
+    passes : Vec<Sample>,
+    // Box filtered sums of the features.
+    features : Vec<Sample>,
+    // Squared distance to the pixel centre and the ids of the closest sample.
+    ids : Vec<(f32, u32, u32)>,
+    // Unfiltered luminance moments of the samples taken inside each pixel,
+    // (sum, sum of squares, count), for the per-pixel variance estimate.
+    moments : Vec<(f32, f32, u32)>,
+}
+
+// Resolved film, colour plus the auxiliary feature buffers. This is what the
+// denoiser works on and what gets saved for denoising later.
+pub struct FilmBuffers {
+    pub nx : usize,
+    pub ny : usize,
+    pub colour : Vec<Vec3>,
+    pub albedo : Vec<Vec3>,
+    pub normal : Vec<Vec3>,
+    pub depth : Vec<f32>,
+    /// Variance of each pixel's mean luminance.
+    pub variance : Vec<f32>,
+}
+
+impl Film {
+    pub fn build(nx : usize, ny : usize, filter : Filter) -> Self {
+        Self::build_rows(nx, ny, filter, 0, ny)
+    }
+
+    fn build_rows(nx : usize, ny : usize, filter : Filter, row_start : usize, row_end : usize) -> Self {
+        let size = nx * (row_end - row_start);
+        Film {
+            nx,
+            ny,
+            filter,
+            row_start,
+            row_end,
+            colour : vec![Vec3::default(); size],
+            weights : vec![0.0; size],
+            passes : vec![],
+            features : vec![],
+            ids : vec![],
+            moments : vec![],
+        }
+    }
+
+    // Keeps the lighting split up for the AOV passes.
+    pub fn with_passes(mut self) -> Self {
+        self.passes = vec![Sample::default(); self.colour.len()];
+        self
+    }
+
+    // Keeps the first hit features, ids and variance for the denoiser and the AOV passes.
+    pub fn with_features(mut self) -> Self {
+        let size = self.colour.len();
+        self.features = vec![Sample::default(); size];
+        self.ids = vec![(f32::MAX, 0, 0); size];
+        self.moments = vec![(0.0, 0.0, 0); size];
+        self
+    }
+
+    // An empty film for rendering rows [row_start, row_end), widened by the
+    // filter radius so splats across the band edges aren't lost.
+    pub fn tile(&self, row_start : usize, row_end : usize) -> Self {
+        let margin = self.filter.radius.ceil() as usize;
+        let start = row_start.saturating_sub(margin);
+        let end = (row_end + margin).min(self.ny);
+        let mut tile = Self::build_rows(self.nx, self.ny, self.filter, start, end);
+        if !self.passes.is_empty() {
+            tile = tile.with_passes();
+        }
+        if !self.features.is_empty() {
+            tile = tile.with_features();
+        }
+        tile
+    }
+
+    // (x, y) is the continuous raster position, x to the right and y down,
+    // with pixel (i, j) covering [i, i+1) x [j, j+1).
+    pub fn add_sample(&mut self, x : f32, y : f32, sample : &Sample) {
+        let radius = self.filter.radius;
+        let x0 = (x - 0.5 - radius).ceil().max(0.0) as usize;
+        let x1 = ((x - 0.5 + radius).floor() as i64).min(self.nx as i64 - 1);
+        let y0 = ((y - 0.5 - radius).ceil().max(0.0) as usize).max(self.row_start);
+        let y1 = ((y - 0.5 + radius).floor() as i64).min(self.row_end as i64 - 1);
+
+        for j in y0 as i64..=y1 {
+            let dy = j as f32 + 0.5 - y;
+            let row = (j as usize - self.row_start) * self.nx;
+            for i in x0 as i64..=x1 {
+                let weight = self.filter.evaluate(i as f32 + 0.5 - x, dy);
+                if weight != 0.0 {
+                    let index = row + i as usize;
+                    self.colour[index] += &(&sample.colour * weight);
+                    self.weights[index] += weight;
+                    if let Some(passes) = self.passes.get_mut(index) {
+                        passes.add_lighting(sample, weight);
+                    }
+                }
+            }
+        }
+
+        if self.features.is_empty() {
+            return;
+        }
+        // Samples on the far edge of the image belong to the last column or row.
+        let i = (x.floor().max(0.0) as usize).min(self.nx - 1);
+        let j = (y.floor().max(0.0) as usize).min(self.ny - 1);
+        if j >= self.row_start && j < self.row_end {
+            let index = (j - self.row_start) * self.nx + i;
+            let luminance = luminance(&sample.colour);
+            let moment = &mut self.moments[index];
+            moment.0 += luminance;
+            moment.1 += luminance * luminance;
+            moment.2 += 1;
+            self.features[index].add_features(sample, 1.0);
+
+            let (dx, dy) = (x - i as f32 - 0.5, y - j as f32 - 0.5);
+            let distance = dx * dx + dy * dy;
+            if distance < self.ids[index].0 {
+                self.ids[index] = (distance, sample.object_id, sample.material_id);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, tile : &Film) {
+        let offset = (tile.row_start - self.row_start) * self.nx;
+        for index in 0..tile.colour.len() {
+            let target = offset + index;
+            self.colour[target] += &tile.colour[index];
+            self.weights[target] += tile.weights[index];
+        }
+        for (index, passes) in tile.passes.iter().enumerate() {
+            self.passes[offset + index].add_lighting(passes, 1.0);
+        }
+        for (index, features) in tile.features.iter().enumerate() {
+            let target = offset + index;
+            self.features[target].add_features(features, 1.0);
+            if tile.ids[index].0 < self.ids[target].0 {
+                self.ids[target] = tile.ids[index];
+            }
+            let (sum, sum_sq, count) = tile.moments[index];
+            let moment = &mut self.moments[target];
+            moment.0 += sum;
+            moment.1 += sum_sq;
+            moment.2 += count;
+        }
+    }
+
+    // Filtered colour, clamped to be non negative.
+    pub fn resolve_colour(&self) -> Vec<Vec3> {
+        self.colour.iter().zip(self.weights.iter()).map(|(colour, &weight)| {
+            if weight != 0.0 {
+                (colour / weight).max(&Vec3::default())
+            } else {
+                Vec3::default()
+            }
+        }).collect()
+    }
+
+    // Filtered per pixel samples, ids are those of the sample closest to the pixel centre.
+    pub fn resolve_samples(&self) -> Vec<Sample> {
+        let mut resolved = Vec::with_capacity(self.colour.len());
+        for (index, colour) in self.resolve_colour().into_iter().enumerate() {
+            let mut sample = Sample::default();
+            let weight = self.weights[index];
+            if let Some(passes) = self.passes.get(index).filter(|_| weight != 0.0) {
+                sample.add_lighting(passes, 1.0 / weight);
+            }
+            sample.colour = colour;
+            if let Some(features) = self.features.get(index) {
+                let count = self.moments[index].2;
+                if count != 0 {
+                    sample.add_features(features, 1.0 / count as f32);
+                }
+                if sample.normal.square_length() > 0.0 {
+                    sample.normal = &sample.normal / sample.normal.length();
+                }
+                let (_, object_id, material_id) = self.ids[index];
+                sample.object_id = object_id;
+                sample.material_id = material_id;
+            }
+            resolved.push(sample);
+        }
+        resolved
+    }
+
+    pub fn buffers(&self) -> FilmBuffers {
+        let samples = self.resolve_samples();
+        let variance = if self.moments.is_empty() {
+            vec![0.0; samples.len()]
+        } else {
+            self.moments.iter().map(|&(sum, sum_sq, count)| {
+                if count > 1 {
+                    let n = count as f32;
+                    let mean = sum / n;
+                    ((sum_sq / n - mean * mean) / (n - 1.0)).max(0.0)
+                } else {
+                    0.0
+                }
+            }).collect()
+        };
+
+        FilmBuffers {
+            nx : self.nx,
+            ny : self.row_end - self.row_start,
+            colour : samples.iter().map(|sample| sample.colour).collect(),
+            albedo : samples.iter().map(|sample| sample.albedo).collect(),
+            normal : samples.iter().map(|sample| sample.normal).collect(),
+            depth : samples.iter().map(|sample| sample.depth).collect(),
+            variance,
+        }
+    }
+}
+
+impl FilmBuffers {
+    // Saved as a set of PFM files sharing a common prefix.
+    pub fn save(&self, prefix : &str) -> io::Result<()> {
+        let create = |name : &str| File::create(format!("{}_{}.pfm", prefix, name)).map(BufWriter::new);
+        imageio::write_pfm(&mut create("colour")?, self.nx, self.ny, &self.colour)?;
+        imageio::write_pfm(&mut create("albedo")?, self.nx, self.ny, &self.albedo)?;
+        imageio::write_pfm(&mut create("normal")?, self.nx, self.ny, &self.normal)?;
+        imageio::write_pfm_mono(&mut create("depth")?, self.nx, self.ny, &self.depth)?;
+        imageio::write_pfm_mono(&mut create("variance")?, self.nx, self.ny, &self.variance)
+    }
+
+    pub fn load(prefix : &str) -> io::Result<FilmBuffers> {
+        let open = |name : &str| imageio::read_pfm(&format!("{}_{}.pfm", prefix, name));
+        let colour = open("colour")?;
+        let (nx, ny) = (colour.nx, colour.ny);
+        let rest = [open("albedo")?, open("normal")?, open("depth")?, open("variance")?];
+        if rest.iter().any(|image| image.nx != nx || image.ny != ny) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "buffer sizes don't match"));
+        }
+        let [albedo, normal, depth, variance] = rest;
+
+        Ok(FilmBuffers {
+            nx,
+            ny,
+            colour : colour.to_colours(),
+            albedo : albedo.to_colours(),
+            normal : normal.to_colours(),
+            depth : depth.to_scalars(),
+            variance : variance.to_scalars(),
+        })
+    }
+}
+
+pub fn luminance(c : &Vec3) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn samples_on_the_far_edge_land_in_the_last_pixel() {
+        let mut film = Film::build(4, 3, Filter::build(FilterKind::Box, 0.5)).with_features();
+        let sample = Sample { depth : 2.0, colour : Vec3::from(1.0, 1.0, 1.0), ..Sample::default() };
+        film.add_sample(4.0, 3.0, &sample);
+        let buffers = film.buffers();
+        assert_eq!(buffers.depth[3 * 4 - 1], 2.0);
+        assert_eq!(buffers.depth.iter().filter(|&&depth| depth != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn tiles_merge_back_into_the_full_film() {
+        let filter = Filter::build(FilterKind::Tent, 1.0);
+        let sample = Sample { colour : Vec3::from(1.0, 0.5, 0.25), depth : 1.0, ..Sample::default() };
+        let mut whole = Film::build(5, 6, filter).with_features();
+        let mut film = Film::build(5, 6, filter).with_features();
+        let mut tiles = [film.tile(0, 3), film.tile(3, 6)];
+        for j in 0..6 {
+            for i in 0..5 {
+                let (x, y) = (i as f32 + 0.3, j as f32 + 0.6);
+                whole.add_sample(x, y, &sample);
+                tiles[j / 3].add_sample(x, y, &sample);
+            }
+        }
+        for tile in tiles.iter() {
+            film.merge(tile);
+        }
+        let (expected, merged) = (whole.buffers(), film.buffers());
+        for (a, b) in expected.colour.iter().zip(merged.colour.iter()) {
+            assert!((a - b).length() < 1e-5);
+        }
+        assert_eq!(expected.depth, merged.depth);
+    }
+}
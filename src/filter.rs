@@ -0,0 +1,121 @@
+use std::f32;
+
+#[derive(Copy, Clone, Debug)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    /// Gaussian with falloff `alpha`, shifted so it reaches zero at the radius.
+    Gaussian { alpha : f32 },
+    /// Mitchell-Netravali cubic, B = C = 1/3 is the usual recommendation.
+    Mitchell { b : f32, c : f32 },
+    /// Lanczos windowed sinc using the radius as the number of lobes.
+    Lanczos,
+}
+
+// Separable pixel reconstruction filter. Offsets and radius are in pixels.
+// Weights are scaled so the filter integrates to 1 over the plane.
+#[derive(Copy, Clone, Debug)]
+pub struct Filter {
+    pub kind : FilterKind,
+    pub radius : f32,
+    scale : f32,
+}
+
+impl Filter {
+    pub fn build(kind : FilterKind, radius : f32) -> Self {
+        let mut filter = Filter { kind, radius, scale : 1.0 };
+        // Midpoint rule over [-radius, radius], plenty for these smooth profiles.
+        const STEPS : usize = 4096;
+        let step = 2.0 * radius / STEPS as f32;
+        let integral : f32 = (0..STEPS).map(|i| filter.evaluate_1d(-radius + (i as f32 + 0.5) * step) * step).sum();
+        if integral > 0.0 {
+            filter.scale = 1.0 / (integral * integral);
+        }
+        filter
+    }
+
+    pub fn evaluate(&self, dx : f32, dy : f32) -> f32 {
+        self.scale * self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x : f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian { alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * self.radius * self.radius).exp()).max(0.0)
+            }
+            FilterKind::Mitchell { b, c } => mitchell(2.0 * x / self.radius, b, c),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+// x in [0, 2]
+fn mitchell(x : f32, b : f32, c : f32) -> f32 {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    let value = if x > 1.0 {
+        (-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+    } else {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    };
+    value / 6.0
+}
+
+fn sinc(x : f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    let px = f32::consts::PI * x;
+    px.sin() / px
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integral(filter : &Filter) -> f32 {
+        let steps = 400;
+        let step = 2.0 * filter.radius / steps as f32;
+        let at = |i : usize| -filter.radius + (i as f32 + 0.5) * step;
+        let mut sum = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                sum += filter.evaluate(at(i), at(j)) * step * step;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn filters_integrate_to_one() {
+        let kinds = [
+            (FilterKind::Box, 0.5),
+            (FilterKind::Tent, 1.0),
+            (FilterKind::Gaussian { alpha : 2.0 }, 1.5),
+            (FilterKind::Mitchell { b : 1.0 / 3.0, c : 1.0 / 3.0 }, 2.0),
+            (FilterKind::Lanczos, 3.0),
+        ];
+        for (kind, radius) in kinds.iter() {
+            for scale in [1.0, 2.0].iter() {
+                let filter = Filter::build(*kind, radius * scale);
+                let total = integral(&filter);
+                assert!((total - 1.0).abs() < 0.01, "{:?} radius {} integrates to {}", kind, filter.radius, total);
+            }
+        }
+    }
+
+    #[test]
+    fn filters_vanish_outside_the_radius() {
+        let filter = Filter::build(FilterKind::Mitchell { b : 1.0 / 3.0, c : 1.0 / 3.0 }, 2.0);
+        assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        assert_eq!(filter.evaluate(2.01, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.0, -2.01), 0.0);
+    }
+}
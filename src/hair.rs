@@ -0,0 +1,442 @@
+use crate::curve::ribbon_frame;
+use crate::material::Material;
+use crate::ray::*;
+use crate::rng::Random;
+use crate::vec3::*;
+
+use std::f32::consts::{LN_2, PI};
+
+// Lobes modelled separately: R, TT and TRT, with everything after lumped
+// into one more.
+const P_MAX : usize = 3;
+
+// Hair fibre scattering after d'Eon et al. and Chiang et al., as laid out in
+// pbrt. Light reflects off the cuticle (R), passes through the fibre (TT) or
+// bounces once inside it (TRT); each lobe has a longitudinal spread from the
+// roughness `beta_m` and an azimuthal one from `beta_n`, and the cuticle
+// scales tilt the lobes by `alpha` degrees.
+//
+// The fibre frame comes from the hit's dpdu along the strand, with h, the
+// offset across the fibre, taken from v as `Curve` sets it.
+pub struct Hair {
+    sigma_a : Vec3,
+    colour : Option<Vec3>,
+    eta : f32,
+    beta_m : f32,
+    beta_n : f32,
+    alpha : f32,
+    // Derived from the above by `update`.
+    variance : [f32; P_MAX + 1],
+    scale : f32,
+    sin_2k_alpha : [f32; 3],
+    cos_2k_alpha : [f32; 3]
+}
+
+impl Hair {
+    // Absorption coefficients per unit of fibre diameter.
+    pub fn build(sigma_a : Vec3) -> Box<Hair> {
+        let mut hair = Box::new(Hair {
+            sigma_a,
+            colour : None,
+            eta : 1.55,
+            beta_m : 0.3,
+            beta_n : 0.3,
+            alpha : 2.0,
+            variance : [0.0; P_MAX + 1],
+            scale : 0.0,
+            sin_2k_alpha : [0.0; 3],
+            cos_2k_alpha : [0.0; 3]
+        });
+        hair.update();
+        hair
+    }
+
+    // Natural hair colours from the concentrations of the two melanin
+    // pigments: eumelanin runs from blonde at 0.3 through brown to black at
+    // 8, and pheomelanin adds red.
+    pub fn from_melanin(eumelanin : f32, pheomelanin : f32) -> Box<Hair> {
+        let eumelanin_sigma_a = Vec3::from(0.419, 0.697, 1.37);
+        let pheomelanin_sigma_a = Vec3::from(0.187, 0.4, 1.05);
+        Hair::build(&eumelanin_sigma_a * eumelanin + &pheomelanin_sigma_a * pheomelanin)
+    }
+
+    // Absorption chosen so that many strands together look about `colour`.
+    pub fn from_colour(colour : Vec3) -> Box<Hair> {
+        let mut hair = Hair::build(Vec3::default());
+        hair.colour = Some(colour);
+        hair.update();
+        hair
+    }
+
+    // Longitudinal and azimuthal roughness, both between 0 and 1.
+    pub fn with_roughness(mut self : Box<Self>, longitudinal : f32, azimuthal : f32) -> Box<Self> {
+        self.beta_m = longitudinal.clamp(0.0, 1.0);
+        self.beta_n = azimuthal.clamp(0.0, 1.0);
+        self.update();
+        self
+    }
+
+    pub fn with_scale_tilt(mut self : Box<Self>, degrees : f32) -> Box<Self> {
+        self.alpha = degrees;
+        self.update();
+        self
+    }
+
+    pub fn with_refraction_index(mut self : Box<Self>, eta : f32) -> Box<Self> {
+        self.eta = eta;
+        self
+    }
+
+    fn update(&mut self) {
+        let beta_m = self.beta_m;
+        let base = 0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20);
+        let base = base * base;
+        self.variance = [base, 0.25 * base, 4.0 * base, 4.0 * base];
+
+        let beta_n = self.beta_n;
+        let sqrt_pi_over_8 = 0.626_657_07;
+        self.scale = sqrt_pi_over_8 * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+
+        self.sin_2k_alpha[0] = self.alpha.to_radians().sin();
+        self.cos_2k_alpha[0] = safe_sqrt(1.0 - self.sin_2k_alpha[0] * self.sin_2k_alpha[0]);
+        for i in 1..3 {
+            self.sin_2k_alpha[i] = 2.0 * self.cos_2k_alpha[i - 1] * self.sin_2k_alpha[i - 1];
+            self.cos_2k_alpha[i] = self.cos_2k_alpha[i - 1] * self.cos_2k_alpha[i - 1] - self.sin_2k_alpha[i - 1] * self.sin_2k_alpha[i - 1];
+        }
+
+        if let Some(colour) = self.colour {
+            let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+                + 5.574 * beta_n.powi(4) + 0.245 * beta_n.powi(5);
+            let channel = |c : f32| (c.max(1.0e-4).ln() / denominator).powi(2);
+            self.sigma_a = Vec3::from(channel(*colour.r()), channel(*colour.g()), channel(*colour.b()));
+        }
+    }
+
+    // Longitudinal angle after the cuticle tilt for lobe p.
+    fn tilted(&self, p : usize, sin_theta : f32, cos_theta : f32) -> (f32, f32) {
+        let (sin_alpha, cos_alpha, sign) = match p {
+            0 => (self.sin_2k_alpha[1], self.cos_2k_alpha[1], -1.0),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0], 1.0),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2], 1.0),
+            _ => return (sin_theta, cos_theta),
+        };
+        (sin_theta * cos_alpha + sign * cos_theta * sin_alpha, (cos_theta * cos_alpha - sign * sin_theta * sin_alpha).abs())
+    }
+
+    // Attenuation of each lobe leaving towards the viewer at cos_theta_o.
+    fn attenuation(&self, cos_theta_o : f32, h : f32) -> [Vec3; P_MAX + 1] {
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let eta_p = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o.max(1.0e-6);
+        let sin_gamma_t = (h / eta_p).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let path = 2.0 * cos_gamma_t / cos_theta_t.max(1.0e-6);
+        let transmittance = Vec3::from((-self.sigma_a.r() * path).exp(), (-self.sigma_a.g() * path).exp(), (-self.sigma_a.b() * path).exp());
+
+        let cos_gamma_o = safe_sqrt(1.0 - h * h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let mut ap = [Vec3::default(); P_MAX + 1];
+        ap[0] = Vec3::from(f, f, f);
+        ap[1] = &transmittance * ((1.0 - f) * (1.0 - f));
+        for p in 2..P_MAX {
+            ap[p] = &ap[p - 1].direct_product(&transmittance) * f;
+        }
+        // The rest as a geometric series. It only fails to converge at the
+        // fibre's edge with no absorption, where nothing gets in anyway.
+        let tf = &transmittance * f;
+        let rest = |a : f32, tf : f32| if tf < 1.0 { a * tf / (1.0 - tf) } else { 0.0 };
+        ap[P_MAX] = Vec3::from(
+            rest(*ap[P_MAX - 1].r(), *tf.r()),
+            rest(*ap[P_MAX - 1].g(), *tf.g()),
+            rest(*ap[P_MAX - 1].b(), *tf.b()));
+        ap
+    }
+
+    // Probability of sampling each lobe, in proportion to its attenuation.
+    fn lobe_pdf(ap : &[Vec3; P_MAX + 1]) -> [f32; P_MAX + 1] {
+        let weights = ap.map(|a| (a.r() + a.g() + a.b()) / 3.0);
+        let total : f32 = weights.iter().sum();
+        if total > 0.0 { weights.map(|w| w / total) } else { [1.0 / (P_MAX + 1) as f32; P_MAX + 1] }
+    }
+
+    // Scattering times the cosine term and the pdf of sampling it, for unit
+    // directions in the fibre frame: x along the strand, z facing the viewer.
+    fn evaluate(&self, wo : &Vec3, wi : &Vec3, h : f32) -> (Vec3, f32) {
+        let (sin_theta_o, sin_theta_i) = (wo.x().clamp(-1.0, 1.0), wi.x().clamp(-1.0, 1.0));
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+        let phi = wi.y().atan2(*wi.z()) - wo.y().atan2(*wo.z());
+
+        let eta_p = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o.max(1.0e-6);
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let gamma_t = (h / eta_p).clamp(-1.0, 1.0).asin();
+
+        let ap = self.attenuation(cos_theta_o, h);
+        let lobe_pdf = Hair::lobe_pdf(&ap);
+        let mut f = Vec3::default();
+        let mut pdf = 0.0;
+        for p in 0..P_MAX {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let mp = longitudinal(cos_theta_i, cos_theta_op, sin_theta_i, sin_theta_op, self.variance[p]);
+            let np = azimuthal(phi, p, self.scale, gamma_o, gamma_t);
+            f += &(&ap[p] * (mp * np));
+            pdf += mp * np * lobe_pdf[p];
+        }
+        let mp = longitudinal(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.variance[P_MAX]);
+        f += &(&ap[P_MAX] * (mp / (2.0 * PI)));
+        pdf += mp * lobe_pdf[P_MAX] / (2.0 * PI);
+        (f, pdf)
+    }
+
+    // Picks a lobe, then a longitudinal and an azimuthal angle from it.
+    fn sample(&self, wo : &Vec3, h : f32, rnd : &mut Random) -> Vec3 {
+        let sin_theta_o = wo.x().clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.y().atan2(*wo.z());
+
+        let lobe_pdf = Hair::lobe_pdf(&self.attenuation(cos_theta_o, h));
+        let mut choice = rnd.gen();
+        let mut p = 0;
+        while p < P_MAX && choice >= lobe_pdf[p] {
+            choice -= lobe_pdf[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let variance = self.variance[p];
+        let u = rnd.gen().max(1.0e-5);
+        let cos_theta = 1.0 + variance * (u + (1.0 - u) * (-2.0 / variance).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * rnd.gen()).cos();
+        let sin_theta_i = (-cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op).clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let eta_p = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o.max(1.0e-6);
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let gamma_t = (h / eta_p).clamp(-1.0, 1.0).asin();
+        let delta_phi = if p < P_MAX {
+            lobe_phi(p, gamma_o, gamma_t) + sample_trimmed_logistic(rnd.gen(), self.scale, -PI, PI)
+        } else {
+            2.0 * PI * rnd.gen()
+        };
+        let phi_i = phi_o + delta_phi;
+        Vec3::from(sin_theta_i, cos_theta_i * phi_i.cos(), cos_theta_i * phi_i.sin())
+    }
+}
+
+impl Material for Hair {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        rnd: &mut Random,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let length = rec.dpdu.length();
+        if length == 0.0 {
+            return false;
+        }
+        let along = &rec.dpdu / length;
+        let (facing, side) = ribbon_frame(&along, &ray.direction);
+        // pbrt's frame has y = facing × along, the opposite way to v.
+        let across = &side * -1.0;
+        let h = -1.0 + 2.0 * rec.v;
+
+        let outgoing = &(Vec3::default() - ray.direction) / ray.direction.length();
+        let wo = Vec3::from(dot(&outgoing, &along), dot(&outgoing, &across), dot(&outgoing, &facing));
+        let wi = self.sample(&wo, h, rnd);
+        let (f, pdf) = self.evaluate(&wo, &wi, h);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return false;
+        }
+
+        let direction = &along * *wi.x() + &across * *wi.y() + &facing * *wi.z();
+        // Start beyond the strand on whichever side the light leaves, so
+        // the new ray doesn't find the same ribbon again.
+        let clearance = rec.dpdv.length();
+        let offset = if *wi.z() < 0.0 { -clearance } else { clearance };
+        scattered.origin = rec.p + &facing * offset;
+        scattered.direction = direction;
+        scattered.time = ray.time;
+        attenuation.set(&(&f / pdf));
+        true
+    }
+
+    fn albedo(&self, _rec : &HitRecord) -> Vec3 {
+        let beta_n = self.beta_n;
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4) + 0.245 * beta_n.powi(5);
+        let channel = |sigma : f32| (-sigma.sqrt() * denominator).exp();
+        Vec3::from(channel(*self.sigma_a.r()), channel(*self.sigma_a.g()), channel(*self.sigma_a.b()))
+    }
+}
+
+fn safe_sqrt(x : f32) -> f32 {
+    x.max(0.0).sqrt()
+}
+
+// Fresnel reflectance for unpolarised light arriving from outside at
+// cos_theta onto a dielectric of index eta.
+fn fresnel_dielectric(cos_theta : f32, eta : f32) -> f32 {
+    let cos_i = cos_theta.clamp(-1.0, 1.0);
+    let (eta_i, eta_t, cos_i) = if cos_i > 0.0 { (1.0, eta, cos_i) } else { (eta, 1.0, -cos_i) };
+    let sin_t = eta_i / eta_t * safe_sqrt(1.0 - cos_i * cos_i);
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+    let parallel = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let perpendicular = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Modified Bessel function of the first kind, order zero.
+fn bessel_i0(x : f32) -> f32 {
+    let mut sum = 0.0;
+    let mut x2i = 1.0;
+    let mut four_i = 1.0;
+    let mut factorial = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f32;
+        }
+        sum += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    sum
+}
+
+fn log_bessel_i0(x : f32) -> f32 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Longitudinal scattering, with a log form for narrow lobes where the
+// direct one overflows.
+fn longitudinal(cos_theta_i : f32, cos_theta_o : f32, sin_theta_i : f32, sin_theta_o : f32, variance : f32) -> f32 {
+    let a = cos_theta_i * cos_theta_o / variance;
+    let b = sin_theta_i * sin_theta_o / variance;
+    if variance <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / variance + LN_2 + (1.0 / (2.0 * variance)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / variance).sinh() * 2.0 * variance)
+    }
+}
+
+// Azimuth lobe p leaves at relative to the incoming direction.
+fn lobe_phi(p : usize, gamma_o : f32, gamma_t : f32) -> f32 {
+    let p = p as f32;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x : f32, s : f32) -> f32 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x : f32, s : f32) -> f32 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x : f32, s : f32, a : f32, b : f32) -> f32 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u : f32, s : f32, a : f32, b : f32) -> f32 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// Azimuthal scattering for lobe p.
+fn azimuthal(phi : f32, p : usize, s : f32, gamma_o : f32, gamma_t : f32) -> f32 {
+    let mut delta = phi - lobe_phi(p, gamma_o, gamma_t);
+    while delta > PI {
+        delta -= 2.0 * PI;
+    }
+    while delta < -PI {
+        delta += 2.0 * PI;
+    }
+    trimmed_logistic(delta, s, -PI, PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{Hitable, XzRect};
+
+    // The albedo doesn't depend on the hit, any surface record will do.
+    fn albedo(hair : &Hair) -> Vec3 {
+        let floor = XzRect { x0 : 0.0, x1 : 1.0, z0 : 0.0, z1 : 1.0, y : 0.0, material : Hair::from_colour(Vec3::from(1.0, 1.0, 1.0)) };
+        let down = Ray { origin : Vec3::from(0.5, 1.0, 0.5), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        hair.albedo(&floor.hit(&down, 0.001, f32::MAX).unwrap())
+    }
+
+    fn random_direction(rnd : &mut Random) -> Vec3 {
+        let z = 1.0 - 2.0 * rnd.gen();
+        let phi = 2.0 * PI * rnd.gen();
+        let r = safe_sqrt(1.0 - z * z);
+        Vec3::from(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn colours_come_back_as_the_albedo() {
+        let colour = Vec3::from(0.6, 0.35, 0.2);
+        let hair = Hair::from_colour(colour).with_roughness(0.4, 0.5);
+        assert!((albedo(&hair) - colour).length() < 1e-4, "{:?}", albedo(&hair));
+        // White needs nothing absorbed, and darker channels absorb more.
+        assert!(Hair::from_colour(Vec3::from(1.0, 1.0, 1.0)).sigma_a.length() < 1e-6);
+        assert!(hair.sigma_a.r() < hair.sigma_a.g() && hair.sigma_a.g() < hair.sigma_a.b());
+        let melanin = Hair::from_melanin(1.0, 0.0);
+        assert!((melanin.sigma_a - Vec3::from(0.419, 0.697, 1.37)).length() < 1e-6);
+    }
+
+    #[test]
+    fn fibres_without_absorption_lose_no_energy() {
+        // Over every incoming direction the scattering integrates to one,
+        // whatever the roughness, for any view and offset across the fibre.
+        let mut rnd = Random::create_with_seed(11);
+        for roughness in [0.2, 0.5, 0.8].iter() {
+            let hair = Hair::build(Vec3::default()).with_roughness(*roughness, *roughness);
+            let count = 100000;
+            let mut sum = 0.0;
+            for _ in 0..count {
+                let wo = random_direction(&mut rnd);
+                let wi = random_direction(&mut rnd);
+                let h = -1.0 + 2.0 * rnd.gen();
+                let (f, _) = hair.evaluate(&wo, &wi, h);
+                sum += f.g();
+            }
+            let integral = 4.0 * PI * sum / count as f32;
+            assert!((integral - 1.0).abs() < 0.05, "roughness {} integrates to {}", roughness, integral);
+        }
+    }
+
+    #[test]
+    fn sampled_directions_are_weighted_to_one() {
+        // With nothing absorbed the sampling weights average out to the
+        // whole of the energy too.
+        let mut rnd = Random::create_with_seed(5);
+        let hair = Hair::build(Vec3::default()).with_roughness(0.4, 0.4);
+        let count = 50000;
+        let mut sum = 0.0;
+        for _ in 0..count {
+            let wo = random_direction(&mut rnd);
+            let h = -1.0 + 2.0 * rnd.gen();
+            let wi = hair.sample(&wo, h, &mut rnd);
+            assert!((wi.length() - 1.0).abs() < 1e-3);
+            let (f, pdf) = hair.evaluate(&wo, &wi, h);
+            if pdf > 0.0 {
+                sum += f.g() / pdf;
+            }
+        }
+        let mean = sum / count as f32;
+        assert!((mean - 1.0).abs() < 0.05, "weights average {}", mean);
+    }
+}
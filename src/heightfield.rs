@@ -0,0 +1,236 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::texture::*;
+use crate::vec3::*;
+
+// Terrain from a grid of heights over the rectangle `min`..`max` in x and
+// z, each grid square split into two triangles. Heights run from 0 at
+// `min.y` to 1 at `max.y`. Rays walk the squares under them in order, so a
+// million point terrain costs about as much per ray as its width in
+// squares. Shading uses normals interpolated from the grid for smooth
+// slopes; the geometric surface is still the triangles.
+//
+// u runs along +x and v towards -z, so an image used for both the heights
+// and the colour reads the right way up seen from above.
+pub struct Heightfield {
+    // Vertex counts along x and z.
+    nx : usize,
+    nz : usize,
+    min : Vec3,
+    max : Vec3,
+    // World space height of each vertex, row by row along x.
+    heights : Vec<f32>,
+    normals : Vec<Vec3>,
+    // Lowest and highest vertex of each square, to skip squares the ray
+    // passes over or under.
+    cell_ranges : Vec<(f32, f32)>,
+    bounds : Aabb,
+    material : Box<dyn Material>
+}
+
+impl Heightfield {
+    // `heights` holds nx * nz values, row by row along x starting at min.z.
+    pub fn build(heights : Vec<f32>, resolution : (usize, usize), min : Vec3, max : Vec3, material : Box<dyn Material>) -> Box<Heightfield> {
+        let (nx, nz) = resolution;
+        assert!(nx >= 2 && nz >= 2, "a heightfield needs at least 2x2 heights");
+        assert_eq!(heights.len(), nx * nz, "heightfield resolution doesn't match the heights");
+        let heights : Vec<f32> = heights.iter().map(|h| min.y() + h * (max.y() - min.y())).collect();
+        let (dx, dz) = ((max.x() - min.x()) / (nx - 1) as f32, (max.z() - min.z()) / (nz - 1) as f32);
+
+        // Central differences, one sided at the edges.
+        let height = |i : usize, j : usize| heights[i + nx * j];
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(nz - 1));
+                let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f32 * dx);
+                let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f32 * dz);
+                let normal = Vec3::from(-slope_x, 1.0, -slope_z);
+                normals.push(&normal / normal.length());
+            }
+        }
+
+        let mut cell_ranges = Vec::with_capacity((nx - 1) * (nz - 1));
+        let (mut low, mut high) = (f32::MAX, f32::MIN);
+        for j in 0..nz - 1 {
+            for i in 0..nx - 1 {
+                let corners = [height(i, j), height(i + 1, j), height(i, j + 1), height(i + 1, j + 1)];
+                let cell_low = corners.iter().cloned().fold(f32::MAX, f32::min);
+                let cell_high = corners.iter().cloned().fold(f32::MIN, f32::max);
+                low = low.min(cell_low);
+                high = high.max(cell_high);
+                cell_ranges.push((cell_low, cell_high));
+            }
+        }
+        let bounds = padded_bounds(&[Vec3::from(*min.x(), low, *min.z()), Vec3::from(*max.x(), high, *max.z())]);
+        Box::new(Heightfield { nx, nz, min, max, heights, normals, cell_ranges, bounds, material })
+    }
+
+    // Heights read from the grey level of `texture` at each vertex's uv and
+    // position (at min.y), so both image and solid noise textures work.
+    pub fn from_texture(texture : &dyn Texture, resolution : (usize, usize), min : Vec3, max : Vec3, material : Box<dyn Material>) -> Box<Heightfield> {
+        let (nx, nz) = resolution;
+        let mut heights = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (s, t) = (i as f32 / (nx - 1).max(1) as f32, j as f32 / (nz - 1).max(1) as f32);
+                let p = Vec3::from(min.x() + s * (max.x() - min.x()), *min.y(), min.z() + t * (max.z() - min.z()));
+                let colour = texture.colour(s, 1.0 - t, &p);
+                heights.push((colour.r() + colour.g() + colour.b()) / 3.0);
+            }
+        }
+        Heightfield::build(heights, resolution, min, max, material)
+    }
+
+    // One vertex per pixel of a height map, read as linear grey.
+    pub fn from_image(path : String, min : Vec3, max : Vec3, material : Box<dyn Material>) -> Result<Box<Heightfield>, TextureError> {
+        let image = ImageTexture::load_with_colour_space(path, ColourSpace::Linear)?.with_filter(TextureFilter::Nearest);
+        let (width, height) = image.size();
+        if width < 2 || height < 2 {
+            return Ok(Heightfield::from_texture(&image, (2, 2), min, max, material));
+        }
+        let mut heights = Vec::with_capacity(width * height);
+        for j in 0..height {
+            for i in 0..width {
+                // Texel centres, top row at min.z.
+                let colour = image.colour((i as f32 + 0.5) / width as f32, 1.0 - (j as f32 + 0.5) / height as f32, &Vec3::default());
+                heights.push((colour.r() + colour.g() + colour.b()) / 3.0);
+            }
+        }
+        Ok(Heightfield::build(heights, (width, height), min, max, material))
+    }
+
+    fn vertex(&self, i : usize, j : usize) -> Vec3 {
+        let (s, t) = (i as f32 / (self.nx - 1) as f32, j as f32 / (self.nz - 1) as f32);
+        Vec3::from(self.min.x() + s * (self.max.x() - self.min.x()), self.heights[i + self.nx * j], self.min.z() + t * (self.max.z() - self.min.z()))
+    }
+
+    // Nearest hit in square (i, j) within the ray's range.
+    fn hit_cell(&self, ray : &Ray, i : usize, j : usize, t_min : f32, t_max : f32) -> Option<HitRecord<'_>> {
+        let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+        let [v00, v10, v01, v11] = corners.map(|(a, b)| self.vertex(a, b));
+        // Both wound to face up.
+        let triangles = [([v00, v11, v10], [0, 3, 1]), ([v00, v01, v11], [0, 2, 3])];
+        let mut best : Option<(f32, f32, f32, usize)> = None;
+        for (index, (points, _)) in triangles.iter().enumerate() {
+            let limit = best.map_or(t_max, |b| b.0);
+            if let Some((t, b1, b2)) = intersect_triangle(ray, points, t_min, limit) {
+                best = Some((t, b1, b2, index));
+            }
+        }
+        let (t, b1, b2, index) = best?;
+        let (triangle, indices) = &triangles[index];
+
+        let b0 = 1.0 - b1 - b2;
+        let normal_at = |k : usize| { let (a, b) = corners[k]; self.normals[a + self.nx * b] };
+        let normal = &normal_at(indices[0]) * b0 + &normal_at(indices[1]) * b1 + &normal_at(indices[2]) * b2;
+        let p = ray.point_at_parameter(t);
+        let (width, depth) = (self.max.x() - self.min.x(), self.max.z() - self.min.z());
+        // Tangents along the triangle's plane.
+        let face = cross(&(triangle[1] - triangle[0]), &(triangle[2] - triangle[0]));
+        let (slope_x, slope_z) = if *face.y() != 0.0 { (-face.x() / face.y(), -face.z() / face.y()) } else { (0.0, 0.0) };
+        Some(HitRecord::build(ray, t, &normal / normal.length(), &*self.material)
+            .with_uv((p.x() - self.min.x()) / width, (self.max.z() - p.z()) / depth)
+            .with_derivatives(Vec3::from(width, slope_x * width, 0.0), Vec3::from(0.0, -slope_z * depth, -depth)))
+    }
+}
+
+impl Hitable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t_start, t_end) = self.bounds.hit_range(ray, t_min, t_max)?;
+        let (cells_x, cells_z) = (self.nx - 1, self.nz - 1);
+        let dx = (self.max.x() - self.min.x()) / cells_x as f32;
+        let dz = (self.max.z() - self.min.z()) / cells_z as f32;
+
+        // Walk the squares under the ray, 2D DDA.
+        let start = ray.point_at_parameter(t_start);
+        let cell = |offset : f32, size : f32, count : usize| ((offset / size).floor().max(0.0) as usize).min(count - 1);
+        let mut i = cell(start.x() - self.min.x(), dx, cells_x);
+        let mut j = cell(start.z() - self.min.z(), dz, cells_z);
+        let (dir_x, dir_z) = (*ray.direction.x(), *ray.direction.z());
+        let boundary_t = |origin : f32, edge : f32, dir : f32| if dir == 0.0 { f32::MAX } else { (edge - origin) / dir };
+        let mut next_x = boundary_t(*ray.origin.x(), self.min.x() + dx * (if dir_x > 0.0 { i + 1 } else { i }) as f32, dir_x);
+        let mut next_z = boundary_t(*ray.origin.z(), self.min.z() + dz * (if dir_z > 0.0 { j + 1 } else { j }) as f32, dir_z);
+        let step_x = if dir_x == 0.0 { f32::MAX } else { dx / dir_x.abs() };
+        let step_z = if dir_z == 0.0 { f32::MAX } else { dz / dir_z.abs() };
+
+        let mut t_enter = t_start;
+        loop {
+            let t_exit = next_x.min(next_z).min(t_end);
+            // Skip squares the ray is entirely above or below.
+            let (low, high) = self.cell_ranges[i + cells_x * j];
+            let (y0, y1) = (ray.origin.y() + t_enter * ray.direction.y(), ray.origin.y() + t_exit * ray.direction.y());
+            if y0.min(y1) <= high + 1e-4 && y0.max(y1) >= low - 1e-4 {
+                if let Some(rec) = self.hit_cell(ray, i, j, t_min, t_max) {
+                    return Some(rec);
+                }
+            }
+            if t_exit >= t_end {
+                return None;
+            }
+            if next_x < next_z {
+                if dir_x > 0.0 { i += 1; if i >= cells_x { return None; } } else { if i == 0 { return None; } i -= 1; }
+                t_enter = next_x;
+                next_x += step_x;
+            } else {
+                if dir_z > 0.0 { j += 1; if j >= cells_z { return None; } } else { if j == 0 { return None; } j -= 1; }
+                t_enter = next_z;
+                next_z += step_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn ray(origin : Vec3, direction : Vec3) -> Ray {
+        Ray { origin, direction, time : 0.0 }
+    }
+
+    #[test]
+    fn rays_land_on_the_grid_triangles() {
+        // A ramp rising from y = 0 at z = 0 to y = 1 at z = 1.
+        let ramp = Heightfield::build(vec![0.0, 0.0, 1.0, 1.0], (2, 2), Vec3::default(), Vec3::from(1.0, 1.0, 1.0), grey());
+        let rec = ramp.hit(&ray(Vec3::from(0.5, 5.0, 0.25), Vec3::from(0.0, -1.0, 0.0)), 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 4.75).abs() < 1e-4);
+        assert!((rec.u - 0.5).abs() < 1e-4 && (rec.v - 0.75).abs() < 1e-4);
+        let half = 0.5f32.sqrt();
+        assert!((rec.normal - Vec3::from(0.0, half, -half)).length() < 1e-4);
+        // Running along the ground, the ramp is met halfway up.
+        let level = ray(Vec3::from(0.5, 0.5, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        assert!((ramp.hit(&level, 0.001, f32::MAX).unwrap().t - 5.5).abs() < 1e-4);
+        assert!(ramp.hit(&ray(Vec3::from(1.5, 5.0, 0.5), Vec3::from(0.0, -1.0, 0.0)), 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn height_maps_give_a_vertex_per_pixel() {
+        let hill = Heightfield::from_image(String::from("textures/hill_height.png"), Vec3::from(-1.0, 0.0, -1.0), Vec3::from(1.0, 2.0, 1.0), grey())
+            .expect("the height map fixture should load");
+        assert_eq!((hill.nx, hill.nz), (65, 65));
+        // White at the centre is the top, black in the corners the bottom.
+        assert!((hill.heights[32 + 65 * 32] - 2.0).abs() < 1e-4);
+        assert!(hill.heights[0].abs() < 1e-4);
+        let rec = hill.hit(&ray(Vec3::from(0.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0)), 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-3);
+        assert!(*rec.normal.y() > 0.99);
+
+        match Heightfield::from_image(String::from("textures/no_such_height.png"), Vec3::default(), Vec3::from(1.0, 1.0, 1.0), grey()) {
+            Err(TextureError::NotFound(_)) => {},
+            _ => panic!("a missing height map should be NotFound")
+        }
+    }
+}
@@ -13,10 +13,32 @@ use std::vec::Vec;
 use std::f32; 
 
 pub trait Hitable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
     fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb;
+
+    // Every surface crossing between t_min and t_max, nearest first. Found by
+    // asking `hit` again from just past the previous crossing, shapes that
+    // know all their roots up front can do better.
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        const MAX_CROSSINGS : usize = 64;
+        let mut hits = Vec::new();
+        let mut t = t_min;
+        while hits.len() < MAX_CROSSINGS {
+            match self.hit(ray, t, t_max) {
+                Some(rec) => {
+                    t = rec.t + CROSSING_EPSILON * (1.0 + rec.t.abs());
+                    hits.push(rec);
+                },
+                None => break
+            }
+        }
+        hits
+    }
 }
 
+// Gap left after a crossing before looking for the next one, relative to t.
+const CROSSING_EPSILON : f32 = 0.0001;
+
 pub struct BvhTree {
     pub root: Box<dyn Hitable>,
 }
@@ -27,12 +49,15 @@ pub struct BvhNode {
     pub right: Box<dyn Hitable>,
 }
 
+// uv from `get_sphere_uv`.
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
     pub material: Box<dyn Material>,
 }
 
+// uv as for `Sphere`, about the centre at the ray's time so the mapping
+// travels with the sphere.
 pub struct MovingSphere {
     pub center_start : Vec3,
     pub center_end : Vec3,
@@ -90,7 +115,7 @@ impl BvhNode {
 }
 
 impl Hitable for BvhNode {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         if self.bounding_box.hit(ray, t_min, t_max) {
             let hit_left = self.left.hit(ray, t_min, t_max);
             let hit_right = self.right.hit(ray, t_min, t_max);
@@ -116,106 +141,102 @@ impl Hitable for BvhNode {
     }
 }
 
-// not convinced this works if the sphere isn't centered at 0
-fn get_sphere_uv(center : &Vec3, p : &Vec3) -> (f32, f32) {
-    let p = (p - center).make_normalised();
-    let phi = p.z().atan2(*p.x());
-    let theta = p.y().asin();
+// Spherical uv about `center`, so it holds wherever the sphere is. u goes
+// once round the y axis, starting and ending on the -x side (the seam) and
+// increasing towards -z from +x; v runs from 0 at the bottom pole to 1 at the
+// top. Off the seam u stays in [0,1), the seam itself maps to 0.
+pub(crate) fn get_sphere_uv(center : &Vec3, p : &Vec3) -> (f32, f32) {
+    let d = p - center;
+    let length = d.length();
+    if length == 0.0 {
+        return (0.0, 0.5);
+    }
+    // `make_normalised` is approximate, asin needs the y in range.
+    let phi = d.z().atan2(*d.x());
+    let theta = (d.y() / length).clamp(-1.0, 1.0).asin();
     let u = 1.0 - (phi + f32::consts::PI) / (2.0*f32::consts::PI);
     let v = (theta + 0.5 * f32::consts::PI) / f32::consts::PI;
-    (u,v)
+    (if u >= 1.0 { 0.0 } else { u }, v)
 }
 
-impl Hitable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.square_length();
-        let b = dot(&oc, &ray.direction);
-        let c = oc.square_length() - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let tmp = (-b - discriminant.sqrt()) / a;
-            if tmp < t_max && tmp > t_min {
-                let hit_point = ray.point_at_parameter(tmp);
-                let (u,v) = get_sphere_uv(&self.center, &hit_point);
-                let record = HitRecord {
-                    t: tmp,
-                    p: hit_point,
-                    normal: &(hit_point - self.center) / self.radius,
-                    material: &*self.material,
-                    u, v
-                };
-                return Option::Some(record);
-            }
+// Derivatives of the point with respect to the uv from `get_sphere_uv`.
+pub(crate) fn get_sphere_dpduv(center : &Vec3, p : &Vec3) -> (Vec3, Vec3) {
+    let p = p - center;
+    let (x, y, z) = (*p.x(), *p.y(), *p.z());
+    let rho = (x * x + z * z).sqrt().max(1e-6);
+    let dpdu = &Vec3::from(z, 0.0, -x) * (2.0 * f32::consts::PI);
+    let dpdv = &Vec3::from(-y * x / rho, rho, -y * z / rho) * f32::consts::PI;
+    (dpdu, dpdv)
+}
 
-            let tmp = (-b + discriminant.sqrt()) / a;
-            if tmp < t_max && tmp > t_min {
-                let hit_point = ray.point_at_parameter(tmp);
-                let (u,v) = get_sphere_uv(&self.center, &hit_point);
-                let record = HitRecord {
-                    t: tmp,
-                    p: hit_point,
-                    normal: &(hit_point - self.center) / self.radius,
-                    material: &*self.material,
-                    u, v
-                };
-                return Option::Some(record);
-            }
-        }
+// Where a ray crosses the sphere about `center`, both roots nearest first.
+fn sphere_roots(center : &Vec3, radius : f32, ray : &Ray) -> Option<(f32, f32)> {
+    let oc = ray.origin - *center;
+    let a = ray.direction.square_length();
+    let b = dot(&oc, &ray.direction);
+    let c = oc.square_length() - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        Some(((-b - root) / a, (-b + root) / a))
+    } else {
+        None
+    }
+}
 
-        Option::None
+impl Sphere {
+    fn record(&self, ray : &Ray, t : f32) -> HitRecord<'_> {
+        let hit_point = ray.point_at_parameter(t);
+        let (u,v) = get_sphere_uv(&self.center, &hit_point);
+        let (dpdu, dpdv) = get_sphere_dpduv(&self.center, &hit_point);
+        HitRecord::build(ray, t, &(hit_point - self.center) / self.radius, &*self.material)
+            .with_uv(u, v)
+            .with_derivatives(dpdu, dpdv)
+    }
+}
+
+impl Hitable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (near, far) = sphere_roots(&self.center, self.radius, ray)?;
+        [near, far].iter().find(|t| **t < t_max && **t > t_min).map(|t| self.record(ray, *t))
     }
 
     fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
         let radial_length = Vec3::from(self.radius, self.radius, self.radius);
         Aabb::build(self.center - radial_length, self.center + radial_length)
     }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        match sphere_roots(&self.center, self.radius, ray) {
+            Some((near, far)) => [near, far].iter().filter(|t| **t < t_max && **t > t_min).map(|t| self.record(ray, *t)).collect(),
+            None => Vec::new()
+        }
+    }
 }
 
 impl MovingSphere {
     fn center(&self, time : f32)  -> Vec3 {
         self.center_start + (&(self.center_end - self.center_start) * ((time - self.time_start) / (self.time_end - self.time_start)))
     }
+
+    fn record(&self, ray : &Ray, t : f32) -> HitRecord<'_> {
+        let hit_point = ray.point_at_parameter(t);
+        let center = self.center(ray.time);
+        // Measured against where the sphere starts, so the texture moves with it.
+        let object_p = hit_point - center + self.center_start;
+        let (u,v) = get_sphere_uv(&center, &hit_point);
+        let (dpdu, dpdv) = get_sphere_dpduv(&center, &hit_point);
+        HitRecord::build(ray, t, &(hit_point - center) / self.radius, &*self.material)
+            .with_object_p(object_p)
+            .with_uv(u, v)
+            .with_derivatives(dpdu, dpdv)
+    }
 }
 
 impl Hitable for MovingSphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center(ray.time);
-        let a = ray.direction.square_length();
-        let b = dot(&oc, &ray.direction);
-        let c = oc.square_length() - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let tmp = (-b - discriminant.sqrt()) / a;
-            if tmp < t_max && tmp > t_min {
-                let hit_point = ray.point_at_parameter(tmp);
-                let (u,v) = get_sphere_uv(&self.center(ray.time), &hit_point);
-                let record = HitRecord {
-                    t: tmp,
-                    p: hit_point,
-                    normal: &(hit_point - self.center(ray.time)) / self.radius,
-                    material: &*self.material,
-                    u, v
-                };
-                return Option::Some(record);
-            }
-
-            let tmp = (-b + discriminant.sqrt()) / a;
-            if tmp < t_max && tmp > t_min {
-                let hit_point = ray.point_at_parameter(tmp);
-                let (u,v) = get_sphere_uv(&self.center(ray.time), &hit_point);
-                let record = HitRecord {
-                    t: tmp,
-                    p: hit_point,
-                    normal: &(hit_point - self.center(ray.time)) / self.radius,
-                    material: &*self.material,
-                    u, v
-                };
-                return Option::Some(record);
-            }
-        }
-
-        Option::None
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (near, far) = sphere_roots(&self.center(ray.time), self.radius, ray)?;
+        [near, far].iter().find(|t| **t < t_max && **t > t_min).map(|t| self.record(ray, *t))
     }
 
     fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb {
@@ -224,8 +245,17 @@ impl Hitable for MovingSphere {
         let bb1 = Aabb::build(self.center(time1) - radial_length, self.center(time1) + radial_length);
         Aabb::surrounding_box(&bb0, &bb1)
     }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        match sphere_roots(&self.center(ray.time), self.radius, ray) {
+            Some((near, far)) => [near, far].iter().filter(|t| **t < t_max && **t > t_min).map(|t| self.record(ray, *t)).collect(),
+            None => Vec::new()
+        }
+    }
 }
 
+// Axis aligned rects map [0,1]² linearly onto the rect, u along the first
+// named axis and v along the second, whichever side they're seen from.
 pub struct XyRect {
     pub x0 : f32,
     pub x1 : f32, 
@@ -236,7 +266,7 @@ pub struct XyRect {
 }
 
 impl Hitable for XyRect {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.z - ray.origin.z()) / ray.direction.z();
         if t < t_min || t > t_max {
             return Option::None;
@@ -248,14 +278,9 @@ impl Hitable for XyRect {
             return Option::None;
         }
 
-        let record = HitRecord { 
-            u: (x - self.x0)/(self.x1 - self.x0),
-            v: (y - self.y0)/(self.y1 - self.y0),
-            t,
-            material : &*self.material,
-            p : ray.point_at_parameter(t),
-            normal : Vec3::from(0.0, 0.0, 1.0)
-        };
+        let record = HitRecord::build(ray, t, Vec3::from(0.0, 0.0, 1.0), &*self.material)
+            .with_uv((x - self.x0)/(self.x1 - self.x0), (y - self.y0)/(self.y1 - self.y0))
+            .with_derivatives(Vec3::from(self.x1 - self.x0, 0.0, 0.0), Vec3::from(0.0, self.y1 - self.y0, 0.0));
 
         Option::Some(record)
     }
@@ -275,7 +300,7 @@ pub struct XzRect {
 }
 
 impl Hitable for XzRect {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.y - ray.origin.y()) / ray.direction.y();
         if t < t_min || t > t_max {
             return Option::None;
@@ -287,14 +312,9 @@ impl Hitable for XzRect {
             return Option::None;
         }
 
-        let record = HitRecord { 
-            u: (x - self.x0)/(self.x1 - self.x0),
-            v: (z - self.z0)/(self.z1 - self.z0),
-            t,
-            material : &*self.material,
-            p : ray.point_at_parameter(t),
-            normal : Vec3::from(0.0, 1.0, 0.0)
-        };
+        let record = HitRecord::build(ray, t, Vec3::from(0.0, 1.0, 0.0), &*self.material)
+            .with_uv((x - self.x0)/(self.x1 - self.x0), (z - self.z0)/(self.z1 - self.z0))
+            .with_derivatives(Vec3::from(self.x1 - self.x0, 0.0, 0.0), Vec3::from(0.0, 0.0, self.z1 - self.z0));
 
         Option::Some(record)
     }
@@ -314,7 +334,7 @@ pub struct YzRect {
 }
 
 impl Hitable for YzRect {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.x - ray.origin.x()) / ray.direction.x();
         if t < t_min || t > t_max {
             return Option::None;
@@ -326,14 +346,9 @@ impl Hitable for YzRect {
             return Option::None;
         }
 
-        let record = HitRecord { 
-            u: (y - self.y0)/(self.y1 - self.y0),
-            v: (z - self.z0)/(self.z1 - self.z0),
-            t,
-            material : &*self.material,
-            p : ray.point_at_parameter(t),
-            normal : Vec3::from(1.0, 0.0, 0.0)
-        };
+        let record = HitRecord::build(ray, t, Vec3::from(1.0, 0.0, 0.0), &*self.material)
+            .with_uv((y - self.y0)/(self.y1 - self.y0), (z - self.z0)/(self.z1 - self.z0))
+            .with_derivatives(Vec3::from(0.0, self.y1 - self.y0, 0.0), Vec3::from(0.0, 0.0, self.z1 - self.z0));
 
         Option::Some(record)
     }
@@ -343,6 +358,266 @@ impl Hitable for YzRect {
     }
 }
 
+// Shapes that can hand out points on their surface, for lights that need
+// to be sampled by area.
+pub trait Sampleable {
+    fn area(&self) -> f32;
+    // A point spread uniformly over the surface and the unit normal there.
+    fn sample(&self, rnd : &mut Random) -> (Vec3, Vec3);
+}
+
+// Two unit vectors completing a right handed frame with the unit vector `n`.
+pub(crate) fn orthonormal_basis(n : &Vec3) -> (Vec3, Vec3) {
+    let helper = if n.x().abs() > 0.9 { Vec3::from(0.0, 1.0, 0.0) } else { Vec3::from(1.0, 0.0, 0.0) };
+    let tangent = cross(&helper, n);
+    let tangent = &tangent / tangent.length();
+    (tangent, cross(n, &tangent))
+}
+
+// Bounds of a handful of points, padded so flat shapes aren't zero thickness.
+pub(crate) fn padded_bounds(points : &[Vec3]) -> Aabb {
+    let mut min = points[0];
+    let mut max = points[0];
+    for point in &points[1..] {
+        min = min.min(point);
+        max = max.max(point);
+    }
+    let pad = Vec3::from(0.0001, 0.0001, 0.0001);
+    Aabb::build(min - pad, max + pad)
+}
+
+// Parallelogram spanned by the edges `u` and `v` from `origin`, any way
+// round. uv is (0,0) at the origin and (1,1) at the far corner, the normal
+// is along u × v. Like the rects it's one sided for shading, so pick the
+// edge order to face the normal where it's needed.
+pub struct Quad {
+    origin : Vec3,
+    u : Vec3,
+    v : Vec3,
+    normal : Vec3,
+    // Plane offset along the normal, and the vector used to find the
+    // hit's coordinates along u and v.
+    d : f32,
+    w : Vec3,
+    material : Box<dyn Material>
+}
+
+impl Quad {
+    pub fn build(origin : Vec3, u : Vec3, v : Vec3, material : Box<dyn Material>) -> Box<Quad> {
+        let n = cross(&u, &v);
+        let normal = &n / n.length();
+        let d = dot(&normal, &origin);
+        let w = &n / n.square_length();
+        Box::new(Quad { origin, u, v, normal, d, w, material })
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = dot(&self.normal, &ray.direction);
+        if denominator.abs() < 1e-8 {
+            return Option::None;
+        }
+        let t = (self.d - dot(&self.normal, &ray.origin)) / denominator;
+        if t < t_min || t > t_max {
+            return Option::None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let planar = p - self.origin;
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return Option::None;
+        }
+
+        Option::Some(HitRecord::build(ray, t, self.normal, &*self.material)
+            .with_uv(alpha, beta)
+            .with_derivatives(self.u, self.v))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        padded_bounds(&[self.origin, self.origin + self.u, self.origin + self.v, self.origin + self.u + self.v])
+    }
+}
+
+impl Sampleable for Quad {
+    fn area(&self) -> f32 {
+        cross(&self.u, &self.v).length()
+    }
+
+    fn sample(&self, rnd : &mut Random) -> (Vec3, Vec3) {
+        let (a, b) = (rnd.gen(), rnd.gen());
+        (self.origin + &self.u * a + &self.v * b, self.normal)
+    }
+}
+
+// Flat disk facing along `normal`. u is the angle round the centre as a
+// fraction of a turn, v the distance out as a fraction of the radius.
+pub struct Disk {
+    center : Vec3,
+    normal : Vec3,
+    radius : f32,
+    // Directions of u = 0 and u = 0.25 in the disk's plane.
+    tangent : Vec3,
+    bitangent : Vec3,
+    material : Box<dyn Material>
+}
+
+impl Disk {
+    pub fn build(center : Vec3, normal : Vec3, radius : f32, material : Box<dyn Material>) -> Box<Disk> {
+        let normal = &normal / normal.length();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Box::new(Disk { center, normal, radius, tangent, bitangent, material })
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = dot(&self.normal, &ray.direction);
+        if denominator.abs() < 1e-8 {
+            return Option::None;
+        }
+        let t = dot(&self.normal, &(self.center - ray.origin)) / denominator;
+        if t < t_min || t > t_max {
+            return Option::None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let offset = p - self.center;
+        let (x, y) = (dot(&offset, &self.tangent), dot(&offset, &self.bitangent));
+        let r = (x * x + y * y).sqrt();
+        if r > self.radius {
+            return Option::None;
+        }
+
+        let phi = y.atan2(x);
+        let phi = if phi < 0.0 { phi + 2.0 * f32::consts::PI } else { phi };
+        // At the very centre the angle is arbitrary, take the u = 0 direction.
+        let radial = if r > 0.0 { &offset / r } else { self.tangent };
+        Option::Some(HitRecord::build(ray, t, self.normal, &*self.material)
+            .with_uv(phi / (2.0 * f32::consts::PI), r / self.radius)
+            .with_derivatives(&cross(&self.normal, &offset) * (2.0 * f32::consts::PI), &radial * self.radius))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        // How far the rim reaches along each axis.
+        let reach = |n : f32| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let extent = Vec3::from(reach(*self.normal.x()), reach(*self.normal.y()), reach(*self.normal.z()));
+        padded_bounds(&[self.center - extent, self.center + extent])
+    }
+}
+
+impl Sampleable for Disk {
+    fn area(&self) -> f32 {
+        f32::consts::PI * self.radius * self.radius
+    }
+
+    fn sample(&self, rnd : &mut Random) -> (Vec3, Vec3) {
+        let r = self.radius * rnd.gen().sqrt();
+        let phi = 2.0 * f32::consts::PI * rnd.gen();
+        (self.center + &self.tangent * (r * phi.cos()) + &self.bitangent * (r * phi.sin()), self.normal)
+    }
+}
+
+// Triangle with uv interpolated barycentrically from its corners, by
+// default (0,0), (1,0) and (0,1). The normal is along (p1 - p0) × (p2 - p0).
+pub struct Triangle {
+    points : [Vec3; 3],
+    uvs : [(f32, f32); 3],
+    normal : Vec3,
+    material : Box<dyn Material>
+}
+
+impl Triangle {
+    pub fn build(p0 : Vec3, p1 : Vec3, p2 : Vec3, material : Box<dyn Material>) -> Box<Triangle> {
+        let n = cross(&(p1 - p0), &(p2 - p0));
+        let normal = &n / n.length();
+        Box::new(Triangle { points : [p0, p1, p2], uvs : [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], normal, material })
+    }
+
+    pub fn with_uvs(mut self : Box<Self>, uvs : [(f32, f32); 3]) -> Box<Triangle> {
+        self.uvs = uvs;
+        self
+    }
+
+    fn uv_derivatives(&self) -> (Vec3, Vec3) {
+        triangle_uv_derivatives(&self.points, &self.uvs)
+    }
+}
+
+// How the point moves with u and v over a triangle, from the edges and
+// their uv deltas. Falls back to the raw edges when the uvs don't span it.
+pub(crate) fn triangle_uv_derivatives(points : &[Vec3; 3], uvs : &[(f32, f32); 3]) -> (Vec3, Vec3) {
+    let e1 = points[1] - points[0];
+    let e2 = points[2] - points[0];
+    let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+    let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+    let determinant = du1 * dv2 - dv1 * du2;
+    if determinant.abs() < 1e-12 {
+        return (e1, e2);
+    }
+    let inverse = 1.0 / determinant;
+    (&(&e1 * dv2 - &e2 * dv1) * inverse, &(&e2 * du1 - &e1 * du2) * inverse)
+}
+
+// Möller-Trumbore ray triangle test, the hit's t and the barycentric
+// weights of the second and third corners.
+pub(crate) fn intersect_triangle(ray : &Ray, points : &[Vec3; 3], t_min : f32, t_max : f32) -> Option<(f32, f32, f32)> {
+    let e1 = points[1] - points[0];
+    let e2 = points[2] - points[0];
+    let pvec = cross(&ray.direction, &e2);
+    let determinant = dot(&e1, &pvec);
+    if determinant.abs() < 1e-12 {
+        return Option::None;
+    }
+    let inverse = 1.0 / determinant;
+    let tvec = ray.origin - points[0];
+    let b1 = dot(&tvec, &pvec) * inverse;
+    if !(0.0..=1.0).contains(&b1) {
+        return Option::None;
+    }
+    let qvec = cross(&tvec, &e1);
+    let b2 = dot(&ray.direction, &qvec) * inverse;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return Option::None;
+    }
+    let t = dot(&e2, &qvec) * inverse;
+    if t < t_min || t > t_max {
+        return Option::None;
+    }
+    Option::Some((t, b1, b2))
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, b1, b2) = intersect_triangle(ray, &self.points, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let (dpdu, dpdv) = self.uv_derivatives();
+        Option::Some(HitRecord::build(ray, t, self.normal, &*self.material)
+            .with_uv(b0 * self.uvs[0].0 + b1 * self.uvs[1].0 + b2 * self.uvs[2].0, b0 * self.uvs[0].1 + b1 * self.uvs[1].1 + b2 * self.uvs[2].1)
+            .with_derivatives(dpdu, dpdv))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        padded_bounds(&self.points)
+    }
+}
+
+impl Sampleable for Triangle {
+    fn area(&self) -> f32 {
+        0.5 * cross(&(self.points[1] - self.points[0]), &(self.points[2] - self.points[0])).length()
+    }
+
+    fn sample(&self, rnd : &mut Random) -> (Vec3, Vec3) {
+        // The square root keeps the density uniform over the area.
+        let root = rnd.gen().sqrt();
+        let (b1, b2) = (1.0 - root, rnd.gen() * root);
+        let p = &self.points[0] * (1.0 - b1 - b2) + &self.points[1] * b1 + &self.points[2] * b2;
+        (p, self.normal)
+    }
+}
+
 pub struct FlipNormals {
     pub obj : Box<dyn Hitable>
 }
@@ -354,14 +629,44 @@ impl FlipNormals {
 }
 
 impl Hitable for FlipNormals {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let result = self.obj.hit(ray, t_min, t_max);
-        match result {
-            None => Option::None,
-            Some(record) => {
-                Option::Some(HitRecord {t: record.t, p: record.p, normal: &record.normal * -1.0, material: record.material, u: record.u, v: record.v})
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.obj.hit(ray, t_min, t_max).map(|record| {
+            HitRecord { normal: &record.normal * -1.0, ..record }
+        })
+    }
+
+    fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb {
+        self.obj.bounding_box(time0, time1)
+    }
+}
+
+// Attaches ids to everything hit inside `obj`, for the object and material id passes.
+// A `None` leaves whatever id the child reported in place.
+pub struct Tagged {
+    pub obj : Box<dyn Hitable>,
+    pub object_id : Option<u32>,
+    pub material_id : Option<u32>
+}
+
+impl Tagged {
+    pub fn with_object_id(obj : Box<dyn Hitable>, object_id : u32) -> Box<Tagged> {
+        Box::new(Tagged { obj, object_id : Some(object_id), material_id : None })
+    }
+
+    pub fn with_material_id(obj : Box<dyn Hitable>, material_id : u32) -> Box<Tagged> {
+        Box::new(Tagged { obj, object_id : None, material_id : Some(material_id) })
+    }
+}
+
+impl Hitable for Tagged {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.obj.hit(ray, t_min, t_max).map(|rec| {
+            HitRecord {
+                object_id : self.object_id.unwrap_or(rec.object_id),
+                material_id : self.material_id.unwrap_or(rec.material_id),
+                ..rec
             }
-        }
+        })
     }
 
     fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb {
@@ -369,6 +674,13 @@ impl Hitable for FlipNormals {
     }
 }
 
+pub fn tag_objects(list : Vec<Box<dyn Hitable>>) -> Vec<Box<dyn Hitable>> {
+    list.into_iter()
+        .enumerate()
+        .map(|(index, obj)| Tagged::with_object_id(obj, index as u32 + 1) as Box<dyn Hitable>)
+        .collect()
+}
+
 pub struct BoxShape {
     material : Box<dyn Material>,
     faces : Vec<Box<dyn Hitable>>,
@@ -400,16 +712,38 @@ impl BoxShape {
     }
 }
 
+// Each face of a box gets the whole of [0,1]² with u to the right and v up,
+// as seen from outside. The side faces are upright (v along +y), the top has
+// its bottom edge towards +z and the bottom its bottom edge towards -z,
+// like the unfolded net of a cube. The rects are built with u and v along
+// the axes, so the faces seen from their back get remapped here.
+fn box_face_uv(face : usize, rec : &HitRecord) -> (f32, f32, Vec3, Vec3) {
+    let (u, v, dpdu, dpdv) = (rec.u, rec.v, rec.dpdu, rec.dpdv);
+    match face {
+        // -z, looking towards +z the x axis runs left.
+        1 => (1.0 - u, v, &dpdu * -1.0, dpdv),
+        // +y, rect uv is (x, z).
+        2 => (u, 1.0 - v, dpdu, &dpdv * -1.0),
+        // +x, rect uv is (y, z), right is -z.
+        4 => (1.0 - v, u, &dpdv * -1.0, dpdu),
+        // -x, right is +z.
+        5 => (v, u, dpdv, dpdu),
+        // +z and -y already run the right way.
+        _ => (u, v, dpdu, dpdv)
+    }
+}
+
 impl Hitable for BoxShape {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut least_t = t_max;
         let mut record : Option<HitRecord> = Option::None;
 
-        for obj in &self.faces {
+        for (face, obj) in self.faces.iter().enumerate() {
             let hit  = obj.hit(ray, t_min, least_t);
             if let Some(rec) = hit {
                 least_t = rec.t;
-                record = Option::Some(HitRecord { t: rec.t, p: rec.p, normal: rec.normal, material : &*self.material, u: rec.u, v: rec.v });
+                let (u, v, dpdu, dpdv) = box_face_uv(face, &rec);
+                record = Option::Some(HitRecord { material : &*self.material, u, v, dpdu, dpdv, ..rec });
             }
         }
 
@@ -419,15 +753,29 @@ impl Hitable for BoxShape {
     fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
         Aabb { min: self.pmin, max: self.pmax }
     }
+
+    // Each face is flat, so crosses the ray at most once.
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let mut hits : Vec<HitRecord> = self.faces.iter().enumerate().filter_map(|(face, obj)| {
+            obj.hit(ray, t_min, t_max).map(|rec| {
+                let (u, v, dpdu, dpdv) = box_face_uv(face, &rec);
+                HitRecord { material : &*self.material, u, v, dpdu, dpdv, ..rec }
+            })
+        }).collect();
+        hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+        hits
+    }
 }
 
+// Instancing wrappers keep the child's uv, the mapping belongs to the object
+// and moves with it; only the position and tangents are transformed.
 pub struct Translate {
     pub obj : Box<dyn Hitable>,
     pub offset : Vec3
 }
 
 impl Hitable for Translate {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let moved_ray = Ray { origin: ray.origin - self.offset, direction: ray.direction, time : ray.time };
         let result = self.obj.hit(&moved_ray, t_min, t_max);
         match result {
@@ -435,12 +783,8 @@ impl Hitable for Translate {
             Some(rec) => {
                 Option::Some(
                     HitRecord {
-                        t: rec.t,
                         p : rec.p + self.offset,
-                        normal : rec.normal,
-                        material : rec.material,
-                        u: rec.u,
-                        v: rec.v
+                        ..rec
                     }
                 )
             }
@@ -489,7 +833,7 @@ impl RotateY {
 }
 
 impl Hitable for RotateY {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let rotated_ray = Ray {
             origin: Vec3::from(
                 self.cos_theta * ray.origin.x() - self.sin_theta * ray.origin.z(),
@@ -515,13 +859,16 @@ impl Hitable for RotateY {
                     -self.sin_theta * rec.p.x() + self.cos_theta * rec.p.z()
                 );
 
-                let normal = Vec3::from(
-                    self.cos_theta * rec.normal.x() + self.sin_theta * rec.normal.z(),
-                    *rec.normal.y(),
-                    -self.sin_theta * rec.normal.x() + self.cos_theta * rec.normal.z()
+                let rotate = |d : &Vec3| Vec3::from(
+                    self.cos_theta * d.x() + self.sin_theta * d.z(),
+                    *d.y(),
+                    -self.sin_theta * d.x() + self.cos_theta * d.z()
                 );
+                let normal = rotate(&rec.normal);
+                let dpdu = rotate(&rec.dpdu);
+                let dpdv = rotate(&rec.dpdv);
 
-                Option::Some(HitRecord { t: rec.t, p: point, normal: normal, material: rec.material, u: rec.u, v: rec.v })
+                Option::Some(HitRecord { p: point, normal, dpdu, dpdv, ..rec })
             }
         }
     }
@@ -549,35 +896,27 @@ impl ConstantMedium {
 }
 
 impl Hitable for ConstantMedium {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let option_rec1 = self.boundary.hit(ray, -f32::MAX, f32::MAX);
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // Where the ray enters and leaves the boundary, assumed convex.
+        let crossings = self.boundary.all_hits(ray, -f32::MAX, f32::MAX);
         let mut rng = thread_rng();
         let dist = distributions::Uniform::new(0.0f32, 1.0f32);
         
                 
-        if let Some(rec1) = option_rec1 {
-            let option_rec2 = self.boundary.hit(ray, rec1.t + 0.0001, f32::MAX);
-            if let Some(rec2) = option_rec2 {
-                let mut r1_t = if rec1.t < t_min {t_min} else {rec1.t};
-                let r2_t = if rec2.t > t_max {t_max} else {rec2.t};
-                if r1_t >= r2_t {
-                    return Option::None;
-                }
-                if r1_t < 0.0 { r1_t = 0.0; }
-
-                let distance_inside_boundary = (r2_t - r1_t) * ray.direction.length();
-                let hit_distance = -dist.sample(&mut rng).ln() / self.density;
-                if hit_distance < distance_inside_boundary {
-                    let t = rec1.t + hit_distance / ray.direction.length();
-                    return Option::Some(HitRecord {
-                        t,
-                        p : ray.point_at_parameter(t),
-                        normal : Vec3::from(1.0, 0.0, 0.0), // arbitrary???
-                        material : &*self.phase_function,
-                        u : 0.0,
-                        v : 0.0
-                    });
-                }
+        if let [rec1, rec2, ..] = crossings.as_slice() {
+            let mut r1_t = if rec1.t < t_min {t_min} else {rec1.t};
+            let r2_t = if rec2.t > t_max {t_max} else {rec2.t};
+            if r1_t >= r2_t {
+                return Option::None;
+            }
+            if r1_t < 0.0 { r1_t = 0.0; }
+
+            let distance_inside_boundary = (r2_t - r1_t) * ray.direction.length();
+            let hit_distance = -dist.sample(&mut rng).ln() / self.density;
+            if hit_distance < distance_inside_boundary {
+                let t = rec1.t + hit_distance / ray.direction.length();
+                // The normal is arbitrary, the phase function scatters the same way whatever it is.
+                return Option::Some(HitRecord::build(ray, t, Vec3::from(1.0, 0.0, 0.0), &*self.phase_function));
             }
         } 
 
@@ -587,4 +926,169 @@ impl Hitable for ConstantMedium {
     fn bounding_box(&self, time0 : f32, time1 : f32) -> Aabb {
         self.boundary.bounding_box(time0, time1)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn ray(origin : Vec3, direction : Vec3) -> Ray {
+        Ray { origin, direction, time : 0.0 }
+    }
+
+    fn uv_of(obj : &dyn Hitable, ray : &Ray) -> (f32, f32) {
+        let rec = obj.hit(ray, 0.001, f32::MAX).expect("ray should hit");
+        (rec.u, rec.v)
+    }
+
+    fn assert_uv(actual : (f32, f32), expected : (f32, f32)) {
+        assert!((actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+            "uv {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn sphere_uv_poles_and_seam() {
+        for center in [Vec3::from(0.0, 0.0, 0.0), Vec3::from(2.0, -1.0, 3.0)].iter() {
+            let sphere = Sphere { center : *center, radius : 1.5, material : grey() };
+            // Any u will do at the poles.
+            let (_, v) = uv_of(&sphere, &ray(center + &Vec3::from(0.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0)));
+            assert!((v - 1.0).abs() < 1e-4);
+            let (_, v) = uv_of(&sphere, &ray(center - &Vec3::from(0.0, 5.0, 0.0), Vec3::from(0.0, 1.0, 0.0)));
+            assert!(v.abs() < 1e-4);
+
+            // Round the equator from the -x seam, u increasing towards -z from +x.
+            assert_uv(uv_of(&sphere, &ray(center - &Vec3::from(5.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0))), (0.0, 0.5));
+            assert_uv(uv_of(&sphere, &ray(center + &Vec3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0))), (0.25, 0.5));
+            assert_uv(uv_of(&sphere, &ray(center + &Vec3::from(5.0, 0.0, 0.0), Vec3::from(-1.0, 0.0, 0.0))), (0.5, 0.5));
+            assert_uv(uv_of(&sphere, &ray(center - &Vec3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, 1.0))), (0.75, 0.5));
+        }
+    }
+
+    #[test]
+    fn box_uv_per_face() {
+        let cube = BoxShape::new_from(&Vec3::from(-1.0, -1.0, -1.0), &Vec3::from(1.0, 1.0, 1.0), grey());
+        let x = Vec3::from(1.0, 0.0, 0.0);
+        let y = Vec3::from(0.0, 1.0, 0.0);
+        let z = Vec3::from(0.0, 0.0, 1.0);
+        let minus = |v : &Vec3| v * -1.0;
+        // (outward normal, right, up) as seen from outside, in face order.
+        let faces = [
+            (z, x, y),
+            (minus(&z), minus(&x), y),
+            (y, x, minus(&z)),
+            (minus(&y), x, z),
+            (x, minus(&z), y),
+            (minus(&x), z, y),
+        ];
+        for (normal, right, up) in faces.iter() {
+            // Halfway from the centre of the face towards its top right corner.
+            let target = normal + &(&(right + up) * 0.5);
+            let rec = cube.hit(&ray(target + normal * 5.0, minus(normal)), 0.001, f32::MAX).expect("ray should hit");
+            assert_uv((rec.u, rec.v), (0.75, 0.75));
+            assert!(dot(&rec.normal, normal) > 0.999);
+        }
+    }
+
+    #[test]
+    fn triangle_uv_is_barycentric() {
+        let points = [Vec3::from(0.0, 0.0, 0.0), Vec3::from(2.0, 0.0, 0.0), Vec3::from(0.0, 2.0, 0.0)];
+        let uvs = [(0.2, 0.1), (0.9, 0.3), (0.4, 0.8)];
+        let triangle = Triangle::build(points[0], points[1], points[2], grey()).with_uvs(uvs);
+        let towards = Vec3::from(0.0, 0.0, -1.0);
+        for i in 0..3 {
+            let j = (i + 1) % 3;
+            let corner = points[i] + Vec3::from(0.0, 0.0, 5.0);
+            assert_uv(uv_of(&*triangle, &ray(corner, towards)), uvs[i]);
+            let midpoint = &(points[i] + points[j]) * 0.5 + Vec3::from(0.0, 0.0, 5.0);
+            assert_uv(uv_of(&*triangle, &ray(midpoint, towards)), (0.5 * (uvs[i].0 + uvs[j].0), 0.5 * (uvs[i].1 + uvs[j].1)));
+        }
+        let default = Triangle::build(points[0], points[1], points[2], grey());
+        assert_uv(uv_of(&*default, &ray(Vec3::from(0.5, 0.5, 5.0), towards)), (0.25, 0.25));
+    }
+
+    #[test]
+    fn quad_uv_corners() {
+        let quad = Quad::build(Vec3::from(1.0, 1.0, 0.0), Vec3::from(2.0, 0.0, 0.0), Vec3::from(0.0, 3.0, 0.0), grey());
+        let towards = Vec3::from(0.0, 0.0, -1.0);
+        for (x, y, uv) in [(1.0, 1.0, (0.0, 0.0)), (3.0, 1.0, (1.0, 0.0)), (1.0, 4.0, (0.0, 1.0)), (3.0, 4.0, (1.0, 1.0))].iter() {
+            assert_uv(uv_of(&*quad, &ray(Vec3::from(*x, *y, 5.0), towards)), *uv);
+        }
+    }
+
+    #[test]
+    fn tilted_quads_hit_between_their_edges() {
+        // Leaning back at 45 degrees, facing up and towards +z.
+        let quad = Quad::build(Vec3::from(0.0, 0.0, 0.0), Vec3::from(2.0, 0.0, 0.0), Vec3::from(0.0, 1.0, -1.0), grey());
+        let down = Vec3::from(0.0, -1.0, 0.0);
+        let incoming = Ray { origin : Vec3::from(0.5, 5.0, -0.5), direction : down, time : 0.25 };
+        let rec = quad.hit(&incoming, 0.001, f32::MAX).expect("ray should hit");
+        assert!((rec.t - 4.5).abs() < 1e-4);
+        assert_uv((rec.u, rec.v), (0.25, 0.5));
+        let half = 0.5f32.sqrt();
+        assert!((rec.normal - Vec3::from(0.0, half, half)).length() < 1e-4);
+        assert!((rec.dpdv - Vec3::from(0.0, 1.0, -1.0)).length() < 1e-4);
+        // Everything else is left at the defaults.
+        assert_eq!((rec.object_id, rec.material_id, rec.footprint, rec.time), (0, 0, 0.0, 0.25));
+        assert!((rec.object_p - rec.p).length() == 0.0 && (rec.colour - Vec3::from(1.0, 1.0, 1.0)).length() == 0.0);
+        assert!(quad.hit(&ray(Vec3::from(2.5, 5.0, -0.5), down), 0.001, f32::MAX).is_none());
+        assert!(quad.hit(&ray(Vec3::from(0.5, 5.0, 0.5), down), 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn disks_hit_within_their_radius() {
+        let disk = Disk::build(Vec3::from(1.0, 2.0, 0.0), Vec3::from(0.0, 0.0, 3.0), 2.0, grey());
+        let towards = Vec3::from(0.0, 0.0, -1.0);
+        let above = |offset : Vec3| ray(disk.center + offset + Vec3::from(0.0, 0.0, 5.0), towards);
+        assert_uv(uv_of(&*disk, &above(disk.tangent)), (0.0, 0.5));
+        assert_uv(uv_of(&*disk, &above(&disk.bitangent * 1.5)), (0.25, 0.75));
+        assert_uv(uv_of(&*disk, &above(&disk.tangent * -2.0)), (0.5, 1.0));
+        assert!(disk.hit(&above(&disk.bitangent * 2.1), 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn samples_spread_over_the_surface() {
+        let mut rnd = Random::create_with_seed(7);
+        let quad = Quad::build(Vec3::from(0.0, 0.0, 0.0), Vec3::from(2.0, 0.0, 0.0), Vec3::from(0.0, 1.0, -1.0), grey());
+        let disk = Disk::build(Vec3::from(1.0, 2.0, 0.0), Vec3::from(0.0, 1.0, 1.0), 2.0, grey());
+        let triangle = Triangle::build(Vec3::from(0.0, 0.0, 0.0), Vec3::from(3.0, 0.0, 0.0), Vec3::from(0.0, 3.0, 3.0), grey());
+        let shapes : [(&dyn Sampleable, &dyn Hitable, f32, Vec3); 3] = [
+            (&*quad, &*quad, 2.0 * 2.0f32.sqrt(), Vec3::from(1.0, 0.5, -0.5)),
+            (&*disk, &*disk, 4.0 * f32::consts::PI, Vec3::from(1.0, 2.0, 0.0)),
+            (&*triangle, &*triangle, 4.5 * 2.0f32.sqrt(), Vec3::from(1.0, 1.0, 1.0))
+        ];
+        for (sampleable, hitable, area, centroid) in shapes.iter() {
+            assert!((sampleable.area() - area).abs() < 1e-4);
+            let count = 4000;
+            let mut sum = Vec3::default();
+            for _ in 0..count {
+                // Every sample lies on the shape, seen from the side it faces.
+                let (point, normal) = sampleable.sample(&mut rnd);
+                let rec = hitable.hit(&ray(point + &normal * 0.5, &normal * -1.0), 0.001, f32::MAX).expect("sample should be on the shape");
+                assert!((rec.t - 0.5).abs() < 1e-3);
+                sum = sum + point;
+            }
+            assert!((&sum / count as f32 - *centroid).length() < 0.05);
+        }
+    }
+
+    #[test]
+    fn instancing_keeps_uv() {
+        let offset = Vec3::from(3.0, 1.0, -2.0);
+        let moved = Translate { obj : Box::new(Sphere { center : Vec3::from(0.0, 0.0, 0.0), radius : 1.0, material : grey() }), offset };
+        assert_uv(uv_of(&moved, &ray(offset - Vec3::from(5.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0))), (0.0, 0.5));
+        assert_uv(uv_of(&moved, &ray(offset + Vec3::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0))), (0.25, 0.5));
+
+        // A quarter turn brings the box's +z face round to +x.
+        let cube = BoxShape::new_from(&Vec3::from(-1.0, -1.0, -1.0), &Vec3::from(1.0, 1.0, 1.0), grey());
+        let turned = RotateY::create_new(cube, 90.0);
+        let world = ray(Vec3::from(5.0, 0.5, 0.25), Vec3::from(-1.0, 0.0, 0.0));
+        assert_uv(uv_of(&*turned, &world), (0.375, 0.75));
+
+        let both = Translate { obj : RotateY::create_new(BoxShape::new_from(&Vec3::from(-1.0, -1.0, -1.0), &Vec3::from(1.0, 1.0, 1.0), grey()), 90.0), offset };
+        assert_uv(uv_of(&both, &ray(world.origin + offset, world.direction)), (0.375, 0.75));
+    }
+}
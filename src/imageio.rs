@@ -0,0 +1,816 @@
+use crate::inflate;
+use crate::vec3::Vec3;
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+// Pixels are stored top row first, left to right, which is the order the
+// renderer produces them in.
+
+// Plain text PPM, values expected in [0,1] and already display encoded.
+pub fn write_ppm<W : Write>(out : &mut W, nx : usize, ny : usize, pixels : &[Vec3]) -> io::Result<()> {
+    write!(out, "P3\n{} {}\n255\n", nx, ny)?;
+    for col in pixels.iter() {
+        let c = col.clamp(0.0, 1.0);
+        let ir = (255.99 * c.r()) as i32;
+        let ig = (255.99 * c.g()) as i32;
+        let ib = (255.99 * c.b()) as i32;
+        writeln!(out, "{} {} {}", ir, ig, ib)?;
+    }
+    Ok(())
+}
+
+// Little endian colour PFM. PFM stores its rows bottom to top.
+pub fn write_pfm<W : Write>(out : &mut W, nx : usize, ny : usize, pixels : &[Vec3]) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", nx, ny)?;
+    let mut bytes : Vec<u8> = Vec::with_capacity(nx * ny * 12);
+    for row in pixels.chunks(nx).rev() {
+        for col in row {
+            bytes.extend_from_slice(&col.r().to_le_bytes());
+            bytes.extend_from_slice(&col.g().to_le_bytes());
+            bytes.extend_from_slice(&col.b().to_le_bytes());
+        }
+    }
+    out.write_all(&bytes)
+}
+
+pub fn write_pfm_mono<W : Write>(out : &mut W, nx : usize, ny : usize, values : &[f32]) -> io::Result<()> {
+    write!(out, "Pf\n{} {}\n-1.0\n", nx, ny)?;
+    let mut bytes : Vec<u8> = Vec::with_capacity(nx * ny * 4);
+    for row in values.chunks(nx).rev() {
+        for value in row {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    out.write_all(&bytes)
+}
+
+// Float image as read from disk, top row first with interleaved channels.
+pub struct FloatImage {
+    pub nx : usize,
+    pub ny : usize,
+    pub channels : usize,
+    pub data : Vec<f32>,
+}
+
+impl FloatImage {
+    // Single channel images are expanded to grey.
+    pub fn to_colours(&self) -> Vec<Vec3> {
+        self.data
+            .chunks(self.channels)
+            .map(|c| if c.len() >= 3 { Vec3::from(c[0], c[1], c[2]) } else { Vec3::from(c[0], c[0], c[0]) })
+            .collect()
+    }
+
+    // Multi channel images keep their first channel.
+    pub fn to_scalars(&self) -> Vec<f32> {
+        self.data.chunks(self.channels).map(|c| c[0]).collect()
+    }
+}
+
+pub fn read_pfm(path : &str) -> io::Result<FloatImage> {
+    let bytes = std::fs::read(path)?;
+    let invalid = |msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+    // Three whitespace separated header tokens after the magic, then exactly
+    // one whitespace byte before the raster.
+    let mut tokens : Vec<String> = Vec::with_capacity(4);
+    let mut position = 0;
+    while tokens.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("truncated header"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    position += 1;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+    let nx : usize = tokens[1].parse().map_err(|_| invalid("bad width"))?;
+    let ny : usize = tokens[2].parse().map_err(|_| invalid("bad height"))?;
+    let scale : f32 = tokens[3].parse().map_err(|_| invalid("bad scale"))?;
+    let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(channels)).ok_or_else(|| invalid("image too large"))?;
+    let raster = count.checked_mul(4).and_then(|size| size.checked_add(position))
+        .and_then(|end| bytes.get(position..end))
+        .ok_or_else(|| invalid("truncated raster"))?;
+
+    let mut data = vec![0.0f32; count];
+    let row_len = nx * channels;
+    for (index, chunk) in raster.chunks(4).enumerate() {
+        let raw = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        let value = if scale < 0.0 { f32::from_le_bytes(raw) } else { f32::from_be_bytes(raw) };
+        // flip back to top row first
+        let (row, column) = (index / row_len, index % row_len);
+        data[(ny - 1 - row) * row_len + column] = value;
+    }
+
+    Ok(FloatImage { nx, ny, channels, data })
+}
+
+// Single part, scanline, uncompressed OpenEXR with 32 bit float channels.
+// Each channel is a (name, values) pair with values top row first.
+pub fn write_exr<W : Write>(out : &mut W, nx : usize, ny : usize, channels : &[(String, Vec<f32>)]) -> io::Result<()> {
+    // EXR wants its channel list sorted by name, and the pixel data in the same order.
+    let mut sorted : Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header : Vec<u8> = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+    let mut chlist : Vec<u8> = Vec::new();
+    for (name, _) in sorted.iter() {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+        chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &chlist);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    let mut window : Vec<u8> = Vec::new();
+    for value in [0, 0, nx as i32 - 1, ny as i32 - 1].iter() {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // One scanline per block: y, byte count, then each channel's row in turn.
+    let block_size = 8 + 4 * nx * sorted.len();
+    let table_start = header.len() + 8 * ny;
+    for y in 0..ny {
+        header.extend_from_slice(&((table_start + y * block_size) as u64).to_le_bytes());
+    }
+    out.write_all(&header)?;
+
+    let mut block : Vec<u8> = Vec::with_capacity(block_size);
+    for y in 0..ny {
+        block.clear();
+        block.extend_from_slice(&(y as i32).to_le_bytes());
+        block.extend_from_slice(&((block_size - 8) as i32).to_le_bytes());
+        for (_, values) in sorted.iter() {
+            for value in &values[y * nx..(y + 1) * nx] {
+                block.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        out.write_all(&block)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header : &mut Vec<u8>, name : &str, kind : &str, value : &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// How the values in an image file were stored. Integer images are scaled to
+// [0,1] on load and usually carry a transfer curve, float ones are linear.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleType {
+    Integer,
+    Float,
+}
+
+// Reads an image for use as a texture, picking the reader from the file
+// extension: PFM and EXR (float), PNM and 16 bit PNG (integer, up to 16
+// bits), anything else through stb_image. Channels stay as stored: 1 grey,
+// 2 grey and alpha, 3 RGB or 4 RGBA.
+pub fn read_image(path : &str) -> io::Result<(FloatImage, SampleType)> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "pfm" => read_pfm(path).map(|image| (image, SampleType::Float)),
+        "exr" => read_exr(path).map(|image| (image, SampleType::Float)),
+        "pnm" | "ppm" | "pgm" => read_pnm(path).map(|image| (image, SampleType::Integer)),
+        "png" if png_bit_depth(path)? == 16 => read_png16(path).map(|image| (image, SampleType::Integer)),
+        _ => read_stb(path),
+    }
+}
+
+// The file is read here rather than by stb so a missing or unreadable file
+// comes back as the same io error as for the other formats.
+fn read_stb(path : &str) -> io::Result<(FloatImage, SampleType)> {
+    let bytes = std::fs::read(path)?;
+    match stb_image::image::load_from_memory(&bytes) {
+        stb_image::image::LoadResult::ImageU8(img) => Ok((FloatImage {
+            nx : img.width,
+            ny : img.height,
+            channels : img.depth,
+            data : img.data.iter().map(|&v| f32::from(v) / 255.0).collect(),
+        }, SampleType::Integer)),
+        stb_image::image::LoadResult::ImageF32(img) => Ok((FloatImage {
+            nx : img.width,
+            ny : img.height,
+            channels : img.depth,
+            data : img.data,
+        }, SampleType::Float)),
+        stb_image::image::LoadResult::Error(msg) => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg)))
+        }
+    }
+}
+
+// Binary PGM (P5) or PPM (P6), 8 or 16 bits per sample.
+pub fn read_pnm(path : &str) -> io::Result<FloatImage> {
+    let bytes = std::fs::read(path)?;
+    let invalid = |msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+    let mut tokens : Vec<String> = Vec::with_capacity(4);
+    let mut position = 0;
+    while tokens.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if position < bytes.len() && bytes[position] == b'#' {
+            while position < bytes.len() && bytes[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("truncated header"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    position += 1;
+
+    let channels = match tokens[0].as_str() {
+        "P6" => 3,
+        "P5" => 1,
+        _ => return Err(invalid("only binary PGM and PPM are supported")),
+    };
+    let nx : usize = tokens[1].parse().map_err(|_| invalid("bad width"))?;
+    let ny : usize = tokens[2].parse().map_err(|_| invalid("bad height"))?;
+    let max_value : u32 = tokens[3].parse().map_err(|_| invalid("bad maximum value"))?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("bad maximum value"));
+    }
+    let sample_size = if max_value > 255 { 2 } else { 1 };
+    let raster = nx.checked_mul(ny)
+        .and_then(|n| n.checked_mul(channels * sample_size))
+        .and_then(|size| size.checked_add(position))
+        .and_then(|end| bytes.get(position..end))
+        .ok_or_else(|| invalid("truncated raster"))?;
+
+    let scale = 1.0 / max_value as f32;
+    let data = if sample_size == 2 {
+        raster.chunks(2).map(|s| f32::from(u16::from_be_bytes([s[0], s[1]])) * scale).collect()
+    } else {
+        raster.iter().map(|&s| f32::from(s) * scale).collect()
+    };
+    Ok(FloatImage { nx, ny, channels, data })
+}
+
+const PNG_SIGNATURE : [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+fn png_bit_depth(path : &str) -> io::Result<u8> {
+    let mut header = Vec::with_capacity(25);
+    std::io::Read::read_to_end(&mut std::io::Read::take(std::fs::File::open(path)?, 25), &mut header)?;
+    if header.len() < 25 || header[..8] != PNG_SIGNATURE || &header[12..16] != b"IHDR" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: not a PNG file", path)));
+    }
+    Ok(header[24])
+}
+
+// 16 bit PNGs, which stb_image can't read. Non-interlaced grey, grey and
+// alpha, RGB and RGBA only.
+pub fn read_png16(path : &str) -> io::Result<FloatImage> {
+    let bytes = std::fs::read(path)?;
+    let invalid = |msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+    if bytes.len() < 8 || bytes[..8] != PNG_SIGNATURE {
+        return Err(invalid("not a PNG file"));
+    }
+
+    let mut position = 8;
+    let mut header : Option<&[u8]> = None;
+    let mut compressed : Vec<u8> = Vec::new();
+    while position + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([bytes[position], bytes[position + 1], bytes[position + 2], bytes[position + 3]]) as usize;
+        let kind = &bytes[position + 4..position + 8];
+        let data = bytes.get(position + 8..position + 8 + length).ok_or_else(|| invalid("truncated chunk"))?;
+        match kind {
+            b"IHDR" => header = Some(data),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        position += 12 + length;
+    }
+
+    let header = header.filter(|h| h.len() >= 13).ok_or_else(|| invalid("missing header"))?;
+    let nx = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let ny = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let channels = match header[9] {
+        0 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(invalid("unsupported 16 bit colour type")),
+    };
+    if header[8] != 16 || header[12] != 0 {
+        return Err(invalid("only non-interlaced 16 bit images are read here"));
+    }
+
+    let raw = inflate::zlib_decompress(&compressed).map_err(|e| invalid(&e.to_string()))?;
+    let pixel_size = channels * 2;
+    let stride = nx * pixel_size;
+    if raw.len() < ny * (stride + 1) {
+        return Err(invalid("truncated image data"));
+    }
+
+    // Undo the per row filters, each row is a filter type byte then the row.
+    let mut rows = vec![0u8; ny * stride];
+    for y in 0..ny {
+        let filter = raw[y * (stride + 1)];
+        let source = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, current) = rows.split_at_mut(y * stride);
+        let previous = if y > 0 { &done[(y - 1) * stride..] } else { &[][..] };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= pixel_size { i32::from(current[x - pixel_size]) } else { 0 };
+            let b = if y > 0 { i32::from(previous[x]) } else { 0 };
+            let c = if y > 0 && x >= pixel_size { i32::from(previous[x - pixel_size]) } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                4 => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+                },
+                _ => return Err(invalid("bad row filter")),
+            };
+            current[x] = (i32::from(source[x]) + predictor) as u8;
+        }
+    }
+
+    let data = rows.chunks(2).map(|s| f32::from(u16::from_be_bytes([s[0], s[1]])) / 65535.0).collect();
+    Ok(FloatImage { nx, ny, channels, data })
+}
+
+// Single part scanline OpenEXR, uncompressed or ZIP compressed, with half,
+// float or uint channels. R, G, B and A are picked out by name (with or
+// without a layer prefix), a lone Y channel gives a grey image.
+pub fn read_exr(path : &str) -> io::Result<FloatImage> {
+    let bytes = std::fs::read(path)?;
+    let invalid = |msg : &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+    // Every size and offset comes from the file, so all the arithmetic on
+    // them is checked and anything out of range is just a bad file.
+    let read_i32 = |at : usize| -> io::Result<i32> {
+        at.checked_add(4).and_then(|end| bytes.get(at..end))
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("truncated file"))
+    };
+    let read_size = |at : usize| -> io::Result<usize> {
+        usize::try_from(read_i32(at)?).map_err(|_| invalid("negative size"))
+    };
+    let slice = |at : usize, size : usize| -> io::Result<&[u8]> {
+        at.checked_add(size).and_then(|end| bytes.get(at..end)).ok_or_else(|| invalid("truncated file"))
+    };
+
+    if bytes.len() < 8 || bytes[..4] != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(invalid("not an EXR file"));
+    }
+    if bytes[5] & 0x1e != 0 {
+        return Err(invalid("only single part scanline images are supported"));
+    }
+
+    // Attributes are name, type, size and value, up to an empty name.
+    let mut position = 8;
+    let read_string = |at : &mut usize| -> io::Result<String> {
+        let end = bytes[*at..].iter().position(|&b| b == 0).ok_or_else(|| invalid("truncated header"))?;
+        let s = String::from_utf8_lossy(&bytes[*at..*at + end]).into_owned();
+        *at += end + 1;
+        Ok(s)
+    };
+    let mut channel_list : Vec<(String, i32)> = Vec::new();
+    let mut compression = 0u8;
+    let mut window = [0i32; 4];
+    loop {
+        let name = read_string(&mut position)?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = read_string(&mut position)?;
+        let size = read_size(position)?;
+        position += 4;
+        let value = slice(position, size)?;
+        match name.as_str() {
+            "channels" => {
+                let mut at = 0;
+                while at < value.len() && value[at] != 0 {
+                    let end = value[at..].iter().position(|&b| b == 0).ok_or_else(|| invalid("bad channel list"))?;
+                    let channel = String::from_utf8_lossy(&value[at..at + end]).into_owned();
+                    at += end + 1;
+                    let kind = value.get(at..at + 4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| invalid("bad channel list"))?;
+                    let sampling = value.get(at + 8..at + 16).ok_or_else(|| invalid("bad channel list"))?;
+                    if sampling != [1, 0, 0, 0, 1, 0, 0, 0] {
+                        return Err(invalid("subsampled channels aren't supported"));
+                    }
+                    channel_list.push((channel, kind));
+                    at += 16;
+                }
+            },
+            "compression" => compression = *value.first().ok_or_else(|| invalid("bad compression"))?,
+            "dataWindow" => {
+                for (i, w) in window.iter_mut().enumerate() {
+                    *w = read_i32(position + 4 * i)?;
+                }
+            },
+            _ => {}
+        }
+        position += size;
+    }
+
+    let lines_per_block = match compression {
+        0 | 2 => 1,
+        3 => 16,
+        _ => return Err(invalid("only uncompressed and ZIP compressed images are supported")),
+    };
+    // Window corners are inclusive, a backwards window is empty.
+    let extent = |low : i32, high : i32| usize::try_from(i64::from(high) - i64::from(low) + 1).unwrap_or(0);
+    let nx = extent(window[0], window[2]);
+    let ny = extent(window[1], window[3]);
+    if nx == 0 || ny == 0 || channel_list.is_empty() {
+        return Err(invalid("empty image"));
+    }
+    channel_list.sort_by(|a, b| a.0.cmp(&b.0));
+    let sample_sizes : Vec<usize> = channel_list.iter().map(|&(_, kind)| if kind == 1 { 2 } else { 4 }).collect();
+    // Catch a corrupt window before allocating for it: the pixels have to
+    // fit in the file, allowing for DEFLATE's best ratio of about 1032 to 1.
+    // Once that holds none of the sizes below can overflow.
+    let ratio = if compression == 3 { 1032 } else { 1 };
+    let line_size = sample_sizes.iter().sum::<usize>().checked_mul(nx);
+    match line_size.and_then(|size| size.checked_mul(ny)) {
+        Some(size) if size / ratio <= bytes.len() => {},
+        _ => return Err(invalid("image larger than the file holding it")),
+    }
+    let line_size = line_size.unwrap_or(0);
+
+    // Stored channel values, channel by channel, top row first.
+    let mut planes = vec![vec![0.0f32; nx * ny]; channel_list.len()];
+    let block_count = ny.div_ceil(lines_per_block);
+    for block in 0..block_count {
+        let offset = slice(position + 8 * block, 8)
+            .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+            .map_err(|_| invalid("truncated offset table"))?;
+        let offset = usize::try_from(offset).map_err(|_| invalid("bad offset table"))?;
+        // Blocks for lines above the window start at its top.
+        let first_line = usize::try_from(i64::from(read_i32(offset)?) - i64::from(window[1])).unwrap_or(0);
+        let size = read_size(offset.saturating_add(4))?;
+        let data = slice(offset.saturating_add(8), size).map_err(|_| invalid("truncated block"))?;
+        let lines = lines_per_block.min(ny.saturating_sub(first_line));
+        let expected = lines * line_size;
+
+        let unpacked;
+        let data = if compression == 0 || size >= expected {
+            data
+        } else {
+            unpacked = exr_unzip(data, expected).map_err(|e| invalid(&e.to_string()))?;
+            &unpacked[..]
+        };
+        if data.len() < expected {
+            return Err(invalid("short block"));
+        }
+
+        let mut at = 0;
+        for line in 0..lines {
+            let y = first_line + line;
+            for (channel, &(_, kind)) in channel_list.iter().enumerate() {
+                for x in 0..nx {
+                    let b = &data[at..at + sample_sizes[channel]];
+                    planes[channel][y * nx + x] = match kind {
+                        0 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+                        1 => half_to_f32(u16::from_le_bytes([b[0], b[1]])),
+                        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                    };
+                    at += sample_sizes[channel];
+                }
+            }
+        }
+    }
+
+    let find = |wanted : &str| {
+        let suffix = format!(".{}", wanted);
+        channel_list.iter().position(|(name, _)| name == wanted)
+            .or_else(|| channel_list.iter().position(|(name, _)| name.ends_with(&suffix)))
+    };
+    let selected : Vec<usize> = match (find("R"), find("G"), find("B"), find("A"), find("Y")) {
+        (Some(r), Some(g), Some(b), Some(a), _) => vec![r, g, b, a],
+        (Some(r), Some(g), Some(b), None, _) => vec![r, g, b],
+        (_, _, _, Some(a), Some(y)) => vec![y, a],
+        (_, _, _, None, Some(y)) => vec![y],
+        _ => vec![0],
+    };
+    let planes = &planes;
+    let data = (0..nx * ny)
+        .flat_map(|index| selected.iter().map(move |&channel| planes[channel][index]))
+        .collect();
+    Ok(FloatImage { nx, ny, channels : selected.len(), data })
+}
+
+// EXR's ZIP blocks are zlib data whose bytes went through a delta predictor
+// and were split into even and odd halves before compression.
+fn exr_unzip(data : &[u8], expected : usize) -> io::Result<Vec<u8>> {
+    let mut raw = inflate::zlib_decompress(data)?;
+    raw.truncate(expected);
+    for i in 1..raw.len() {
+        raw[i] = raw[i - 1].wrapping_add(raw[i]).wrapping_sub(128);
+    }
+    let half = raw.len().div_ceil(2);
+    let mut out = Vec::with_capacity(raw.len());
+    for i in 0..half {
+        out.push(raw[i]);
+        if half + i < raw.len() {
+            out.push(raw[half + i]);
+        }
+    }
+    Ok(out)
+}
+
+pub fn half_to_f32(h : u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((h >> 10) & 0x1f);
+    let mantissa = f32::from(h & 0x3ff);
+    match exponent {
+        0 => sign * mantissa * (-24.0f32).exp2(),
+        31 => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * ((exponent - 15) as f32).exp2(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflate::tests::{zlib_fixed, zlib_stored};
+
+    type Compress = fn(&[u8]) -> Vec<u8>;
+
+    // The readers take paths, so the test images go through a file named
+    // after the test and the process.
+    fn temp_file(name : &str, bytes : &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("raytracer-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn png_chunk(png : &mut Vec<u8>, kind : &[u8], data : &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        // The reader doesn't check the CRC.
+        png.extend_from_slice(&[0, 0, 0, 0]);
+    }
+
+    // A 16 bit PNG whose row y uses filter type y % 5.
+    fn png16(nx : usize, ny : usize, colour_type : u8, channels : usize, values : &[u16], compress : Compress) -> Vec<u8> {
+        let pixel_size = channels * 2;
+        let stride = nx * pixel_size;
+        let rows : Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+        let mut filtered = Vec::new();
+        for y in 0..ny {
+            let filter = (y % 5) as u8;
+            filtered.push(filter);
+            for x in 0..stride {
+                let a = if x >= pixel_size { i32::from(rows[y * stride + x - pixel_size]) } else { 0 };
+                let b = if y > 0 { i32::from(rows[(y - 1) * stride + x]) } else { 0 };
+                let c = if y > 0 && x >= pixel_size { i32::from(rows[(y - 1) * stride + x - pixel_size]) } else { 0 };
+                let predictor = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => (a + b) / 2,
+                    _ => {
+                        let p = a + b - c;
+                        let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                        if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+                    },
+                };
+                filtered.push((i32::from(rows[y * stride + x]) - predictor) as u8);
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(nx as u32).to_be_bytes());
+        header.extend_from_slice(&(ny as u32).to_be_bytes());
+        header.extend_from_slice(&[16, colour_type, 0, 0, 0]);
+        let mut png = PNG_SIGNATURE.to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        // Split the data over two IDAT chunks, the reader has to join them.
+        let compressed = compress(&filtered);
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        png_chunk(&mut png, b"IDAT", first);
+        png_chunk(&mut png, b"IDAT", second);
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    // Values that make every filter's predictor matter, with carries across
+    // the byte boundary.
+    fn png_values(count : usize) -> Vec<u16> {
+        (0..count as u32).map(|i| (i.wrapping_mul(40503) ^ (i << 7)) as u16).collect()
+    }
+
+    #[test]
+    fn png16_filters() {
+        for &(colour_type, channels) in [(0u8, 1usize), (2, 3), (4, 2), (6, 4)].iter() {
+            let (nx, ny) = (7, 10);
+            let values = png_values(nx * ny * channels);
+            let compressors : [Compress; 2] = [zlib_stored, zlib_fixed];
+            for (i, compress) in compressors.iter().enumerate() {
+                let path = temp_file(&format!("filters-{}-{}.png", colour_type, i), &png16(nx, ny, colour_type, channels, &values, *compress));
+                let image = read_png16(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                assert_eq!((image.nx, image.ny, image.channels), (nx, ny, channels));
+                for (actual, expected) in image.data.iter().zip(values.iter()) {
+                    assert_eq!(*actual, f32::from(*expected) / 65535.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn png16_bad_input_is_an_error() {
+        let png = png16(6, 5, 2, 3, &png_values(6 * 5 * 3), zlib_fixed);
+        // Everything up to the last IDAT's CRC is needed.
+        for length in 0..png.len() - 16 {
+            let path = temp_file("truncated.png", &png[..length]);
+            assert!(read_png16(&path).is_err(), "{} of {} bytes", length, png.len());
+        }
+        for position in 0..png.len() {
+            let mut corrupt = png.clone();
+            corrupt[position] ^= 0xff;
+            let path = temp_file("corrupt.png", &corrupt);
+            let _ = read_png16(&path);
+        }
+        std::fs::remove_file(temp_file("corrupt.png", &[])).unwrap();
+        std::fs::remove_file(temp_file("truncated.png", &[])).unwrap();
+        assert!(matches!(read_png16("no-such-file.png"), Err(e) if e.kind() == io::ErrorKind::NotFound));
+    }
+
+    // Flat patches so the ZIP blocks come out smaller than the raw data.
+    fn exr_channels(nx : usize, ny : usize) -> Vec<(String, Vec<f32>)> {
+        ["R", "G", "B"].iter().enumerate().map(|(c, name)| {
+            let values = (0..nx * ny).map(|i| ((i % nx / 4 + i / nx / 3 + c) % 5) as f32 * 0.25 - 0.5).collect();
+            (name.to_string(), values)
+        }).collect()
+    }
+
+    fn check_exr(image : &FloatImage, nx : usize, ny : usize, channels : &[(String, Vec<f32>)]) {
+        assert_eq!((image.nx, image.ny, image.channels), (nx, ny, 3));
+        for (index, pixel) in image.data.chunks(3).enumerate() {
+            for (c, value) in pixel.iter().enumerate() {
+                assert_eq!(*value, channels[c].1[index]);
+            }
+        }
+    }
+
+    // Rewrites `write_exr`'s output with ZIP compression, 16 lines a block.
+    fn zip_exr(exr : &[u8], nx : usize, ny : usize, channel_count : usize) -> Vec<u8> {
+        let compression = exr.windows(24).position(|w| w == b"compression\0compression\0").unwrap() + 28;
+        let last = b"screenWindowWidth\0float\0";
+        let header_end = exr.windows(last.len()).position(|w| w == last).unwrap() + last.len() + 9;
+        let mut header = exr[..header_end].to_vec();
+        header[compression] = 3;
+
+        let line_size = 4 * nx * channel_count;
+        let lines = |y : usize| &exr[header_end + 8 * ny + y * (line_size + 8) + 8..][..line_size];
+        let mut blocks = Vec::new();
+        for first in (0..ny).step_by(16) {
+            let raw : Vec<u8> = (first..ny.min(first + 16)).flat_map(|y| lines(y).to_vec()).collect();
+            let mut split : Vec<u8> = raw.iter().step_by(2).cloned().collect();
+            split.extend(raw.iter().skip(1).step_by(2));
+            let mut predicted = split.clone();
+            for i in 1..split.len() {
+                predicted[i] = split[i].wrapping_sub(split[i - 1]).wrapping_add(128);
+            }
+            let compressed = zlib_fixed(&predicted);
+            assert!(compressed.len() < raw.len());
+            let mut block = (first as i32).to_le_bytes().to_vec();
+            block.extend_from_slice(&(compressed.len() as i32).to_le_bytes());
+            block.extend(compressed);
+            blocks.push(block);
+        }
+
+        let mut offset = header.len() + 8 * blocks.len();
+        for block in blocks.iter() {
+            header.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += block.len();
+        }
+        header.extend(blocks.concat());
+        header
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let (nx, ny) = (24, 37);
+        let channels = exr_channels(nx, ny);
+        let mut exr = Vec::new();
+        write_exr(&mut exr, nx, ny, &channels).unwrap();
+
+        let path = temp_file("uncompressed.exr", &exr);
+        let image = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        check_exr(&image.unwrap(), nx, ny, &channels);
+
+        let path = temp_file("zip.exr", &zip_exr(&exr, nx, ny, channels.len()));
+        let image = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        check_exr(&image.unwrap(), nx, ny, &channels);
+    }
+
+    #[test]
+    fn exr_bad_input_is_an_error() {
+        let (nx, ny) = (8, 18);
+        let channels = exr_channels(nx, ny);
+        let mut exr = Vec::new();
+        write_exr(&mut exr, nx, ny, &channels).unwrap();
+        let zipped = zip_exr(&exr, nx, ny, channels.len());
+        for file in [exr, zipped].iter() {
+            for length in 0..file.len() {
+                let path = temp_file("truncated.exr", &file[..length]);
+                assert!(read_exr(&path).is_err(), "{} of {} bytes", length, file.len());
+            }
+            for position in 0..file.len() {
+                let mut corrupt = file.clone();
+                corrupt[position] ^= 0xff;
+                let path = temp_file("corrupt.exr", &corrupt);
+                let _ = read_exr(&path);
+            }
+        }
+        std::fs::remove_file(temp_file("corrupt.exr", &[])).unwrap();
+        std::fs::remove_file(temp_file("truncated.exr", &[])).unwrap();
+    }
+
+    fn read_patched<F : Fn(&str) -> io::Result<FloatImage>>(read : F, name : &str, bytes : &[u8]) -> io::ErrorKind {
+        let path = temp_file(name, bytes);
+        let result = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        result.err().expect("out of range header should be an error").kind()
+    }
+
+    #[test]
+    fn headers_out_of_range_are_invalid_data() {
+        let huge = b"PF\n4294967296 4294967296\n-1.0\n";
+        assert_eq!(read_patched(read_pfm, "huge.pfm", huge), io::ErrorKind::InvalidData);
+        let huge = b"P6\n18446744073709551615 3\n65535\n";
+        assert_eq!(read_patched(read_pnm, "huge.ppm", huge), io::ErrorKind::InvalidData);
+
+        let (nx, ny) = (4, 3);
+        let mut exr = Vec::new();
+        write_exr(&mut exr, nx, ny, &exr_channels(nx, ny)).unwrap();
+        let field = |name : &[u8]| exr.windows(name.len()).position(|w| w == name).unwrap() + name.len() + 4;
+        let window = field(b"dataWindow\0box2i\0");
+        let patch = |at : usize, value : &[u8]| {
+            let mut patched = exr.clone();
+            patched[at..at + value.len()].copy_from_slice(value);
+            patched
+        };
+        // The widest window there is, and one whose top is as far down as it goes.
+        let widest = [i32::MIN.to_le_bytes(), i32::MIN.to_le_bytes(), i32::MAX.to_le_bytes(), i32::MAX.to_le_bytes()].concat();
+        assert_eq!(read_patched(read_exr, "wide.exr", &patch(window, &widest)), io::ErrorKind::InvalidData);
+        let lowest = patch(window + 4, &i32::MAX.to_le_bytes());
+        assert_eq!(read_patched(read_exr, "low.exr", &lowest), io::ErrorKind::InvalidData);
+
+        // The offset table follows the header, the first block starts where it ends.
+        let table = exr.len() - ny * (8 + 4 * nx * 3) - 8 * ny;
+        let far = patch(table, &u64::MAX.to_le_bytes());
+        assert_eq!(read_patched(read_exr, "far.exr", &far), io::ErrorKind::InvalidData);
+        let first_block = table + 8 * ny;
+        let negative = patch(first_block + 4, &(-1i32).to_le_bytes());
+        assert_eq!(read_patched(read_exr, "negative.exr", &negative), io::ErrorKind::InvalidData);
+        // A block for a line far above the window is read as the top line.
+        let path = temp_file("above.exr", &patch(first_block, &i32::MIN.to_le_bytes()));
+        let image = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(image.is_ok());
+    }
+}
@@ -0,0 +1,393 @@
+use std::io;
+
+// Decoder for zlib / DEFLATE streams (RFC 1950 and 1951), enough for the
+// compressed image formats we read: PNG and ZIP compressed EXR. Written for
+// clarity over speed, textures are only decoded once per render.
+
+const LENGTH_BASE : [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA : [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE : [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA : [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order the code length code lengths are sent in.
+const CODE_LENGTH_ORDER : [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid(msg : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("inflate: {}", msg))
+}
+
+// Reads bits least significant first, as DEFLATE packs them.
+struct BitReader<'a> {
+    bytes : &'a [u8],
+    position : usize,
+    bit_buffer : u32,
+    bit_count : u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count : u32) -> io::Result<u32> {
+        while self.bit_count < count {
+            let byte = *self.bytes.get(self.position).ok_or_else(|| invalid("unexpected end of data"))?;
+            self.position += 1;
+            self.bit_buffer |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+// Canonical Huffman code as the number of codes of each length plus the
+// symbols sorted by code.
+struct Huffman {
+    counts : [u16; 16],
+    symbols : Vec<u16>,
+}
+
+impl Huffman {
+    fn build(lengths : &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader : &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad huffman code"))
+    }
+}
+
+// Decompresses a zlib stream, the checksum isn't verified.
+pub fn zlib_decompress(bytes : &[u8]) -> io::Result<Vec<u8>> {
+    if bytes.len() < 2 || bytes[0] & 0x0f != 8 || (u16::from(bytes[0]) << 8 | u16::from(bytes[1])) % 31 != 0 {
+        return Err(invalid("not a zlib stream"));
+    }
+    if bytes[1] & 0x20 != 0 {
+        return Err(invalid("preset dictionaries aren't supported"));
+    }
+    inflate(&bytes[2..])
+}
+
+// Decompresses raw DEFLATE data.
+pub fn inflate(bytes : &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader { bytes, position : 0, bit_buffer : 0, bit_count : 0 };
+    let mut out : Vec<u8> = Vec::with_capacity(bytes.len() * 4);
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut out)?,
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|l| *l = 8);
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                lengths[280..].iter_mut().for_each(|l| *l = 8);
+                let literals = Huffman::build(&lengths);
+                let distances = Huffman::build(&[5u8; 30]);
+                compressed_block(&mut reader, &mut out, &literals, &distances)?
+            },
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                compressed_block(&mut reader, &mut out, &literals, &distances)?
+            },
+            _ => return Err(invalid("bad block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored_block(reader : &mut BitReader, out : &mut Vec<u8>) -> io::Result<()> {
+    reader.align_to_byte();
+    let start = reader.position;
+    if start + 4 > reader.bytes.len() {
+        return Err(invalid("unexpected end of data"));
+    }
+    let length = u16::from_le_bytes([reader.bytes[start], reader.bytes[start + 1]]) as usize;
+    let check = u16::from_le_bytes([reader.bytes[start + 2], reader.bytes[start + 3]]) as usize;
+    if length != !check & 0xffff {
+        return Err(invalid("stored block length doesn't match its complement"));
+    }
+    let data = reader.bytes.get(start + 4..start + 4 + length).ok_or_else(|| invalid("unexpected end of data"))?;
+    out.extend_from_slice(data);
+    reader.position = start + 4 + length;
+    Ok(())
+}
+
+fn dynamic_tables(reader : &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::build(&code_lengths);
+
+    let mut lengths : Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid("repeat with no previous length"))?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("code lengths overrun"));
+    }
+    Ok((Huffman::build(&lengths[..literal_count]), Huffman::build(&lengths[literal_count..])))
+}
+
+fn compressed_block(reader : &mut BitReader, out : &mut Vec<u8>, literals : &Huffman, distances : &Huffman) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let index = symbol - 257;
+            if index >= LENGTH_BASE.len() {
+                return Err(invalid("bad length symbol"));
+            }
+            let length = LENGTH_BASE[index] as usize + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+            let index = distances.decode(reader)? as usize;
+            if index >= DISTANCE_BASE.len() {
+                return Err(invalid("bad distance symbol"));
+            }
+            let distance = DISTANCE_BASE[index] as usize + reader.bits(u32::from(DISTANCE_EXTRA[index]))? as usize;
+            if distance > out.len() {
+                return Err(invalid("distance too far back"));
+            }
+            // Copies can overlap their own output, so go a byte at a time.
+            let start = out.len() - distance;
+            for i in 0..length {
+                let byte = out[start + i];
+                out.push(byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const PANGRAM : &[u8] = b"Sphinx of black quartz, judge my vow. Pack my box with five dozen liquor jugs. How vexingly quick daft zebras jump!";
+
+    // PANGRAM through zlib at level 9, which picks a dynamic Huffman block.
+    const DYNAMIC : [u8; 105] = [
+        0x78, 0xda, 0x15, 0x8c, 0x4b, 0x12, 0xc2, 0x20, 0x10, 0x05, 0xaf, 0xf2, 0xdc, 0x5b, 0x39, 0x87,
+        0x4b, 0xab, 0x3c, 0x01, 0xc8, 0x40, 0x46, 0x09, 0x93, 0x10, 0x7e, 0xe1, 0xf4, 0x8e, 0xcb, 0x57,
+        0xdd, 0xfd, 0x5e, 0xfb, 0xca, 0x69, 0x40, 0x3c, 0x6c, 0x34, 0xef, 0x2f, 0x8e, 0x6a, 0x72, 0x99,
+        0x77, 0x7c, 0xaa, 0x0b, 0x84, 0xed, 0x42, 0x93, 0xbe, 0xe0, 0xf9, 0x47, 0x3a, 0xac, 0x0c, 0x74,
+        0x2e, 0x2b, 0x3c, 0x37, 0x82, 0x93, 0x49, 0x09, 0x91, 0x8f, 0x2a, 0x59, 0x83, 0x70, 0x2e, 0x78,
+        0x48, 0x47, 0xa3, 0xc1, 0x29, 0xc4, 0x4b, 0xbf, 0x58, 0x33, 0x67, 0x7c, 0xc1, 0x24, 0x9b, 0xcd,
+        0xa9, 0xd2, 0xb6, 0xdf, 0x7e, 0x61, 0x01, 0x29, 0x9a,
+    ];
+
+    // The same with zlib held to the fixed codes.
+    const FIXED : [u8; 115] = [
+        0x78, 0x01, 0x0b, 0x2e, 0xc8, 0xc8, 0xcc, 0xab, 0x50, 0xc8, 0x4f, 0x53, 0x48, 0xca, 0x49, 0x4c,
+        0xce, 0x56, 0x28, 0x2c, 0x4d, 0x2c, 0x2a, 0xa9, 0xd2, 0x51, 0xc8, 0x2a, 0x4d, 0x49, 0x4f, 0x55,
+        0xc8, 0xad, 0x54, 0x28, 0xcb, 0x2f, 0xd7, 0x53, 0x08, 0x00, 0x49, 0x01, 0x39, 0x49, 0xf9, 0x15,
+        0x0a, 0xe5, 0x99, 0x25, 0x19, 0x0a, 0x69, 0x99, 0x65, 0xa9, 0x0a, 0x29, 0xf9, 0x55, 0xa9, 0x79,
+        0x0a, 0x39, 0x99, 0x85, 0xa5, 0xf9, 0x45, 0x40, 0x0d, 0xe9, 0xc5, 0x7a, 0x0a, 0x1e, 0xf9, 0xe5,
+        0x0a, 0x65, 0xa9, 0x15, 0x99, 0x79, 0xe9, 0x39, 0x95, 0x40, 0xb3, 0x32, 0x81, 0xda, 0x52, 0x12,
+        0xd3, 0x4a, 0x14, 0xaa, 0x52, 0x93, 0x8a, 0x12, 0x8b, 0x81, 0x8a, 0x72, 0x0b, 0x14, 0x01, 0x61,
+        0x01, 0x29, 0x9a,
+    ];
+
+    // Raw DEFLATE stored blocks holding `data`, the last one marked final.
+    fn stored(data : &[u8], last : bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let chunks : Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(0xffff).collect() };
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.push(if last && i == chunks.len() - 1 { 1 } else { 0 });
+            out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            out.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+
+    // Writes bits least significant first, the other way round from `BitReader`.
+    struct BitWriter {
+        out : Vec<u8>,
+        buffer : u32,
+        count : u32,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value : u32, count : u32) {
+            self.buffer |= value << self.count;
+            self.count += count;
+            while self.count >= 8 {
+                self.out.push(self.buffer as u8);
+                self.buffer >>= 8;
+                self.count -= 8;
+            }
+        }
+
+        // Huffman codes go in most significant bit first.
+        fn code(&mut self, code : u32, length : u32) {
+            self.bits(code.reverse_bits() >> (32 - length), length);
+        }
+
+        fn literal_or_length(&mut self, symbol : u32) {
+            match symbol {
+                0..=143 => self.code(0x30 + symbol, 8),
+                144..=255 => self.code(0x190 + symbol - 144, 9),
+                256..=279 => self.code(symbol - 256, 7),
+                _ => self.code(0xc0 + symbol - 280, 8),
+            }
+        }
+    }
+
+    // A zlib stream of a single fixed Huffman block, with greedy matches
+    // found by looking back up to 256 bytes. Only the tests need to write
+    // compressed data. The checksum is left as zero, nothing here reads it.
+    pub(crate) fn zlib_fixed(data : &[u8]) -> Vec<u8> {
+        let mut writer = BitWriter { out : vec![0x78, 0x01], buffer : 0, count : 0 };
+        writer.bits(1, 1);
+        writer.bits(1, 2);
+        let mut position = 0;
+        while position < data.len() {
+            let (mut best_length, mut best_distance) = (0, 0);
+            for distance in 1..=position.min(256) {
+                let mut length = 0;
+                while length < 258 && position + length < data.len() && data[position + length - distance] == data[position + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = distance;
+                }
+            }
+            if best_length < 3 {
+                writer.literal_or_length(u32::from(data[position]));
+                position += 1;
+                continue;
+            }
+            let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= best_length).unwrap();
+            writer.literal_or_length(257 + index as u32);
+            writer.bits((best_length - LENGTH_BASE[index] as usize) as u32, u32::from(LENGTH_EXTRA[index]));
+            let index = DISTANCE_BASE.iter().rposition(|&base| base as usize <= best_distance).unwrap();
+            writer.code(index as u32, 5);
+            writer.bits((best_distance - DISTANCE_BASE[index] as usize) as u32, u32::from(DISTANCE_EXTRA[index]));
+            position += best_length;
+        }
+        writer.literal_or_length(256);
+        writer.bits(0, 7);
+        writer.out.extend_from_slice(&[0, 0, 0, 0]);
+        writer.out
+    }
+
+    // A zlib stream of stored blocks.
+    pub(crate) fn zlib_stored(data : &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        out.extend(stored(data, true));
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn stored_blocks() {
+        assert_eq!(zlib_decompress(&zlib_stored(PANGRAM)).unwrap(), PANGRAM);
+        assert_eq!(zlib_decompress(&zlib_stored(&[])).unwrap(), Vec::<u8>::new());
+        let long : Vec<u8> = (0..200000u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_stored(&long)).unwrap(), long);
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!((FIXED[2] >> 1) & 3, 1);
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), PANGRAM);
+
+        // Long runs, matches overlapping their own output and every length and distance range.
+        let mut data : Vec<u8> = Vec::new();
+        data.extend(std::iter::repeat(0x80).take(1000));
+        data.extend((0..3000u32).map(|i| ((i / 5) % 7 * 40 + (i % 3)) as u8));
+        data.extend((0..2000u32).map(|i| (i * i % 253) as u8));
+        assert_eq!(zlib_decompress(&zlib_fixed(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!((DYNAMIC[2] >> 1) & 3, 2);
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), PANGRAM);
+    }
+
+    #[test]
+    fn mixed_blocks() {
+        // A stored block ends on a byte boundary, so a fixed one can follow it directly.
+        let mut raw = stored(b"Stored first. ", false);
+        raw.extend_from_slice(&FIXED[2..]);
+        let mut expected = b"Stored first. ".to_vec();
+        expected.extend_from_slice(PANGRAM);
+        assert_eq!(inflate(&raw).unwrap(), expected);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        // The final block's end code sits in the last byte before the checksum.
+        for stream in [&DYNAMIC[..], &FIXED[..], &zlib_stored(PANGRAM)[..]].iter() {
+            for length in 0..stream.len() - 4 {
+                assert!(zlib_decompress(&stream[..length]).is_err(), "{} of {} bytes", length, stream.len());
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_input_does_not_panic() {
+        for stream in [&DYNAMIC[..], &FIXED[..]].iter() {
+            for position in 0..stream.len() {
+                for flip in [0x01u8, 0x10, 0x80, 0xff].iter() {
+                    let mut corrupt = stream.to_vec();
+                    corrupt[position] ^= flip;
+                    let _ = zlib_decompress(&corrupt);
+                }
+            }
+        }
+        assert!(zlib_decompress(&[0x78, 0x01, 0x07]).is_err(), "block type 3");
+        assert!(zlib_decompress(&[0x78, 0x01, 0x01, 0x05, 0x00, 0x00, 0x00]).is_err(), "bad stored length");
+        assert!(zlib_decompress(&[0x78, 0x02]).is_err(), "bad header check");
+    }
+}
@@ -0,0 +1,446 @@
+use crate::camera::*;
+use crate::ray::Ray;
+use crate::rng::Random;
+use crate::vec3::*;
+
+use std::fs;
+use std::io;
+
+// One spherical surface of a lens prescription, all lengths in mm.
+// A curvature radius of zero marks the aperture stop.
+#[derive(Copy, Clone, Debug)]
+pub struct LensElement {
+    pub curvature_radius: f32,
+    /// Distance along the axis to the next element (or the film for the last one).
+    pub thickness: f32,
+    /// Index of refraction of the medium behind this surface, 0 or 1 for air.
+    pub eta: f32,
+    pub aperture_radius: f32,
+}
+
+// Camera that traces rays from the film through a real lens system, after
+// pbrt's RealisticCamera. Vignetting, distortion and focus breathing fall
+// out of the tracing rather than being modelled.
+//
+// Lens space has the film at z = 0 and the elements stacked along -z, front
+// (scene side) element last. Camera space is the same with z flipped, so the
+// scene is towards +z, x is to the right and y up.
+pub struct RealisticCamera {
+    elements: Vec<LensElement>,
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    units_per_mm: f32,
+    film_width: f32,
+    film_height: f32,
+    // Area of the exit pupil seen from the film centre, weights are relative
+    // to it so the centre of the image isn't darkened by the stop.
+    centre_pupil_area: f32,
+    // Exit pupil bounds on the rear element's plane, (x0, y0, x1, y1), for
+    // film points in PUPIL_INTERVALS rings out to the film corner. They're for
+    // points on the +x axis and get rotated round to the actual film point.
+    pupil_bounds: Vec<(f32, f32, f32, f32)>,
+    shutter: Shutter,
+}
+
+const PUPIL_INTERVALS: usize = 64;
+
+// Lens prescription in the usual pbrt format, one element per line:
+// curvature radius, thickness, index of refraction, aperture diameter.
+// Lines starting with '#' are comments.
+pub fn load_lens_file(path: &str) -> io::Result<Vec<LensElement>> {
+    let text = fs::read_to_string(path)?;
+    let mut elements = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values: Result<Vec<f32>, _> = line.split_whitespace().map(|v| v.parse::<f32>()).collect();
+        match values {
+            Ok(ref v) if v.len() == 4 => elements.push(LensElement {
+                curvature_radius: v[0],
+                thickness: v[1],
+                eta: v[2],
+                aperture_radius: v[3] / 2.0,
+            }),
+            _ => {
+                let msg = format!("{}:{}: expected radius, thickness, eta and aperture", path, number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+        }
+    }
+
+    if elements.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no lens elements", path)));
+    }
+    Ok(elements)
+}
+
+impl RealisticCamera {
+    // `aperture_diameter` (mm) stops the lens down, it can't open it past the
+    // stop in the prescription. `focus_distance` is in scene units, and
+    // `units_per_mm` says how big a millimetre is in the scene.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        look_from: &Vec3,
+        look_at: &Vec3,
+        camera_up: &Vec3,
+        mut elements: Vec<LensElement>,
+        aperture_diameter: f32,
+        focus_distance: f32,
+        film_diagonal: f32,
+        aspect_ratio: f32,
+        units_per_mm: f32,
+        time_open: f32,
+        time_close: f32
+    ) -> Self {
+        for element in elements.iter_mut() {
+            if element.curvature_radius == 0.0 {
+                element.aperture_radius = element.aperture_radius.min(aperture_diameter / 2.0);
+            }
+        }
+
+        let (u, v, w) = camera_frame(look_from, look_at, camera_up);
+        let film_height = film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let mut camera = RealisticCamera {
+            elements,
+            origin: *look_from,
+            u,
+            v,
+            w,
+            units_per_mm,
+            film_width: film_height * aspect_ratio,
+            film_height,
+            centre_pupil_area: 1.0,
+            pupil_bounds: vec![],
+            shutter: Shutter::build(time_open, time_close)
+        };
+
+        let back_focus = camera.focus_thick_lens(focus_distance / units_per_mm);
+        if let Some(last) = camera.elements.last_mut() {
+            last.thickness = back_focus;
+        }
+        camera.pupil_bounds = (0..PUPIL_INTERVALS).map(|i| camera.bound_exit_pupil(i)).collect();
+        camera.centre_pupil_area = camera.centre_pupil_area().max(1e-6);
+        camera
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    fn rear_z(&self) -> f32 {
+        self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    fn front_z(&self) -> f32 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_radius(&self) -> f32 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    // Camera space ray leaving the film, to camera space ray leaving the front element.
+    fn trace_from_film(&self, origin: &Vec3, direction: &Vec3) -> Option<(Vec3, Vec3)> {
+        let mut o = flip_z(origin);
+        let mut d = flip_z(direction);
+        let mut element_z = 0.0;
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+            let (p, normal) = intersect_element(element, element_z, &o, &d)?;
+            o = p;
+            if element.curvature_radius != 0.0 {
+                let eta_i = element.eta;
+                let eta_t = if i > 0 && self.elements[i - 1].eta != 0.0 { self.elements[i - 1].eta } else { 1.0 };
+                d = refract_lens(&(&d * -1.0), &normal, eta_i / eta_t)?;
+            }
+        }
+        Some((flip_z(&o), flip_z(&d)))
+    }
+
+    // Camera space ray entering the front element, to camera space ray arriving at the film side.
+    fn trace_from_scene(&self, origin: &Vec3, direction: &Vec3) -> Option<(Vec3, Vec3)> {
+        let mut o = flip_z(origin);
+        let mut d = flip_z(direction);
+        let mut element_z = -self.front_z();
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+            let (p, normal) = intersect_element(element, element_z, &o, &d)?;
+            o = p;
+            if element.curvature_radius != 0.0 {
+                let eta_i = if i == 0 || self.elements[i - 1].eta == 0.0 { 1.0 } else { self.elements[i - 1].eta };
+                let eta_t = if element.eta != 0.0 { element.eta } else { 1.0 };
+                d = refract_lens(&(&d * -1.0), &normal, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+        Some((flip_z(&o), flip_z(&d)))
+    }
+
+    // Thick lens approximation: principal plane and focal point for a ray
+    // parallel to the axis, coming in from one side and leaving the other.
+    fn cardinal_points(in_origin: &Vec3, out_origin: &Vec3, out_direction: &Vec3) -> (f32, f32) {
+        let tf = -out_origin.x() / out_direction.x();
+        let fz = -(out_origin.z() + tf * out_direction.z());
+        let tp = (in_origin.x() - out_origin.x()) / out_direction.x();
+        let pz = -(out_origin.z() + tp * out_direction.z());
+        (pz, fz)
+    }
+
+    // Distance from the rear element to the film that focuses at `focus_distance` mm.
+    fn focus_thick_lens(&self, focus_distance: f32) -> f32 {
+        let x = 0.001 * (self.film_width * self.film_width + self.film_height * self.film_height).sqrt();
+        let scene_origin = Vec3::from(x, 0.0, self.front_z() + 1.0);
+        let film_origin = Vec3::from(x, 0.0, self.rear_z() - 1.0);
+        let into_film = self.trace_from_scene(&scene_origin, &Vec3::from(0.0, 0.0, -1.0));
+        let into_scene = self.trace_from_film(&film_origin, &Vec3::from(0.0, 0.0, 1.0));
+        match (into_film, into_scene) {
+            (Some((o0, d0)), Some((o1, d1))) => {
+                let (pz0, fz0) = Self::cardinal_points(&scene_origin, &o0, &d0);
+                let (pz1, _) = Self::cardinal_points(&film_origin, &o1, &d1);
+                let f = fz0 - pz0;
+                let z = -focus_distance;
+                let c = (pz1 - z - pz0) * (pz1 - z - 4.0 * f - pz0);
+                if c <= 0.0 {
+                    return self.rear_z();
+                }
+                let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+                self.rear_z() + delta
+            }
+            // Lens too strong to trace near the axis, keep the prescription's spacing.
+            _ => self.rear_z(),
+        }
+    }
+
+    fn film_radius(&self) -> f32 {
+        0.5 * (self.film_width * self.film_width + self.film_height * self.film_height).sqrt()
+    }
+
+    // Bounds of the points on the rear element's plane that light gets
+    // through to from film points on the x axis in the given interval, by
+    // tracing a grid of rays (the approach pbrt takes). The grid covers a bit
+    // more than the rear element and the bounds are padded by a grid cell so
+    // thin slivers between the grid points aren't cut off.
+    fn bound_exit_pupil(&self, interval: usize) -> (f32, f32, f32, f32) {
+        const FILM_SAMPLES: usize = 4;
+        const GRID: usize = 32;
+        let extent = 1.5 * self.rear_radius();
+        let cell = 2.0 * extent / GRID as f32;
+        let (r0, r1) = (interval as f32, (interval + 1) as f32);
+        let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for f in 0..FILM_SAMPLES {
+            let radius = (r0 + (r1 - r0) * f as f32 / (FILM_SAMPLES - 1) as f32) / PUPIL_INTERVALS as f32;
+            let film = Vec3::from(radius * self.film_radius(), 0.0, 0.0);
+            for i in 0..=GRID {
+                for j in 0..=GRID {
+                    let (x, y) = (-extent + i as f32 * cell, -extent + j as f32 * cell);
+                    let rear = Vec3::from(x, y, self.rear_z());
+                    if self.trace_from_film(&film, &(rear - film)).is_some() {
+                        bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+                    }
+                }
+            }
+        }
+        if bounds.0 > bounds.2 {
+            // Nothing gets through, an empty box turns the rays down.
+            return (0.0, 0.0, 0.0, 0.0);
+        }
+        (bounds.0 - cell, bounds.1 - cell, bounds.2 + cell, bounds.3 + cell)
+    }
+
+    // Point on the rear element's plane inside the exit pupil bounds for the
+    // film point, and the area of those bounds.
+    fn sample_exit_pupil(&self, film_x: f32, film_y: f32, rnd: &mut Random) -> (Vec3, f32) {
+        let radius = (film_x * film_x + film_y * film_y).sqrt();
+        let interval = ((radius / self.film_radius() * PUPIL_INTERVALS as f32) as usize).min(PUPIL_INTERVALS - 1);
+        let (x0, y0, x1, y1) = self.pupil_bounds[interval];
+        let x = x0 + (x1 - x0) * rnd.gen();
+        let y = y0 + (y1 - y0) * rnd.gen();
+        let (sin, cos) = if radius > 0.0 { (film_y / radius, film_x / radius) } else { (0.0, 1.0) };
+        (Vec3::from(cos * x - sin * y, sin * x + cos * y, self.rear_z()), (x1 - x0) * (y1 - y0))
+    }
+
+    // Area of the rear element's plane that light from the film centre gets
+    // through, from a stratified grid over the centre's exit pupil bounds.
+    fn centre_pupil_area(&self) -> f32 {
+        const N: usize = 64;
+        let (x0, y0, x1, y1) = self.pupil_bounds[0];
+        let film = Vec3::default();
+        let mut passed = 0;
+        for i in 0..N {
+            for j in 0..N {
+                let x = x0 + (x1 - x0) * (i as f32 + 0.5) / N as f32;
+                let y = y0 + (y1 - y0) * (j as f32 + 0.5) / N as f32;
+                let rear = Vec3::from(x, y, self.rear_z());
+                if self.trace_from_film(&film, &(rear - film)).is_some() {
+                    passed += 1;
+                }
+            }
+        }
+        (x1 - x0) * (y1 - y0) * passed as f32 / (N * N) as f32
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn get_ray(&self, s: f32, t: f32, rnd: &mut Random) -> Option<CameraRay> {
+        // The lens flips the image over, so the top of the picture is at the bottom of the film.
+        let film = Vec3::from(-(s - 0.5) * self.film_width, -(t - 0.5) * self.film_height, 0.0);
+        // Only the exit pupil is sampled, most of the rear element is blocked
+        // once the lens is stopped down.
+        let (rear, pupil_area) = self.sample_exit_pupil(*film.x(), *film.y(), rnd);
+
+        let direction = rear - film;
+        let (o, d) = self.trace_from_film(&film, &direction)?;
+        // cos^4 falloff of irradiance on the film, relative to the centre.
+        let cos_theta = direction.z() / direction.length();
+        let weight = cos_theta.powi(4) * pupil_area / self.centre_pupil_area;
+
+        let to_world = |p: &Vec3| &self.u * *p.x() + &self.v * *p.y() - &self.w * *p.z();
+        Some(CameraRay {
+            ray: Ray {
+                origin: self.origin + &to_world(&o) * self.units_per_mm,
+                direction: to_world(&d),
+                time: self.shutter.sample(t, rnd)
+            },
+            weight
+        })
+    }
+
+    fn time_range(&self) -> (f32, f32) {
+        self.shutter.time_range()
+    }
+}
+
+fn flip_z(v: &Vec3) -> Vec3 {
+    Vec3::from(*v.x(), *v.y(), -v.z())
+}
+
+// Ray against the element's surface in lens space, returning the hit point
+// and the surface normal facing back along the ray. Fails if the hit is
+// outside the element's aperture.
+fn intersect_element(element: &LensElement, element_z: f32, o: &Vec3, d: &Vec3) -> Option<(Vec3, Vec3)> {
+    let (t, normal) = if element.curvature_radius == 0.0 {
+        let t = (element_z - o.z()) / d.z();
+        if !t.is_finite() || t <= 0.0 {
+            return None;
+        }
+        (t, Vec3::from(0.0, 0.0, if *d.z() > 0.0 { -1.0 } else { 1.0 }))
+    } else {
+        let radius = element.curvature_radius;
+        let oc = *o - Vec3::from(0.0, 0.0, element_z + radius);
+        let a = d.square_length();
+        let b = 2.0 * dot(d, &oc);
+        let c = oc.square_length() - radius * radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (t0, t1) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+        // Which of the two intersections is on the lens depends on which way
+        // the ray is going and which way the surface bulges.
+        let use_closer = (*d.z() > 0.0) ^ (radius < 0.0);
+        let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+        if t < 0.0 {
+            return None;
+        }
+        let n = oc + d * t;
+        let n = &n / n.length();
+        let n = if dot(&n, d) > 0.0 { &n * -1.0 } else { n };
+        (t, n)
+    };
+
+    let p = *o + d * t;
+    if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+        return None;
+    }
+    Some((p, normal))
+}
+
+// wi points away from the surface, eta is eta_i / eta_t.
+fn refract_lens(wi: &Vec3, normal: &Vec3, eta: f32) -> Option<Vec3> {
+    let wi = wi / wi.length();
+    let cos_i = dot(normal, &wi);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(&wi * -eta + normal * (eta * cos_i - cos_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dgauss(aperture_diameter: f32, focus_distance: f32) -> RealisticCamera {
+        let elements = load_lens_file("lenses/dgauss.50mm.dat").expect("lens file should load");
+        RealisticCamera::build(&Vec3::default(), &Vec3::from(0.0, 0.0, -1.0), &Vec3::from(0.0, 1.0, 0.0),
+            elements, aperture_diameter, focus_distance, 43.3, 1.5, 1.0, 0.0, 1.0)
+    }
+
+    #[test]
+    fn lens_file_is_read() {
+        let elements = load_lens_file("lenses/dgauss.50mm.dat").unwrap();
+        assert_eq!(elements.len(), 11);
+        assert_eq!(elements[5].curvature_radius, 0.0);
+        assert_eq!(elements[5].aperture_radius, 8.55);
+        assert_eq!(elements[10].eta, 1.0);
+    }
+
+    #[test]
+    fn on_axis_point_focuses_on_the_film() {
+        for &focus_distance in [500.0, 2000.0].iter() {
+            let camera = dgauss(17.1, focus_distance);
+            // Scene space is camera space here, with the film at z = 0 and
+            // the scene towards +z.
+            let point = Vec3::from(0.0, 0.0, focus_distance);
+            for &height in [0.2, 0.4, -0.3].iter() {
+                let front = Vec3::from(height, 0.0, camera.front_z());
+                let (o, d) = camera.trace_from_scene(&point, &(front - point)).expect("paraxial ray gets through");
+                let t = -o.z() / d.z();
+                let on_film = o + &d * t;
+                assert!(on_film.x().abs() < 0.01, "focus {} height {} lands at {}", focus_distance, height, on_film.x());
+            }
+        }
+    }
+
+    #[test]
+    fn exit_pupil_bounds_hold_every_ray_that_gets_through() {
+        let camera = dgauss(4.0, 1000.0);
+        let mut rnd = Random::create_with_seed(3);
+        for &(s, t) in [(0.5, 0.5), (0.8, 0.3), (0.0, 1.0), (0.3, 0.05)].iter() {
+            let film_x = -(s - 0.5) * camera.film_width;
+            let film_y = -(t - 0.5) * camera.film_height;
+            let radius = (film_x * film_x + film_y * film_y).sqrt();
+            let interval = ((radius / camera.film_radius() * PUPIL_INTERVALS as f32) as usize).min(PUPIL_INTERVALS - 1);
+            let (x0, y0, x1, y1) = camera.pupil_bounds[interval];
+            let (sin, cos) = if radius > 0.0 { (film_y / radius, film_x / radius) } else { (0.0, 1.0) };
+            let film = Vec3::from(film_x, film_y, 0.0);
+            for _ in 0..4000 {
+                let rear = Vec3::from((2.0 * rnd.gen() - 1.0) * camera.rear_radius(), (2.0 * rnd.gen() - 1.0) * camera.rear_radius(), camera.rear_z());
+                if camera.trace_from_film(&film, &(rear - film)).is_some() {
+                    // Back into the frame where the film point is on the +x axis.
+                    let (x, y) = (cos * rear.x() + sin * rear.y(), -sin * rear.x() + cos * rear.y());
+                    assert!(x >= x0 && x <= x1 && y >= y0 && y <= y1, "({}, {}) outside {:?}", x, y, (x0, y0, x1, y1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stopped_down_centre_is_not_darkened() {
+        let camera = dgauss(4.0, 1000.0);
+        let mut rnd = Random::create_with_seed(5);
+        let n = 20000;
+        let total: f32 = (0..n).map(|_| camera.get_ray(0.5, 0.5, &mut rnd).map_or(0.0, |r| r.weight)).sum();
+        let mean = total / n as f32;
+        assert!((mean - 1.0).abs() < 0.05, "mean weight {}", mean);
+    }
+}
@@ -1,19 +1,41 @@
+
 mod aabb;
 mod camera;
+mod lens;
 mod hitable;
+mod quadric;
+mod csg;
+mod sdf;
+mod heightfield;
+mod mesh;
+mod subdivision;
+mod curve;
+mod pointcloud;
 mod material;
+mod hair;
 mod ray;
 mod rng;
 mod vec3;
 mod texture;
 mod perlin;
 mod scenes;
+mod tonemap;
+mod imageio;
+mod inflate;
+mod filter;
+mod film;
+mod denoise;
+mod aov;
+mod options;
 
 extern crate stb_image;
 
+use aov::*;
 use camera::Camera;
+use denoise::Denoiser;
+use film::*;
 use hitable::*;
-use material::*;
+use options::{Options, USAGE};
 use ray::Ray;
 use rng::Random;
 use std::f32;
@@ -21,184 +43,282 @@ use vec3::Vec3;
 
 
 use scenes::*;
+use tonemap::*;
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}\n\nscenes: {}", USAGE, SCENE_NAMES.join(", "));
+        return;
+    }
+    // `raytracer denoise <prefix>` denoises a previously saved set of buffers.
+    let denoise_prefix = match args.first() {
+        Some(command) if command == "denoise" => Some(args.get(1).cloned().unwrap_or_else(|| fail("denoise needs a prefix"))),
+        _ => None,
+    };
+    let flags = if denoise_prefix.is_some() { &args[2..] } else { &args[..] };
+    let options = Options::parse(flags).unwrap_or_else(|message| fail(&message));
+
+    if let Some(prefix) = denoise_prefix {
+        let buffers = FilmBuffers::load(&prefix).expect("Could not load film buffers");
+        let cols = Denoiser::default().denoise(&buffers);
+        write_image(buffers.nx, buffers.ny, &cols, &options.tone_mapping);
+        return;
+    }
+
     let mut rnd = Random::create_with_seed(42);
-    let nx = 1280;
-    let ny =  720;
-
-    let time_start = 0.0;
-    let time_end = 1.0;
-
-    // let (mut hitable_list, camera) = random_moving_scene(nx, ny, &mut rnd, time_start, time_end);
-    // let (mut hitable_list, camera) = two_spheres(nx, ny);
-    // let (mut hitable_list, camera) = two_perlin_spheres(nx, ny, &mut rnd);
-    // let (mut hitable_list, camera) = earth_scene(nx, ny);
-    // let (mut hitable_list, camera) = simple_light(nx, ny, &mut rnd);
-    // let (mut hitable_list, camera) = cornell_box(nx, ny);
-    // let (mut hitable_list, camera) = cornell_smoke(nx, ny);
-    let (mut hitable_list, camera) = final_render(nx, ny, &mut rnd);
-    let bvh_tree = BvhTree::build(&mut hitable_list, &mut rnd, time_start, time_end);
-
-    let samples_per_pixel = 16384;
-    let thread_count = 24;
-
-    // let cols = render_single_thread(&camera, nx, ny, samples_per_pixel, &bvh_tree, &mut rnd);
-    let cols = render_multi_thread(
-        camera,
-        nx,
-        ny,
-        samples_per_pixel,
-        bvh_tree,
-        &mut rnd,
-        thread_count,
-    );
-
-    print!("P3\n{} {}\n255\n", nx, ny);
-    for col in cols.iter() {
-        let c = col.clamp(0.0, 1.0);
-        let ir = (255.99 * c.r()) as i32;
-        let ig = (255.99 * c.g()) as i32;
-        let ib = (255.99 * c.b()) as i32;
-        println!("{} {} {}", ir, ig, ib);
+    let (nx, ny) = (options.nx, options.ny);
+
+    let time_start: f32 = 0.0;
+    let time_end: f32 = 1.0;
+
+    let (hitable_list, camera) = build_scene(&options.scene, nx, ny, &mut rnd)
+        .unwrap_or_else(|| fail(&format!("unknown scene '{}', pick one of {}", options.scene, SCENE_NAMES.join(", "))));
+    // Top level objects get ids 1, 2, 3... in scene order for the object id pass,
+    // material ids are given out by the scenes with `Tagged::with_material_id`.
+    let mut hitable_list = tag_objects(hitable_list);
+    // The BVH has to cover every ray time, and a rolling shutter sends the
+    // bottom rows out after the shutter closes.
+    let (shutter_open, shutter_close) = camera.time_range();
+    let bvh_tree = BvhTree::build(&mut hitable_list, &mut rnd, time_start.min(shutter_open), time_end.max(shutter_close));
+
+    let mut film = Film::build(nx, ny, options.filter);
+    if !options.aovs.is_empty() {
+        film = film.with_passes();
+    }
+    if options.denoise || options.save_buffers.is_some() || !options.aovs.is_empty() {
+        film = film.with_features();
+    }
+    let film = if options.thread_count == 1 {
+        let mut film = film;
+        render_single_thread(camera.as_ref(), &mut film, options.samples_per_pixel, &bvh_tree, &options.light_ids, &mut rnd);
+        film
+    } else {
+        render_multi_thread(
+            camera,
+            film,
+            options.samples_per_pixel,
+            bvh_tree,
+            &options.light_ids,
+            &mut rnd,
+            options.thread_count,
+        )
+    };
+
+    if !options.aovs.is_empty() {
+        write_aovs(&options.aov_output, &film, &options.aovs).expect("Could not write AOVs");
     }
+
+    let cols = if options.denoise || options.save_buffers.is_some() {
+        let buffers = film.buffers();
+        if let Some(prefix) = &options.save_buffers {
+            buffers.save(prefix).expect("Could not save film buffers");
+        }
+        if options.denoise {
+            Denoiser::default().denoise(&buffers)
+        } else {
+            buffers.colour
+        }
+    } else {
+        film.resolve_colour()
+    };
+    write_image(nx, ny, &cols, &options.tone_mapping);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    std::process::exit(2);
+}
+
+fn write_image(nx: usize, ny: usize, cols: &[Vec3], tone_mapping: &ToneMapping) {
+    // The film holds linear radiance, everything display related happens here.
+    let pixels : Vec<Vec3> = cols.iter().map(|col| tone_mapping.apply(col)).collect();
+
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    let written = if tone_mapping.is_display_referred() {
+        imageio::write_ppm(&mut out, nx, ny, &pixels)
+    } else {
+        imageio::write_pfm(&mut out, nx, ny, &pixels)
+    };
+    written.expect("Could not write image");
 }
 
 fn render_multi_thread(
-    camera: Camera,
-    nx: usize,
-    ny: usize,
+    camera: Box<dyn Camera>,
+    film: Film,
     samples_per_pixel: i16,
     bvh_tree: BvhTree,
+    light_ids: &[u32],
     _: &mut Random,
     thread_count: usize,
-) -> Vec<Vec3> {
-    let mut cols: Vec<Vec3> = Vec::with_capacity(nx * ny);
-    let mut workers: Vec<std::thread::JoinHandle<std::vec::Vec<vec3::Vec3>>> =
-        Vec::with_capacity(thread_count);
+) -> Film {
+    let mut film = film;
+    let mut workers: Vec<std::thread::JoinHandle<Film>> = Vec::with_capacity(thread_count);
+    let nx = film.nx;
+    let ny = film.ny;
     let nxd = nx as f32;
     let nyd = ny as f32;
 
     let arc_tree = std::sync::Arc::new(bvh_tree);
+    let arc_camera: std::sync::Arc<dyn Camera> = std::sync::Arc::from(camera);
 
     for thread_index in (0..thread_count).rev() {
         let local_bvh = arc_tree.clone();
+        let camera = arc_camera.clone();
+        let light_ids = light_ids.to_vec();
         let thread_seed = 1234 * thread_index as u64;
         let (y0, y1) = get_segment(thread_count, thread_index, ny);
+        // y runs bottom to top, film rows run top to bottom
+        let mut tile = film.tile(ny - y1, ny - y0);
 
         let thd = std::thread::spawn(move || {
             let mut rnd = Random::create_with_seed(thread_seed);
-            let mut cols: Vec<Vec3> = Vec::with_capacity(nx * ny);
             for y in (y0..y1).rev() {
                 let yd = y as f32;
                 for x in 0..nx {
-                    let mut col = Vec3::from(0.0, 0.0, 0.0);
                     let xd = x as f32;
                     for _ in 0..samples_per_pixel {
-                        let u = (xd + rnd.gen()) / nxd;
-                        let v = (yd + rnd.gen()) / nyd;
-                        let r = camera.get_ray(u, v, &mut rnd);
-                        col += &colour(&r, local_bvh.as_ref(), &mut rnd);
+                        let sx = xd + rnd.gen();
+                        let sy = yd + rnd.gen();
+                        let sample = camera_sample(camera.as_ref(), sx, sy, nxd, nyd, local_bvh.as_ref(), &light_ids, &mut rnd);
+                        tile.add_sample(sx, nyd - sy, &sample);
                     }
-
-                    col /= f32::from(samples_per_pixel);
-                    col = Vec3::from(col.x().sqrt(), col.y().sqrt(), col.z().sqrt());
-                    cols.push(col);
                 }
             }
 
-            cols
+            tile
         });
 
         workers.push(thd);
     }
 
     for waiter in workers {
-        let mut result = waiter.join().unwrap();
-        cols.append(&mut result);
+        let tile = waiter.join().unwrap();
+        film.merge(&tile);
     }
 
-    cols
+    film
 }
 
+// Used for --threads 1, which is easier to follow in a debugger.
 fn render_single_thread(
-    camera: &Camera,
-    nx: usize,
-    ny: usize,
+    camera: &dyn Camera,
+    film: &mut Film,
     samples_per_pixel: i16,
     bvh_tree: &BvhTree,
+    light_ids: &[u32],
     rnd: &mut Random,
-) -> Vec<Vec3> {
+) {
+    let nx = film.nx;
+    let ny = film.ny;
     let nxd = nx as f32;
     let nyd = ny as f32;
 
-    let mut cols: Vec<Vec3> = Vec::with_capacity(nx * ny);
     for y in (0..ny).rev() {
         let yd = y as f32;
         for x in 0..nx {
-            let mut col = Vec3::from(0.0, 0.0, 0.0);
             let xd = x as f32;
             for _ in 0..samples_per_pixel {
-                let u = (xd + rnd.gen()) / nxd;
-                let v = (yd + rnd.gen()) / nyd;
-                let r = camera.get_ray(u, v, rnd);
-                col += &colour(&r, &bvh_tree, rnd);
+                let sx = xd + rnd.gen();
+                let sy = yd + rnd.gen();
+                let sample = camera_sample(camera, sx, sy, nxd, nyd, bvh_tree, light_ids, rnd);
+                film.add_sample(sx, nyd - sy, &sample);
             }
-
-            col /= f32::from(samples_per_pixel);
-            col = Vec3::from(col.x().sqrt(), col.y().sqrt(), col.z().sqrt());
-            cols.push(col);
         }
     }
+}
 
-    cols
+// Traces the camera ray through raster position (sx, sy), y up. The rays
+// through the neighbouring pixels are generated from the same random
+// numbers, so they share the lens and time samples and only differ by the
+// pixel offset; they give the texture footprint at the first hit.
+#[allow(clippy::too_many_arguments)]
+fn camera_sample(
+    camera: &dyn Camera,
+    sx: f32,
+    sy: f32,
+    nxd: f32,
+    nyd: f32,
+    world: &BvhTree,
+    light_ids: &[u32],
+    rnd: &mut Random,
+) -> Sample {
+    let fork = rnd.clone();
+    let r = match camera.get_ray(sx / nxd, sy / nyd, rnd) {
+        Some(r) => r,
+        None => return Sample::default(),
+    };
+    let rx = camera.get_ray((sx + 1.0) / nxd, sy / nyd, &mut fork.clone());
+    let ry = camera.get_ray(sx / nxd, (sy + 1.0) / nyd, &mut fork.clone());
+    let differentials = match (rx, ry) {
+        (Some(rx), Some(ry)) => Some((rx.ray, ry.ray)),
+        _ => None,
+    };
+    colour(&r.ray, differentials, world, light_ids, rnd).weighted(r.weight)
 }
 
-fn colour(ray: &Ray, world: &BvhTree, rnd: &mut Random) -> Vec3 {
+// Walks the path forwards keeping the running attenuation, so each bit of
+// emitted light can be credited to the bounce and the light it came from.
+//
+// Only the first hit gets a texture footprint from the ray differentials,
+// textures further along the path are looked up at a single point.
+fn colour(ray: &Ray, differentials: Option<(Ray, Ray)>, world: &BvhTree, light_ids: &[u32], rnd: &mut Random) -> Sample {
     const MAX_DEPTH : usize = 20;
     const MAX_THING: f32 = 1.0e10;
-    let mut accumulated_colour = Vec3::from(0.0, 0.0, 0.0);
-    let mut go = true;
-    let mut depth_stack : [(Vec3, Vec3); MAX_DEPTH] = [(Vec3::from(0.0, 0.0, 0.0), Vec3::from(0.0, 0.0, 0.0)); MAX_DEPTH];
-    let mut index = 0;
+    let mut throughput = Vec3::from(1.0, 1.0, 1.0);
     let mut current_ray = *ray;
-    while index < MAX_DEPTH && go {
-        let record = world.root.hit(&current_ray, 0.001, MAX_THING);
-        depth_stack[index] = match record {
+    let mut sample = Sample::default();
+    for depth in 0..MAX_DEPTH {
+        let mut rec = match world.root.hit(&current_ray, 0.001, MAX_THING) {
             None => {
                 // Render "Sky"
                 // let direction = ray.direction.make_normalised();
                 // let t = 0.5 * (direction.y() + 1.0);
 
                 // (&Vec3::from(1.0, 1.0, 1.0) * (1.0 - t)) + (&Vec3::from(0.5, 0.7, 1.0) * t)
-                go = false;
-                (Vec3::from(0.0,0.0,0.0),Vec3::from(0.0,0.0,0.0))
-            }
-            Some(rec) => {
-                let mut scattered = Ray::default();
-                let mut attenuation = Vec3::default();
-                let emitted = rec.material.emitted(rec.u, rec.v, &rec.p);
-                if rec.material.scatter(&current_ray, &rec, rnd, &mut attenuation, &mut scattered)
-                {
-                    current_ray = scattered;
-                    (emitted, attenuation)
-                } else {
-                    go = false;
-                    (emitted, Vec3::from(0.0, 0.0, 0.0))
-                }
+                break;
             }
+            Some(rec) => rec,
         };
-        index = index + 1;
-    }
 
-    for j in (0..index).rev() {
-        let (emitted, attenuation) = &depth_stack[j];
-        accumulated_colour = &accumulated_colour.direct_product(attenuation) + emitted;
-    }
+        if depth == 0 {
+            if let Some((rx, ry)) = &differentials {
+                rec.set_footprint(rx, ry);
+            }
+            sample.albedo = rec.material.albedo(&rec);
+            sample.normal = rec.normal.make_normalised();
+            sample.depth = rec.t * ray.direction.length();
+            sample.uv = Vec3::from(rec.u, rec.v, 0.0);
+            sample.object_id = rec.object_id;
+            sample.material_id = rec.material_id;
+        }
 
+        let emitted = throughput.direct_product(&rec.material.emitted(rec.u, rec.v, &rec.p));
+        // Bucketed by bounce count, so "direct" only has the light the path
+        // happened to hit after one bounce, nothing is sampled towards the lights.
+        match depth {
+            0 => sample.emission += &emitted,
+            1 => sample.direct += &emitted,
+            _ => sample.indirect += &emitted,
+        }
+        // Options turns down more lights than there are slots.
+        if let Some(slot) = light_ids.iter().take(MAX_LIGHT_AOVS).position(|id| *id == rec.object_id) {
+            sample.lights[slot] += &emitted;
+        }
+
+        let mut scattered = Ray::default();
+        let mut attenuation = Vec3::default();
+        if !rec.material.scatter(&current_ray, &rec, rnd, &mut attenuation, &mut scattered) {
+            break;
+        }
+        throughput = throughput.direct_product(&attenuation);
+        current_ray = scattered;
+    }
 
-    accumulated_colour
+    sample.colour = sample.emission + sample.direct + sample.indirect;
+    sample
 }
 
 fn get_segment(thread_count: usize, thread_index: usize, ny: usize) -> (usize, usize) {
@@ -16,6 +16,11 @@ pub trait Material: Send + Sync {
     fn emitted(&self, _u : f32, _v : f32, _p : &Vec3) -> Vec3 {
         Vec3::from(0.0,0.0,0.0)
     }
+
+    // Surface colour at the first hit, used as a guide by the denoiser.
+    fn albedo(&self, _rec : &HitRecord) -> Vec3 {
+        Vec3::from(1.0,1.0,1.0)
+    }
 }
 
 pub struct Lambertian {
@@ -50,9 +55,13 @@ impl Material for Lambertian {
         scattered.origin = rec.p;
         scattered.direction = target - rec.p;
         scattered.time = ray.time;
-        attenuation.set(&self.albedo.colour(rec.u, rec.v, &rec.p));
+        attenuation.set(&self.albedo.value(rec));
         true
     }
+
+    fn albedo(&self, rec : &HitRecord) -> Vec3 {
+        self.albedo.value(rec)
+    }
 }
 
 impl Metal {
@@ -81,6 +90,10 @@ impl Material for Metal {
         attenuation.set(&self.albedo);
         dot(&scattered.direction, &rec.normal) > 0.0
     }
+
+    fn albedo(&self, _rec : &HitRecord) -> Vec3 {
+        self.albedo
+    }
 }
 
 impl Dielectric {
@@ -179,7 +192,224 @@ impl Material for Isotropic {
             direction: random_in_unit_sphere(rnd),
             time: ray.time
         };
-        *attenuation = self.albedo.colour(rec.u, rec.v, &rec.p);
+        *attenuation = self.albedo.value(rec);
         true
     }
-}
\ No newline at end of file
+
+    fn albedo(&self, rec : &HitRecord) -> Vec3 {
+        self.albedo.value(rec)
+    }
+}
+
+// Shading normal perturbed by a height field, the inner material sees the
+// bumped normal. `height` is read as a grey scalar (the mean of its channels)
+// and scaled by `scale` to world units, so a `NoiseTexture` gives rough stone.
+pub struct BumpMap {
+    pub material : Box<dyn Material>,
+    pub height : Box<dyn Texture>,
+    pub scale : f32
+}
+
+impl BumpMap {
+    pub fn build(material : Box<dyn Material>, height : Box<dyn Texture>, scale : f32) -> Box<BumpMap> {
+        Box::new(BumpMap { material, height, scale })
+    }
+
+    fn displacement(&self, rec : &HitRecord) -> f32 {
+        let h = self.height.value(rec);
+        self.scale * (h.r() + h.g() + h.b()) / 3.0
+    }
+
+    fn bumped<'a>(&self, rec : &HitRecord<'a>) -> HitRecord<'a> {
+        if rec.dpdu.square_length() == 0.0 || rec.dpdv.square_length() == 0.0 {
+            return *rec;
+        }
+        // Finite differences over the lookup footprint, or a small fixed step
+        // when there isn't one.
+        let delta = if rec.footprint > 0.0 { 0.5 * rec.footprint } else { 0.0005 };
+        let displacement = self.displacement(rec);
+        // Solid textures in object space read object_p, so it moves as well.
+        // It's moved along the world space derivatives, which only match the
+        // object space ones when no rotation lies between the two.
+        let shifted_u = HitRecord { u : rec.u + delta, p : rec.p + &rec.dpdu * delta, object_p : rec.object_p + &rec.dpdu * delta, ..*rec };
+        let shifted_v = HitRecord { v : rec.v + delta, p : rec.p + &rec.dpdv * delta, object_p : rec.object_p + &rec.dpdv * delta, ..*rec };
+        let d_du = (self.displacement(&shifted_u) - displacement) / delta;
+        let d_dv = (self.displacement(&shifted_v) - displacement) / delta;
+
+        let normal = &rec.normal / rec.normal.length();
+        let dpdu = rec.dpdu + &normal * d_du;
+        let dpdv = rec.dpdv + &normal * d_dv;
+        let mut bumped = cross(&dpdu, &dpdv);
+        // uv can run either way round, keep to the side of the geometric normal.
+        if dot(&bumped, &normal) < 0.0 {
+            bumped = &bumped * -1.0;
+        }
+        let length = bumped.length();
+        if length == 0.0 || !length.is_finite() {
+            return *rec;
+        }
+        HitRecord { normal : &bumped / length, dpdu, dpdv, ..*rec }
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        rnd: &mut Random,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.material.scatter(ray, &self.bumped(rec), rnd, attenuation, scattered)
+    }
+
+    fn emitted(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec : &HitRecord) -> Vec3 {
+        self.material.albedo(rec)
+    }
+}
+
+// Tangent space normal map: red along dpdu, green along dpdv and blue along
+// the surface normal, each stored as 0.5 * (n + 1). Load the image with
+// `ColourSpace::Linear`. `strength` blends from the geometric normal (0) to
+// the full map (1).
+pub struct NormalMap {
+    pub material : Box<dyn Material>,
+    pub normals : Box<dyn Texture>,
+    pub strength : f32
+}
+
+impl NormalMap {
+    pub fn build(material : Box<dyn Material>, normals : Box<dyn Texture>, strength : f32) -> Box<NormalMap> {
+        Box::new(NormalMap { material, normals, strength })
+    }
+
+    fn mapped<'a>(&self, rec : &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = &rec.normal / rec.normal.length();
+        // Tangent frame following the uv directions, made orthonormal.
+        let tangent = rec.dpdu - &normal * dot(&normal, &rec.dpdu);
+        if tangent.square_length() == 0.0 || !tangent.square_length().is_finite() {
+            return *rec;
+        }
+        let tangent = &tangent / tangent.length();
+        let bitangent = rec.dpdv - &normal * dot(&normal, &rec.dpdv) - &tangent * dot(&tangent, &rec.dpdv);
+        let bitangent = if bitangent.square_length() > 0.0 {
+            &bitangent / bitangent.length()
+        } else {
+            cross(&normal, &tangent)
+        };
+
+        let texel = self.normals.value(rec);
+        let local = Vec3::from(
+            self.strength * (2.0 * texel.r() - 1.0),
+            self.strength * (2.0 * texel.g() - 1.0),
+            1.0 + self.strength * (2.0 * texel.b() - 2.0)
+        );
+        let mapped = &tangent * *local.x() + &bitangent * *local.y() + &normal * *local.z();
+        let length = mapped.length();
+        if length == 0.0 || !length.is_finite() {
+            return *rec;
+        }
+        HitRecord { normal : &mapped / length, ..*rec }
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        rnd: &mut Random,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.material.scatter(ray, &self.mapped(rec), rnd, attenuation, scattered)
+    }
+
+    fn emitted(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec : &HitRecord) -> Vec3 {
+        self.material.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{Hitable, Translate, XzRect};
+    use crate::texture::{ConstantTexture, SolidTexture, TextureSpace};
+
+    // Height rising along x.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+            Vec3::from(*p.x(), *p.x(), *p.x())
+        }
+    }
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    // A unit floor facing up, hit in the middle from above.
+    fn floor() -> XzRect {
+        XzRect { x0 : 0.0, x1 : 1.0, z0 : 0.0, z1 : 1.0, y : 0.0, material : grey() }
+    }
+
+    fn hit_floor(floor : &XzRect) -> HitRecord<'_> {
+        let ray = Ray { origin : Vec3::from(0.5, 1.0, 0.5), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        floor.hit(&ray, 0.001, f32::MAX).unwrap()
+    }
+
+    fn assert_close(a : &Vec3, b : &Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn bumps_tilt_the_normal_down_the_slope() {
+        let floor = floor();
+        let rec = hit_floor(&floor);
+        let flat = BumpMap { material : grey(), height : ConstantTexture::new_with_colour(Vec3::from(0.3, 0.3, 0.3)), scale : 1.0 };
+        assert_close(&flat.bumped(&rec).normal, &Vec3::from(0.0, 1.0, 0.0));
+        // Rising one unit per unit of x is a 45 degree slope.
+        let slope = BumpMap { material : grey(), height : Box::new(Ramp), scale : 1.0 };
+        let half = 0.5f32.sqrt();
+        assert_close(&slope.bumped(&rec).normal, &Vec3::from(-half, half, 0.0));
+    }
+
+    #[test]
+    fn object_space_bumps_tilt_the_normal() {
+        // Moved well away from the slope's origin, the object space position
+        // is what the height is read from.
+        let moved = Translate { offset : Vec3::from(10.0, 0.0, 0.0), obj : Box::new(floor()) };
+        let ray = Ray { origin : Vec3::from(10.5, 1.0, 0.5), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        let rec = moved.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert_close(&rec.object_p, &Vec3::from(0.5, 0.0, 0.5));
+        let slope = BumpMap { material : grey(), height : SolidTexture::build(Box::new(Ramp), TextureSpace::Object), scale : 1.0 };
+        let half = 0.5f32.sqrt();
+        assert_close(&slope.bumped(&rec).normal, &Vec3::from(-half, half, 0.0));
+    }
+
+    #[test]
+    fn normal_maps_follow_the_uv_frame() {
+        let floor = floor();
+        let rec = hit_floor(&floor);
+        let flat = NormalMap { material : grey(), normals : ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 1.0)), strength : 1.0 };
+        assert_close(&flat.mapped(&rec).normal, &Vec3::from(0.0, 1.0, 0.0));
+        // Red is along dpdu, which runs along x here, and green along dpdv.
+        let along_u = NormalMap { material : grey(), normals : ConstantTexture::new_with_colour(Vec3::from(1.0, 0.5, 0.5)), strength : 1.0 };
+        assert_close(&along_u.mapped(&rec).normal, &Vec3::from(1.0, 0.0, 0.0));
+        let along_v = NormalMap { material : grey(), normals : ConstantTexture::new_with_colour(Vec3::from(0.5, 1.0, 0.5)), strength : 1.0 };
+        assert_close(&along_v.mapped(&rec).normal, &Vec3::from(0.0, 0.0, 1.0));
+        // No strength leaves the surface as it was.
+        let off = NormalMap { material : grey(), normals : ConstantTexture::new_with_colour(Vec3::from(1.0, 0.5, 0.5)), strength : 0.0 };
+        assert_close(&off.mapped(&rec).normal, &Vec3::from(0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::rng::Random;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+// Triangle mesh with shading normals and uvs per triangle corner, sharing
+// one material. Each triangle becomes a hitable of its own pointing back at
+// the mesh, so they go into a `BvhTree` like any other geometry.
+pub struct Mesh {
+    pub positions : Vec<Vec3>,
+    pub triangles : Vec<MeshFace>,
+    pub material : Box<dyn Material>
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MeshFace {
+    pub points : [usize; 3],
+    pub normals : [Vec3; 3],
+    pub uvs : [(f32, f32); 3]
+}
+
+impl Mesh {
+    // One hitable per triangle, to add to the scene's list.
+    pub fn into_hitables(self) -> Vec<Box<dyn Hitable>> {
+        let count = self.triangles.len();
+        let mesh = Arc::new(self);
+        (0..count).map(|index| Box::new(MeshTriangle { mesh : mesh.clone(), index }) as Box<dyn Hitable>).collect()
+    }
+
+    // The triangles under a BVH of their own, as a single hitable.
+    pub fn into_bvh(self, rnd : &mut Random) -> Box<dyn Hitable> {
+        let mut hitables = self.into_hitables();
+        if hitables.is_empty() {
+            return Box::new(EmptyMesh);
+        }
+        BvhTree::build(&mut hitables, rnd, 0.0, 1.0).root
+    }
+}
+
+struct EmptyMesh;
+
+impl Hitable for EmptyMesh {
+    fn hit(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        Aabb::build(Vec3::default(), Vec3::default())
+    }
+}
+
+pub struct MeshTriangle {
+    mesh : Arc<Mesh>,
+    index : usize
+}
+
+impl MeshTriangle {
+    fn points(&self) -> [Vec3; 3] {
+        self.mesh.triangles[self.index].points.map(|i| self.mesh.positions[i])
+    }
+}
+
+impl Hitable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let points = self.points();
+        let (t, b1, b2) = intersect_triangle(ray, &points, t_min, t_max)?;
+        let face = &self.mesh.triangles[self.index];
+        let b0 = 1.0 - b1 - b2;
+
+        // Interpolated shading normal, kept on the geometric normal's side.
+        let geometric = cross(&(points[1] - points[0]), &(points[2] - points[0]));
+        let mut normal = &face.normals[0] * b0 + &face.normals[1] * b1 + &face.normals[2] * b2;
+        if dot(&normal, &geometric) < 0.0 {
+            normal = &normal * -1.0;
+        }
+        let length = normal.length();
+        let normal = if length > 0.0 && length.is_finite() { &normal / length } else { &geometric / geometric.length() };
+
+        let (dpdu, dpdv) = triangle_uv_derivatives(&points, &face.uvs);
+        Some(HitRecord::build(ray, t, normal, &*self.mesh.material)
+            .with_uv(b0 * face.uvs[0].0 + b1 * face.uvs[1].0 + b2 * face.uvs[2].0, b0 * face.uvs[0].1 + b1 * face.uvs[1].1 + b2 * face.uvs[2].1)
+            .with_derivatives(dpdu, dpdv))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        padded_bounds(&self.points())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::aov::{Aov, AovOutput, MAX_LIGHT_AOVS};
+use crate::filter::{Filter, FilterKind};
+use crate::tonemap::{OutputSpace, ToneMapper, ToneMapping};
+
+pub const USAGE : &str = "\
+usage: raytracer [options] > image.ppm
+       raytracer denoise <prefix> [options] > image.ppm
+
+  --scene <name>          scene to render, default final_render
+  --size <width>x<height> image size, default 1280x720
+  --samples <n>           samples per pixel, default 16384
+  --threads <n>           worker threads, default 24
+  --filter <kind>         box, tent, gaussian, mitchell (default) or lanczos
+  --filter-radius <r>     filter radius in pixels, defaults to the filter's own
+  --exposure <stops>      exposure compensation, default 0
+  --tone-mapper <kind>    clamp, reinhard, aces (default) or agx
+  --output <space>        srgb (default, PPM), linear or acescg (both PFM)
+  --denoise               denoise the image before writing it
+  --save-buffers <prefix> save the film buffers for `raytracer denoise`
+  --aov <pass>            add a pass: depth, normal, albedo, objectid,
+                          materialid, uv, emission, direct, indirect or light<n>,
+                          direct and indirect are the light found after one
+                          bounce and after two or more
+  --aov-output <path>     multi-layer EXR if it ends in .exr, otherwise the
+                          prefix for one PFM per pass, default passes.exr
+  --light-id <id>         object id of a light for the light<n> passes,
+                          light<n> is the n-th one given, up to 8 of them";
+
+// Everything about a render that isn't part of the scene.
+pub struct Options {
+    pub scene : String,
+    pub nx : usize,
+    pub ny : usize,
+    pub samples_per_pixel : i16,
+    pub thread_count : usize,
+    pub filter : Filter,
+    pub tone_mapping : ToneMapping,
+    pub denoise : bool,
+    pub save_buffers : Option<String>,
+    pub aovs : Vec<Aov>,
+    pub aov_output : AovOutput,
+    /// Object ids of the lights that get a pass of their own, used by Aov::Light(slot).
+    pub light_ids : Vec<u32>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene : String::from("final_render"),
+            nx : 1280,
+            ny : 720,
+            samples_per_pixel : 16384,
+            thread_count : 24,
+            filter : Filter::build(FilterKind::Mitchell { b : 1.0 / 3.0, c : 1.0 / 3.0 }, 2.0),
+            tone_mapping : ToneMapping::build(0.0, ToneMapper::AcesFilmic, OutputSpace::Srgb),
+            denoise : false,
+            save_buffers : None,
+            aovs : vec![],
+            aov_output : AovOutput::Exr(String::from("passes.exr")),
+            light_ids : vec![],
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args : &[String]) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut filter_radius = None;
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if flag == "--denoise" {
+                options.denoise = true;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--scene" => options.scene = value.clone(),
+                "--size" => {
+                    let (nx, ny) = value.split_once('x').ok_or_else(|| format!("bad size '{}'", value))?;
+                    options.nx = parse_number(flag, nx)?;
+                    options.ny = parse_number(flag, ny)?;
+                }
+                "--samples" => options.samples_per_pixel = parse_number(flag, value)?,
+                "--threads" => options.thread_count = parse_number(flag, value)?,
+                "--filter" => options.filter = default_filter(value)?,
+                "--filter-radius" => filter_radius = Some(parse_number(flag, value)?),
+                "--exposure" => options.tone_mapping.exposure = parse_number(flag, value)?,
+                "--tone-mapper" => options.tone_mapping.tone_mapper = tone_mapper(value)?,
+                "--output" => options.tone_mapping.output = output_space(value)?,
+                "--save-buffers" => options.save_buffers = Some(value.clone()),
+                "--aov" => options.aovs.push(Aov::from_name(value).ok_or_else(|| format!("unknown pass '{}'", value))?),
+                "--aov-output" => options.aov_output = if value.ends_with(".exr") {
+                    AovOutput::Exr(value.clone())
+                } else {
+                    AovOutput::Separate(value.clone())
+                },
+                "--light-id" => options.light_ids.push(parse_number(flag, value)?),
+                _ => return Err(format!("unknown option '{}'", flag)),
+            }
+        }
+
+        if options.light_ids.len() > MAX_LIGHT_AOVS {
+            return Err(format!("at most {} lights can have passes of their own", MAX_LIGHT_AOVS));
+        }
+        for aov in options.aovs.iter() {
+            if let Aov::Light(slot) = aov {
+                if *slot >= options.light_ids.len() {
+                    return Err(format!("{} needs at least {} --light-id", aov.name(), slot + 1));
+                }
+            }
+        }
+        if let Some(radius) = filter_radius {
+            options.filter = Filter::build(options.filter.kind, radius);
+        }
+        if options.nx == 0 || options.ny == 0 || options.samples_per_pixel <= 0 || options.thread_count == 0 {
+            return Err(String::from("size, samples and threads have to be positive"));
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T : std::str::FromStr>(flag : &str, value : &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("bad value '{}' for {}", value, flag))
+}
+
+fn default_filter(name : &str) -> Result<Filter, String> {
+    let filter = match name {
+        "box" => Filter::build(FilterKind::Box, 0.5),
+        "tent" => Filter::build(FilterKind::Tent, 1.0),
+        "gaussian" => Filter::build(FilterKind::Gaussian { alpha : 2.0 }, 1.5),
+        "mitchell" => Filter::build(FilterKind::Mitchell { b : 1.0 / 3.0, c : 1.0 / 3.0 }, 2.0),
+        "lanczos" => Filter::build(FilterKind::Lanczos, 3.0),
+        _ => return Err(format!("unknown filter '{}'", name)),
+    };
+    Ok(filter)
+}
+
+fn tone_mapper(name : &str) -> Result<ToneMapper, String> {
+    match name {
+        "clamp" => Ok(ToneMapper::Clamp),
+        "reinhard" => Ok(ToneMapper::Reinhard),
+        "aces" => Ok(ToneMapper::AcesFilmic),
+        "agx" => Ok(ToneMapper::AgX),
+        _ => Err(format!("unknown tone mapper '{}'", name)),
+    }
+}
+
+fn output_space(name : &str) -> Result<OutputSpace, String> {
+    match name {
+        "srgb" => Ok(OutputSpace::Srgb),
+        "linear" => Ok(OutputSpace::LinearRec709),
+        "acescg" => Ok(OutputSpace::AcesCg),
+        _ => Err(format!("unknown output space '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args : &str) -> Result<Options, String> {
+        let args : Vec<String> = args.split_whitespace().map(String::from).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn light_passes_need_a_light() {
+        assert!(parse("--aov light0 --light-id 4").is_ok());
+        assert!(parse("--aov light1 --light-id 4").is_err());
+        assert!(parse("--aov light8 --light-id 1").is_err());
+        assert!(parse("--light-id 1 --light-id 2 --light-id 3 --light-id 4 --light-id 5 --light-id 6 --light-id 7 --light-id 8").is_ok());
+        assert!(parse("--light-id 1 --light-id 2 --light-id 3 --light-id 4 --light-id 5 --light-id 6 --light-id 7 --light-id 8 --light-id 9").is_err());
+    }
+
+    #[test]
+    fn pass_names_round_trip() {
+        let aovs = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::ObjectId, Aov::MaterialId, Aov::Uv,
+            Aov::Emission, Aov::Direct, Aov::Indirect, Aov::Light(3)];
+        for aov in aovs.iter() {
+            assert_eq!(Aov::from_name(&aov.name()), Some(*aov));
+        }
+    }
+
+    #[test]
+    fn settings_are_read() {
+        let options = parse("--scene cornell_box --size 64x48 --samples 8 --threads 1 --filter gaussian --filter-radius 2 \
+            --tone-mapper agx --output linear --denoise --aov-output passes").unwrap();
+        assert_eq!(options.scene, "cornell_box");
+        assert_eq!((options.nx, options.ny, options.samples_per_pixel, options.thread_count), (64, 48, 8, 1));
+        assert_eq!(options.filter.radius, 2.0);
+        assert_eq!(options.tone_mapping.tone_mapper, ToneMapper::AgX);
+        assert_eq!(options.tone_mapping.output, OutputSpace::LinearRec709);
+        assert!(options.denoise);
+        assert!(matches!(options.aov_output, AovOutput::Separate(_)));
+        assert!(parse("--size 64").is_err());
+        assert!(parse("--filter sinc").is_err());
+        assert!(parse("--samples").is_err());
+    }
+}
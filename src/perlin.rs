@@ -5,13 +5,32 @@ pub struct Perlin {
     perm_x : [usize; 256],
     perm_y : [usize; 256],
     perm_z : [usize; 256],
-    random_floats : [Vec3; 256]
+    random_floats : [Vec3; 256],
+    // Extra tables for the 4D noise.
+    perm_w : [usize; 256],
+    gradients_4d : [[f32; 4]; 256]
+}
+
+// Octave settings for the fractal sums: each octave is `lacunarity` times the
+// frequency and `gain` times the amplitude of the one before.
+#[derive(Copy, Clone, Debug)]
+pub struct Fractal {
+    pub octaves : u32,
+    pub lacunarity : f32,
+    pub gain : f32
+}
+
+impl Default for Fractal {
+    // Same as `Perlin::turb`, seven octaves doubling in frequency and halving in amplitude.
+    fn default() -> Self {
+        Fractal { octaves : 7, lacunarity : 2.0, gain : 0.5 }
+    }
 }
 
 impl Perlin {
     pub fn turb(&self, p : &Vec3, depth : i32) -> f32 {
         let mut accum = 0.0;
-        let mut temp_p : Vec3 = p.clone();
+        let mut temp_p : Vec3 = *p;
         let mut weight = 1.0;
 
         for _ in 0..depth {
@@ -30,13 +49,13 @@ impl Perlin {
         let j = p.y().floor() as i32;
         let k = p.z().floor() as i32;
         let mut c = [[[Vec3::from(0.0,0.0,0.0);2];2];2];
-        for di in 0..2 {
+        for (di, plane) in c.iter_mut().enumerate() {
             let i_ind = ((i + di as i32) & 255) as usize;
-            for dj in 0..2 {
+            for (dj, row) in plane.iter_mut().enumerate() {
                 let j_ind = ((j + dj as i32) & 255) as usize;
-                for dk in 0..2 {
+                for (dk, corner) in row.iter_mut().enumerate() {
                     let k_ind = ((k + dk as i32) & 255) as usize;
-                    c[di][dj][dk] = self.random_floats[self.perm_x[i_ind] ^ self.perm_y[j_ind] ^ self.perm_z[k_ind]];
+                    *corner = self.random_floats[self.perm_x[i_ind] ^ self.perm_y[j_ind] ^ self.perm_z[k_ind]];
                 }
             }
         }
@@ -44,27 +63,106 @@ impl Perlin {
         Perlin::interpolate(u, v, w, c)
     }
 
+    // 4D gradient noise, w is usually time so the pattern evolves rather than
+    // slides. Roughly in [-1,1].
+    pub fn noise4(&self, p : &Vec3, w : f32) -> f32 {
+        let point = [*p.x(), *p.y(), *p.z(), w];
+        let cell = point.map(|x| x.floor() as i32);
+        let offset = [point[0] - point[0].floor(), point[1] - point[1].floor(), point[2] - point[2].floor(), w - w.floor()];
+        let smooth = offset.map(|t| t * t * (3.0 - 2.0 * t));
+
+        let mut accum = 0.0;
+        for corner in 0..16 {
+            let bits = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1, (corner >> 3) & 1];
+            let hash = self.perm_x[((cell[0] + bits[0]) & 255) as usize]
+                ^ self.perm_y[((cell[1] + bits[1]) & 255) as usize]
+                ^ self.perm_z[((cell[2] + bits[2]) & 255) as usize]
+                ^ self.perm_w[((cell[3] + bits[3]) & 255) as usize];
+            let gradient = &self.gradients_4d[hash];
+            let mut weight = 1.0;
+            let mut projection = 0.0;
+            for axis in 0..4 {
+                let b = bits[axis] as f32;
+                weight *= b * smooth[axis] + (1.0 - b) * (1.0 - smooth[axis]);
+                projection += gradient[axis] * (offset[axis] - b);
+            }
+            accum += weight * projection;
+        }
+        accum
+    }
+
+    // Fractal Brownian motion, a signed sum of octaves of 4D noise.
+    pub fn fbm(&self, p : &Vec3, w : f32, fractal : &Fractal) -> f32 {
+        let mut accum = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..fractal.octaves {
+            accum += amplitude * self.noise4(&(p * frequency), w * frequency);
+            amplitude *= fractal.gain;
+            frequency *= fractal.lacunarity;
+        }
+        accum
+    }
+
+    // Musgrave's ridged multifractal: octaves of (offset - |noise|)^2, each
+    // weighted by the one before so detail gathers along the ridges. An
+    // offset of 1 gives values in [0, ~2].
+    pub fn ridged(&self, p : &Vec3, w : f32, fractal : &Fractal, offset : f32) -> f32 {
+        let mut accum = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut weight = 1.0;
+        for _ in 0..fractal.octaves {
+            let signal = offset - self.noise4(&(p * frequency), w * frequency).abs();
+            let signal = signal * signal * weight;
+            weight = (2.0 * signal).clamp(0.0, 1.0);
+            accum += amplitude * signal;
+            amplitude *= fractal.gain;
+            frequency *= fractal.lacunarity;
+        }
+        accum
+    }
+
     pub fn build(rnd : &mut Random) -> Perlin {
-        Perlin {
-            perm_x : Perlin::perlin_generate_perm(rnd),
-            perm_y : Perlin::perlin_generate_perm(rnd),
-            perm_z : Perlin::perlin_generate_perm(rnd),
-            random_floats : Perlin::perlin_generate(rnd),
+        let perm_x = Perlin::perlin_generate_perm(rnd);
+        let perm_y = Perlin::perlin_generate_perm(rnd);
+        let perm_z = Perlin::perlin_generate_perm(rnd);
+        let random_floats = Perlin::perlin_generate(rnd);
+
+        // The 4D tables come from a generator seeded off the others, so a
+        // Perlin takes the same numbers from the scene's generator as it
+        // always has and scenes built after it don't change.
+        let seed = perm_x.iter().chain(perm_y.iter()).chain(perm_z.iter())
+            .fold(17u64, |hash, &v| hash.wrapping_mul(31).wrapping_add(v as u64));
+        let mut local = Random::create_with_seed(seed);
+        let perm_w = Perlin::perlin_generate_perm(&mut local);
+        let mut gradients_4d = [[0.0f32; 4]; 256];
+        for gradient in gradients_4d.iter_mut() {
+            loop {
+                let g = [0; 4].map(|_| 2.0 * local.gen() - 1.0);
+                let length = g.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if length > 0.01 && length <= 1.0 {
+                    *gradient = g.map(|x| x / length);
+                    break;
+                }
+            }
         }
+
+        Perlin { perm_x, perm_y, perm_z, random_floats, perm_w, gradients_4d }
     }
 
     fn perlin_generate(rnd : &mut Random) -> [Vec3; 256] {
         let mut array : [Vec3; 256] = [Vec3::from(0.0,0.0,0.0); 256];
-        for index in 0..array.len() {
-            array[index] = (&Vec3::from(rnd.gen() - 0.5, rnd.gen() - 0.5, rnd.gen() - 0.5)*2.0).make_normalised();
+        for value in array.iter_mut() {
+            *value = (&Vec3::from(rnd.gen() - 0.5, rnd.gen() - 0.5, rnd.gen() - 0.5)*2.0).make_normalised();
         }
         array
     }
 
     fn perlin_generate_perm(rnd : &mut Random) -> [usize; 256] {
         let mut array : [usize; 256] = [0;256];
-        for index in 0..array.len() {
-            array[index] = index;
+        for (index, value) in array.iter_mut().enumerate() {
+            *value = index;
         }
         Perlin::permute(&mut array, rnd);
         array
@@ -73,9 +171,7 @@ impl Perlin {
     fn permute(array : &mut [usize; 256], rnd : &mut Random) {
         for index in (0..256).rev() {
             let target = (rnd.gen() * (index + 1) as f32) as usize;
-            let tmp = array[index];
-            array[index] = array[target];
-            array[target] = tmp;
+            array.swap(index, target);
         }
     }
 
@@ -84,21 +180,155 @@ impl Perlin {
         let vv = v*v*(3.0 - 2.0*v);
         let ww = w*w*(3.0 - 2.0*w);
         let mut accum = 0.0;
-        for i in 0..2 {
+        for (i, plane) in c.iter().enumerate() {
             let ix = i as f32;
             let i_component = ix*uu + (1.0 - ix) * (1.0 - uu);
-            for j in 0..2 {
+            for (j, row) in plane.iter().enumerate() {
                 let jx = j as f32;
                 let j_component = jx*vv + (1.0-jx)*(1.0-vv);
-                for k in 0..2 {
+                for (k, corner) in row.iter().enumerate() {
                     let kx = k as f32;
                     let weight_v = Vec3::from(uu - ix, vv - jx, ww - kx);
                     let k_component = kx*ww + (1.0-kx)*(1.0-ww);
-                    accum += i_component * j_component * k_component * dot(&c[i][j][k], &weight_v);
+                    accum += i_component * j_component * k_component * dot(corner, &weight_v);
                 }
             }
         }
 
         accum
     }
-}
\ No newline at end of file
+}
+
+// Which distances a `Worley` lookup returns.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WorleyFeature {
+    /// Distance to the closest feature point, round cells.
+    F1,
+    /// Distance to the second closest point.
+    F2,
+    /// F2 - F1, dark along the cell borders.
+    Edges,
+}
+
+// Worley (cellular) noise, one randomly placed feature point per unit cell.
+pub struct Worley {
+    perm : [usize; 256],
+    points : [Vec3; 256]
+}
+
+impl Worley {
+    pub fn build(rnd : &mut Random) -> Worley {
+        let perm = Perlin::perlin_generate_perm(rnd);
+        let mut points = [Vec3::from(0.0, 0.0, 0.0); 256];
+        for point in points.iter_mut() {
+            *point = Vec3::from(rnd.gen(), rnd.gen(), rnd.gen());
+        }
+        Worley { perm, points }
+    }
+
+    fn feature_point(&self, i : i32, j : i32, k : i32) -> Vec3 {
+        let hash = self.perm[(self.perm[(self.perm[(i & 255) as usize] + (j & 255) as usize) & 255] + (k & 255) as usize) & 255];
+        Vec3::from(i as f32, j as f32, k as f32) + self.points[hash]
+    }
+
+    // (F1, F2), distances to the two closest feature points. Cells are
+    // searched in shells around p's own until no cell further out can hold a
+    // closer point. The 3x3x3 block on its own can get F2 wrong near a cell
+    // corner, the second closest point is at most sqrt(6) away so the search
+    // never goes past the third shell.
+    pub fn distances(&self, p : &Vec3) -> (f32, f32) {
+        let (i, j, k) = (p.x().floor() as i32, p.y().floor() as i32, p.z().floor() as i32);
+        // Distance from p to the nearest face of its own cell.
+        let margin = [*p.x() - i as f32, *p.y() - j as f32, *p.z() - k as f32].iter()
+            .fold(0.5f32, |margin, &f| margin.min(f).min(1.0 - f));
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        let mut shell : i32 = 0;
+        loop {
+            for di in -shell..=shell {
+                for dj in -shell..=shell {
+                    for dk in -shell..=shell {
+                        if di.abs().max(dj.abs()).max(dk.abs()) != shell {
+                            continue;
+                        }
+                        let distance = (self.feature_point(i + di, j + dj, k + dk) - *p).square_length();
+                        if distance < f1 {
+                            f2 = f1;
+                            f1 = distance;
+                        } else if distance < f2 {
+                            f2 = distance;
+                        }
+                    }
+                }
+            }
+            // Every cell in the next shell is at least this far away.
+            let reach = shell as f32 + margin;
+            if f2 <= reach * reach {
+                break;
+            }
+            shell += 1;
+        }
+        (f1.sqrt(), f2.sqrt())
+    }
+
+    pub fn feature(&self, p : &Vec3, feature : WorleyFeature) -> f32 {
+        let (f1, f2) = self.distances(p);
+        match feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::Edges => f2 - f1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(count : usize) -> Vec<Vec3> {
+        let mut rnd = Random::create_with_seed(11);
+        (0..count).map(|_| &Vec3::from(rnd.gen() - 0.5, rnd.gen() - 0.5, rnd.gen() - 0.5) * 40.0).collect()
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let mut rnd = Random::create_with_seed(2);
+        let perlin = Perlin::build(&mut rnd);
+        let fractal = Fractal::default();
+        let amplitude : f32 = (0..fractal.octaves).map(|octave| fractal.gain.powi(octave as i32)).sum();
+        for (index, p) in points(5000).iter().enumerate() {
+            let w = index as f32 * 0.37;
+            assert!(perlin.noise(p).abs() <= 1.0);
+            assert!(perlin.noise4(p, w).abs() <= 1.0);
+            assert!(perlin.fbm(p, w, &fractal).abs() <= amplitude);
+            let ridged = perlin.ridged(p, w, &fractal, 1.0);
+            assert!((0.0..=amplitude).contains(&ridged));
+        }
+        // Noise is zero on the lattice.
+        assert_eq!(perlin.noise4(&Vec3::from(3.0, -2.0, 7.0), 1.0), 0.0);
+    }
+
+    #[test]
+    fn worley_finds_the_closest_points() {
+        let mut rnd = Random::create_with_seed(4);
+        let worley = Worley::build(&mut rnd);
+        // Near cell corners as well as anywhere.
+        let mut samples = points(2000);
+        samples.extend(points(2000).iter().map(|p| Vec3::from(p.x().round() + 0.01, p.y().round() - 0.01, p.z().round() + 0.02)));
+        for p in samples.iter() {
+            let (i, j, k) = (p.x().floor() as i32, p.y().floor() as i32, p.z().floor() as i32);
+            let mut all : Vec<f32> = Vec::with_capacity(343);
+            for di in -3..=3 {
+                for dj in -3..=3 {
+                    for dk in -3..=3 {
+                        all.push((worley.feature_point(i + di, j + dj, k + dk) - *p).length());
+                    }
+                }
+            }
+            all.sort_by(|a, b| a.total_cmp(b));
+            let (f1, f2) = worley.distances(p);
+            assert!((f1 - all[0]).abs() < 1e-5 && (f2 - all[1]).abs() < 1e-5, "{:?}", p);
+            assert!(f1 <= 3.0f32.sqrt() && f2 <= 6.0f32.sqrt());
+            assert!(worley.feature(p, WorleyFeature::Edges) >= 0.0);
+        }
+    }
+}
@@ -0,0 +1,718 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::tonemap::{srgb_eotf, srgb_oetf};
+use crate::vec3::*;
+
+use std::fs;
+use std::io;
+
+// Points in a cloud are stored this many to a leaf of its BVH.
+const LEAF_SIZE : usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position : Vec3,
+    pub radius : f32,
+    // Linear, handed to the material through `HitRecord::colour`.
+    pub colour : Vec3
+}
+
+impl Particle {
+    pub fn build(position : Vec3, radius : f32) -> Particle {
+        Particle { position, radius, colour : Vec3::from(1.0, 1.0, 1.0) }
+    }
+
+    pub fn with_colour(self, colour : Vec3) -> Particle {
+        Particle { colour, ..self }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PointShape {
+    Sphere,
+    // Flat discs turned to face each ray, like splats.
+    Disc
+}
+
+// Flattened BVH node. Leaves hold `count` points from `start`; inner nodes
+// have a count of zero, their first child straight after them and the
+// second at `start`.
+struct Node {
+    min : [f32; 3],
+    max : [f32; 3],
+    start : u32,
+    count : u32
+}
+
+// Lots of small spheres or discs sharing one material, each with its own
+// radius and colour. Points are kept as plain arrays under a BVH of the
+// cloud's own rather than as a hitable apiece, so millions of them fit. The
+// material sees each point's colour through `GeometryColour`.
+pub struct PointCloud {
+    positions : Vec<[f32; 3]>,
+    // Per point, or empty when they're all `radius`.
+    radii : Vec<f32>,
+    radius : f32,
+    // sRGB encoded per point, or empty when they're all white.
+    colours : Vec<[u8; 3]>,
+    shape : PointShape,
+    material : Box<dyn Material>,
+    nodes : Vec<Node>
+}
+
+fn encode(colour : &Vec3) -> [u8; 3] {
+    let channel = |c : f32| (srgb_oetf(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+    [channel(*colour.r()), channel(*colour.g()), channel(*colour.b())]
+}
+
+fn decode(colour : &[u8; 3]) -> Vec3 {
+    let channel = |c : u8| srgb_eotf(c as f32 / 255.0);
+    Vec3::from(channel(colour[0]), channel(colour[1]), channel(colour[2]))
+}
+
+fn invalid(msg : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl PointCloud {
+    pub fn build(mut particles : Vec<Particle>, material : Box<dyn Material>) -> Box<PointCloud> {
+        let mut nodes = Vec::with_capacity(2 * particles.len() / LEAF_SIZE + 1);
+        if !particles.is_empty() {
+            build_nodes(&mut particles, 0, &mut nodes);
+        }
+
+        let radius = particles.first().map_or(0.0, |p| p.radius);
+        let radii = if particles.iter().all(|p| p.radius == radius) {
+            Vec::new()
+        } else {
+            particles.iter().map(|p| p.radius).collect()
+        };
+        let colours : Vec<[u8; 3]> = particles.iter().map(|p| encode(&p.colour)).collect();
+        let colours = if colours.iter().all(|c| *c == [255, 255, 255]) { Vec::new() } else { colours };
+
+        Box::new(PointCloud {
+            positions : particles.iter().map(|p| [*p.position.x(), *p.position.y(), *p.position.z()]).collect(),
+            radii,
+            radius,
+            colours,
+            shape : PointShape::Sphere,
+            material,
+            nodes
+        })
+    }
+
+    pub fn with_shape(mut self : Box<Self>, shape : PointShape) -> Box<Self> {
+        self.shape = shape;
+        self
+    }
+
+    // Loads a `.ply` or `.csv` particle file, see `read_ply` and `read_csv`.
+    // Points the file gives no radius get `radius`.
+    pub fn load(path : &str, radius : f32, material : Box<dyn Material>) -> io::Result<Box<PointCloud>> {
+        let lower = path.to_lowercase();
+        let particles = if lower.ends_with(".ply") {
+            read_ply(path, radius)?
+        } else if lower.ends_with(".csv") {
+            read_csv(path, radius)?
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: expected a .ply or .csv point cloud", path)));
+        };
+        Ok(PointCloud::build(particles, material))
+    }
+
+    fn centre(&self, index : usize) -> Vec3 {
+        let [x, y, z] = self.positions[index];
+        Vec3::from(x, y, z)
+    }
+
+    fn radius_of(&self, index : usize) -> f32 {
+        if self.radii.is_empty() { self.radius } else { self.radii[index] }
+    }
+
+    fn intersect(&self, index : usize, ray : &Ray, t_min : f32, t_max : f32) -> Option<f32> {
+        let centre = self.centre(index);
+        let radius = self.radius_of(index);
+        let a = ray.direction.square_length();
+        match self.shape {
+            PointShape::Sphere => {
+                let oc = ray.origin - centre;
+                let b = dot(&oc, &ray.direction);
+                let c = oc.square_length() - radius * radius;
+                let discriminant = b * b - a * c;
+                if discriminant <= 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                [(-b - root) / a, (-b + root) / a].iter().copied().find(|t| *t > t_min && *t < t_max)
+            },
+            PointShape::Disc => {
+                // A disc turns to face every ray, so one bounced off it would
+                // find it again. Rays starting on a disc pass through it.
+                let to_centre = centre - ray.origin;
+                if to_centre.square_length() <= radius * radius {
+                    return None;
+                }
+                let t = dot(&to_centre, &ray.direction) / a;
+                if t <= t_min || t >= t_max {
+                    return None;
+                }
+                let offset = ray.point_at_parameter(t) - centre;
+                if offset.square_length() <= radius * radius { Some(t) } else { None }
+            }
+        }
+    }
+
+    fn record(&self, index : usize, ray : &Ray, t : f32) -> HitRecord<'_> {
+        let p = ray.point_at_parameter(t);
+        let centre = self.centre(index);
+        let radius = self.radius_of(index);
+        let (normal, (u, v), (dpdu, dpdv)) = match self.shape {
+            PointShape::Sphere => (&(p - centre) / radius, get_sphere_uv(&centre, &p), get_sphere_dpduv(&centre, &p)),
+            PointShape::Disc => {
+                let normal = &(Vec3::default() - ray.direction) / ray.direction.length();
+                let (tangent, bitangent) = orthonormal_basis(&normal);
+                let offset = p - centre;
+                let uv = (0.5 + dot(&offset, &tangent) / (2.0 * radius), 0.5 + dot(&offset, &bitangent) / (2.0 * radius));
+                (normal, uv, (&tangent * (2.0 * radius), &bitangent * (2.0 * radius)))
+            }
+        };
+        let record = HitRecord::build(ray, t, normal, &*self.material)
+            .with_uv(u, v)
+            .with_derivatives(dpdu, dpdv);
+        match self.colours.get(index) {
+            Some(colour) => record.with_colour(decode(colour)),
+            None => record
+        }
+    }
+}
+
+fn particle_bounds(particle : &Particle) -> ([f32; 3], [f32; 3]) {
+    let (p, r) = (&particle.position, particle.radius);
+    ([p.x() - r, p.y() - r, p.z() - r], [p.x() + r, p.y() + r, p.z() + r])
+}
+
+// Splits at the median along the widest spread of centres, reordering the
+// particles so every node covers a contiguous run.
+fn build_nodes(particles : &mut [Particle], offset : usize, nodes : &mut Vec<Node>) -> usize {
+    let (mut min, mut max) = particle_bounds(&particles[0]);
+    let (mut low, mut high) = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+    for particle in particles.iter() {
+        let (pmin, pmax) = particle_bounds(particle);
+        for axis in 0..3 {
+            min[axis] = min[axis].min(pmin[axis]);
+            max[axis] = max[axis].max(pmax[axis]);
+            low[axis] = low[axis].min(*particle.position.get(axis));
+            high[axis] = high[axis].max(*particle.position.get(axis));
+        }
+    }
+
+    let index = nodes.len();
+    nodes.push(Node { min, max, start : offset as u32, count : particles.len() as u32 });
+    if particles.len() <= LEAF_SIZE {
+        return index;
+    }
+
+    let axis = (0..3).max_by(|a, b| (high[*a] - low[*a]).total_cmp(&(high[*b] - low[*b]))).unwrap_or(0);
+    let middle = particles.len() / 2;
+    particles.select_nth_unstable_by(middle, |a, b| a.position.get(axis).total_cmp(b.position.get(axis)));
+    let (left, right) = particles.split_at_mut(middle);
+    build_nodes(left, offset, nodes);
+    let second = build_nodes(right, offset + middle, nodes);
+    nodes[index].start = second as u32;
+    nodes[index].count = 0;
+    index
+}
+
+impl Hitable for PointCloud {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let origin = [*ray.origin.x(), *ray.origin.y(), *ray.origin.z()];
+        let inverse = [1.0 / ray.direction.x(), 1.0 / ray.direction.y(), 1.0 / ray.direction.z()];
+        let crosses = |node : &Node, t_max : f32| {
+            let (mut near, mut far) = (t_min, t_max);
+            for axis in 0..3 {
+                let t0 = (node.min[axis] - origin[axis]) * inverse[axis];
+                let t1 = (node.max[axis] - origin[axis]) * inverse[axis];
+                let (t0, t1) = if inverse[axis] < 0.0 { (t1, t0) } else { (t0, t1) };
+                near = near.max(t0);
+                far = far.min(t1);
+                if far < near {
+                    return false;
+                }
+            }
+            true
+        };
+
+        let mut closest = t_max;
+        let mut nearest = None;
+        // Median splits keep the tree balanced, so this is deep enough for
+        // far more points than fit in memory.
+        let mut stack = [0u32; 64];
+        let mut depth = 1;
+        while depth > 0 {
+            depth -= 1;
+            let index = stack[depth] as usize;
+            let node = &self.nodes[index];
+            if !crosses(node, closest) {
+                continue;
+            }
+            if node.count > 0 {
+                for point in node.start as usize..(node.start + node.count) as usize {
+                    if let Some(t) = self.intersect(point, ray, t_min, closest) {
+                        closest = t;
+                        nearest = Some(point);
+                    }
+                }
+            } else {
+                stack[depth] = node.start;
+                stack[depth + 1] = index as u32 + 1;
+                depth += 2;
+            }
+        }
+        nearest.map(|point| self.record(point, ray, closest))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => Aabb::build(Vec3::from(root.min[0], root.min[1], root.min[2]), Vec3::from(root.max[0], root.max[1], root.max[2])),
+            None => Aabb::build(Vec3::default(), Vec3::default()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PlyType { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+impl PlyType {
+    fn parse(name : &str) -> Option<PlyType> {
+        Some(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+
+    // What a colour channel of this type reads as at full brightness.
+    fn full_scale(self) -> f32 {
+        match self {
+            PlyType::U8 => 255.0,
+            PlyType::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+struct PlyProperty {
+    name : String,
+    kind : PlyType,
+    // Type of the item count for list properties.
+    count : Option<PlyType>
+}
+
+struct PlyElement {
+    name : String,
+    count : usize,
+    properties : Vec<PlyProperty>
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PlyFormat { Ascii, LittleEndian, BigEndian }
+
+// Values from the body of a PLY file, as text or binary.
+struct PlyReader<'a> {
+    format : PlyFormat,
+    data : &'a [u8],
+    position : usize,
+    path : &'a str
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, kind : PlyType) -> io::Result<f64> {
+        if self.format == PlyFormat::Ascii {
+            while self.position < self.data.len() && self.data[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            }
+            let start = self.position;
+            while self.position < self.data.len() && !self.data[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            }
+            let word = std::str::from_utf8(&self.data[start..self.position]).unwrap_or("");
+            return word.parse::<f64>().map_err(|_| invalid(format!("{}: bad value {:?} in the data", self.path, word)));
+        }
+
+        let size = kind.size();
+        let bytes = self.data.get(self.position..self.position + size)
+            .ok_or_else(|| invalid(format!("{}: data ends early", self.path)))?;
+        self.position += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == PlyFormat::BigEndian {
+            buffer[..size].reverse();
+        }
+        let [a, b, c, d, ..] = buffer;
+        Ok(match kind {
+            PlyType::I8 => a as i8 as f64,
+            PlyType::U8 => a as f64,
+            PlyType::I16 => i16::from_le_bytes([a, b]) as f64,
+            PlyType::U16 => u16::from_le_bytes([a, b]) as f64,
+            PlyType::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+            PlyType::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+            PlyType::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+            PlyType::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+// Stanford PLY, ascii or binary either way round. Reads the `vertex`
+// element's x, y and z, with `radius` and red, green and blue (or r, g, b)
+// if it has them. Colours are taken as sRGB, whole numbers running up to
+// the top of their type and floats to 1. Other elements are skipped.
+pub fn read_ply(path : &str, radius : f32) -> io::Result<Vec<Particle>> {
+    let data = fs::read(path)?;
+    let header_error = |line : usize, msg : &str| invalid(format!("{}:{}: {}", path, line, msg));
+
+    let mut format = None;
+    let mut elements : Vec<PlyElement> = Vec::new();
+    let mut position = 0;
+    let mut line_number = 0;
+    loop {
+        let end = data[position..].iter().position(|&b| b == b'\n')
+            .ok_or_else(|| header_error(line_number + 1, "no end_header"))?;
+        let line = String::from_utf8_lossy(&data[position..position + end]).into_owned();
+        position += end + 1;
+        line_number += 1;
+        let words : Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["ply"] if line_number == 1 => {},
+            _ if line_number == 1 => return Err(header_error(1, "not a PLY file")),
+            ["format", kind, _] => {
+                format = Some(match *kind {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    _ => return Err(header_error(line_number, "unknown format")),
+                });
+            },
+            ["element", name, count] => {
+                let count = count.parse::<usize>().map_err(|_| header_error(line_number, "bad element count"))?;
+                elements.push(PlyElement { name : name.to_string(), count, properties : Vec::new() });
+            },
+            ["property", "list", count, kind, name] => {
+                let element = elements.last_mut().ok_or_else(|| header_error(line_number, "property outside an element"))?;
+                let (count, kind) = PlyType::parse(count).zip(PlyType::parse(kind))
+                    .ok_or_else(|| header_error(line_number, "unknown property type"))?;
+                element.properties.push(PlyProperty { name : name.to_string(), kind, count : Some(count) });
+            },
+            ["property", kind, name] => {
+                let element = elements.last_mut().ok_or_else(|| header_error(line_number, "property outside an element"))?;
+                let kind = PlyType::parse(kind).ok_or_else(|| header_error(line_number, "unknown property type"))?;
+                element.properties.push(PlyProperty { name : name.to_string(), kind, count : None });
+            },
+            ["end_header"] => break,
+            _ => {},
+        }
+    }
+    let format = format.ok_or_else(|| invalid(format!("{}: no format line", path)))?;
+
+    let vertices = elements.iter().position(|e| e.name == "vertex")
+        .ok_or_else(|| invalid(format!("{}: no vertex element", path)))?;
+    let find = |names : &[&str]| elements[vertices].properties.iter()
+        .position(|p| p.count.is_none() && names.contains(&p.name.as_str()));
+    let (x, y, z) = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => return Err(invalid(format!("{}: vertices need x, y and z", path))),
+    };
+    let radius_index = find(&["radius"]);
+    let colour_indices = match (find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"])) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+
+    let mut reader = PlyReader { format, data : &data, position, path };
+    let mut particles = Vec::with_capacity(elements[vertices].count);
+    let mut values = Vec::new();
+    for (e, element) in elements.iter().enumerate() {
+        for _ in 0..element.count {
+            values.clear();
+            for property in &element.properties {
+                match property.count {
+                    None => values.push(reader.read(property.kind)?),
+                    Some(count) => {
+                        let items = reader.read(count)? as usize;
+                        for _ in 0..items {
+                            reader.read(property.kind)?;
+                        }
+                        values.push(0.0);
+                    },
+                }
+            }
+            if e != vertices {
+                continue;
+            }
+            let position = Vec3::from(values[x] as f32, values[y] as f32, values[z] as f32);
+            let mut particle = Particle::build(position, radius_index.map_or(radius, |r| values[r] as f32));
+            if let Some(indices) = colour_indices {
+                let channel = |i : usize| {
+                    let property = &element.properties[indices[i]];
+                    srgb_eotf((values[indices[i]] as f32 / property.kind.full_scale()).clamp(0.0, 1.0))
+                };
+                particle = particle.with_colour(Vec3::from(channel(0), channel(1), channel(2)));
+            }
+            particles.push(particle);
+        }
+        // Nothing after the vertices is needed.
+        if e == vertices {
+            break;
+        }
+    }
+    Ok(particles)
+}
+
+// Comma separated rows of x, y, z with optional radius and r, g, b. A
+// header row naming the columns (x, y, z, radius, red/r, green/g, blue/b)
+// lets them come in any order alongside others; without one the columns go
+// by count: x y z, x y z radius, x y z r g b or x y z radius r g b. Colours
+// are sRGB, in 0-255 if any of them is above 1 and 0-1 otherwise. Blank
+// lines and lines starting `#` are skipped.
+pub fn read_csv(path : &str, radius : f32) -> io::Result<Vec<Particle>> {
+    let text = fs::read_to_string(path)?;
+    let error = |line : usize, msg : &str| invalid(format!("{}:{}: {}", path, line, msg));
+
+    // Where to find x, y, z, radius, red, green and blue in a row.
+    let mut columns : Option<[Option<usize>; 7]> = None;
+    let mut rows : Vec<(usize, [f32; 7])> = Vec::new();
+    let mut has_colour = false;
+    let mut bright = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields : Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let parsed : Result<Vec<f32>, _> = fields.iter().map(|f| f.parse::<f32>()).collect();
+        let values = match parsed {
+            Ok(values) => values,
+            Err(_) if columns.is_none() && rows.is_empty() => {
+                let named = |names : &[&str]| fields.iter().position(|f| names.contains(&f.to_lowercase().as_str()));
+                let header = [named(&["x"]), named(&["y"]), named(&["z"]), named(&["radius"]),
+                    named(&["red", "r"]), named(&["green", "g"]), named(&["blue", "b"])];
+                if header[..3].iter().any(|c| c.is_none()) {
+                    return Err(error(number + 1, "header needs x, y and z columns"));
+                }
+                if header[4..].iter().any(|c| c.is_some()) && header[4..].iter().any(|c| c.is_none()) {
+                    return Err(error(number + 1, "header needs all of red, green and blue or none"));
+                }
+                columns = Some(header);
+                continue;
+            },
+            Err(_) => return Err(error(number + 1, "expected numbers")),
+        };
+        let layout = match columns {
+            Some(layout) => layout,
+            None => {
+                let layout = match values.len() {
+                    3 => [Some(0), Some(1), Some(2), None, None, None, None],
+                    4 => [Some(0), Some(1), Some(2), Some(3), None, None, None],
+                    6 => [Some(0), Some(1), Some(2), None, Some(3), Some(4), Some(5)],
+                    7 => [Some(0), Some(1), Some(2), Some(3), Some(4), Some(5), Some(6)],
+                    _ => return Err(error(number + 1, "expected 3, 4, 6 or 7 columns")),
+                };
+                columns = Some(layout);
+                layout
+            },
+        };
+        let mut row = [radius, 0.0, 0.0, radius, 1.0, 1.0, 1.0];
+        for (slot, column) in layout.iter().enumerate() {
+            if let Some(column) = column {
+                row[slot] = *values.get(*column).ok_or_else(|| error(number + 1, "too few columns"))?;
+            }
+        }
+        if layout[4].is_some() {
+            has_colour = true;
+            bright |= row[4..].iter().any(|c| *c > 1.0);
+        }
+        rows.push((number, row));
+    }
+
+    let scale = if bright { 255.0 } else { 1.0 };
+    Ok(rows.iter().map(|(_, row)| {
+        let particle = Particle::build(Vec3::from(row[0], row[1], row[2]), row[3]);
+        if has_colour {
+            let channel = |c : f32| srgb_eotf((c / scale).clamp(0.0, 1.0));
+            particle.with_colour(Vec3::from(channel(row[4]), channel(row[5]), channel(row[6])))
+        } else {
+            particle
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn towards(x : f32, y : f32) -> Ray {
+        Ray { origin : Vec3::from(x, y, 5.0), direction : Vec3::from(0.0, 0.0, -1.0), time : 0.0 }
+    }
+
+    fn assert_close(a : &Vec3, b : &Vec3, tolerance : f32) {
+        assert!((*a - *b).length() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    fn write_temp(name : &str, data : &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("raytracer-{}-{}", std::process::id(), name));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn spheres_and_discs_hit_their_points() {
+        let colour = Vec3::from(0.2, 0.4, 0.6);
+        let particles = vec![Particle::build(Vec3::default(), 0.5).with_colour(colour)];
+
+        let spheres = PointCloud::build(particles.clone(), grey());
+        let hit = spheres.hit(&towards(0.0, 0.0), 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4);
+        assert_close(&hit.normal, &Vec3::from(0.0, 0.0, 1.0), 1e-4);
+        assert_close(&hit.colour, &colour, 0.01);
+        assert!(spheres.hit(&towards(0.6, 0.0), 0.001, f32::MAX).is_none());
+
+        // Discs sit across the ray at the point's centre, facing back at it.
+        let discs = PointCloud::build(particles, grey()).with_shape(PointShape::Disc);
+        let hit = discs.hit(&towards(0.0, 0.3), 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-4);
+        assert_close(&hit.normal, &Vec3::from(0.0, 0.0, 1.0), 1e-4);
+        assert!(discs.hit(&towards(0.0, 0.6), 0.001, f32::MAX).is_none());
+        let leaving = Ray { origin : Vec3::from(0.0, 0.1, 0.0), direction : Vec3::from(0.0, 0.0, 1.0), time : 0.0 };
+        assert!(discs.hit(&leaving, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn the_nearest_point_along_the_ray_is_hit() {
+        // A column of points along z, walked through the cloud's BVH.
+        let particles = (0..1000).map(|i| {
+            let z = i as f32 * 0.01 - 5.0;
+            Particle::build(Vec3::from((i % 7) as f32, 0.0, z), 0.004).with_colour(Vec3::from(0.0, 0.0, 1.0))
+        }).collect();
+        let cloud = PointCloud::build(particles, grey());
+        let hit = cloud.hit(&towards(0.0, 0.0), 0.001, f32::MAX).unwrap();
+        // The last point on x = 0 is number 994, at z = 4.94.
+        assert!((hit.t - (5.0 - 4.94 - 0.004)).abs() < 1e-3, "{}", hit.t);
+        assert_close(&hit.colour, &Vec3::from(0.0, 0.0, 1.0), 0.01);
+
+        let bounds = cloud.bounding_box(0.0, 1.0);
+        assert_close(&bounds.min, &Vec3::from(-0.004, -0.004, -5.004), 1e-4);
+        assert_close(&bounds.max, &Vec3::from(6.004, 0.004, 4.994), 1e-4);
+    }
+
+    #[test]
+    fn ply_files_give_a_particle_per_vertex() {
+        let particles = read_ply("models/rising_spiral.ply", 0.02).unwrap();
+        assert_eq!(particles.len(), 600);
+        let first = &particles[0];
+        assert_close(&first.position, &Vec3::from(0.0, 0.05, 0.0), 1e-6);
+        assert_eq!(first.radius, 0.02);
+        assert_close(&first.colour, &Vec3::from(0.0, 1.0, srgb_eotf(160.0 / 255.0)), 1e-5);
+
+        let cloud = PointCloud::load("models/rising_spiral.ply", 0.02, grey()).unwrap();
+        let bounds = cloud.bounding_box(0.0, 1.0);
+        assert_close(&bounds.min, &Vec3::from(-0.688, 0.03, -0.7545), 1e-4);
+        assert_close(&bounds.max, &Vec3::from(0.8183, 0.669, 0.6215), 1e-4);
+    }
+
+    #[test]
+    fn binary_ply_reads_like_ascii() {
+        // Big endian, with an element before the vertices to skip over and
+        // sixteen bit colours.
+        let mut data = b"ply\nformat binary_big_endian 1.0\nelement material 1\nproperty list uchar int ids\n\
+            element vertex 2\nproperty float x\nproperty float y\nproperty float z\nproperty double radius\n\
+            property ushort red\nproperty ushort green\nproperty ushort blue\nend_header\n".to_vec();
+        data.extend_from_slice(&[2, 0, 0, 0, 7, 0, 0, 0, 9]);
+        for (position, radius, colour) in [([1.0f32, 2.0, 3.0], 0.25f64, [65535u16, 0, 0]), ([-1.0, -2.0, -3.0], 0.5, [0, 0, 65535])] {
+            for x in position {
+                data.extend_from_slice(&x.to_be_bytes());
+            }
+            data.extend_from_slice(&radius.to_be_bytes());
+            for c in colour {
+                data.extend_from_slice(&c.to_be_bytes());
+            }
+        }
+        let path = write_temp("binary.ply", &data);
+        let particles = read_ply(&path, 0.1);
+        fs::remove_file(&path).unwrap();
+
+        let particles = particles.unwrap();
+        assert_eq!(particles.len(), 2);
+        assert_close(&particles[0].position, &Vec3::from(1.0, 2.0, 3.0), 1e-6);
+        assert_eq!(particles[0].radius, 0.25);
+        assert_close(&particles[0].colour, &Vec3::from(1.0, 0.0, 0.0), 1e-5);
+        assert_close(&particles[1].position, &Vec3::from(-1.0, -2.0, -3.0), 1e-6);
+        assert_eq!(particles[1].radius, 0.5);
+        assert_close(&particles[1].colour, &Vec3::from(0.0, 0.0, 1.0), 1e-5);
+    }
+
+    #[test]
+    fn csv_files_read_radius_and_colour() {
+        let particles = read_csv("models/double_helix.csv", 0.1).unwrap();
+        assert_eq!(particles.len(), 400);
+        let first = &particles[0];
+        assert_close(&first.position, &Vec3::from(0.4, 0.0, 0.0), 1e-6);
+        assert_eq!(first.radius, 0.03);
+        assert_close(&first.colour, &Vec3::from(0.0, srgb_eotf(0.4), 1.0), 1e-5);
+
+        // Without a header the columns go by count, and colours above 1 are
+        // read as 0-255.
+        let path = write_temp("plain.csv", b"# x y z r g b\n1, 2, 3, 255, 0, 0\n\n4, 5, 6, 0, 0, 51\n");
+        let particles = read_csv(&path, 0.1);
+        fs::remove_file(&path).unwrap();
+
+        let particles = particles.unwrap();
+        assert_eq!(particles.len(), 2);
+        assert_eq!(particles[1].radius, 0.1);
+        assert_close(&particles[0].colour, &Vec3::from(1.0, 0.0, 0.0), 1e-5);
+        assert_close(&particles[1].colour, &Vec3::from(0.0, 0.0, srgb_eotf(0.2)), 1e-5);
+    }
+
+    #[test]
+    fn bad_clouds_are_errors() {
+        assert_eq!(PointCloud::load("models/rising_spiral.xyz", 0.1, grey()).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(PointCloud::load("models/missing.ply", 0.1, grey()).err().unwrap().kind(), io::ErrorKind::NotFound);
+
+        let path = write_temp("faces.ply", b"ply\nformat ascii 1.0\nelement face 0\nproperty list uchar int vertex_indices\nend_header\n");
+        let error = read_ply(&path, 0.1).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("no vertex element"), "{}", error);
+
+        let path = write_temp("columns.csv", b"# x y z r g\n1,2,3,4,5\n");
+        let error = read_csv(&path, 0.1).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with(":2: expected 3, 4, 6 or 7 columns"), "{}", error);
+    }
+}
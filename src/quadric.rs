@@ -0,0 +1,800 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::vec3::*;
+
+use std::f32;
+
+// Surfaces of revolution, solved analytically in their own frame. Each is
+// built at the origin around +y, like the sphere's poles, and can be placed
+// with `with_placement` to point its axis anywhere. A sweep of less than 360
+// degrees cuts a wedge out, starting from the frame's +x towards +z.
+//
+// u is the angle round the axis as a fraction of the sweep throughout, v
+// runs along the profile as described on each shape. Normals point away
+// from the axis (out of the tube for the torus), whichever side is hit.
+
+// Position and orientation of a shape, its local +y being `axis`.
+struct Frame {
+    origin : Vec3,
+    x : Vec3,
+    y : Vec3,
+    z : Vec3
+}
+
+impl Frame {
+    fn identity() -> Frame {
+        Frame { origin : Vec3::default(), x : Vec3::from(1.0, 0.0, 0.0), y : Vec3::from(0.0, 1.0, 0.0), z : Vec3::from(0.0, 0.0, 1.0) }
+    }
+
+    fn build(origin : Vec3, axis : Vec3) -> Frame {
+        let length = axis.length();
+        if length == 0.0 || !length.is_finite() {
+            return Frame { origin, ..Frame::identity() };
+        }
+        let y = &axis / length;
+        let (z, x) = orthonormal_basis(&y);
+        Frame { origin, x, y, z }
+    }
+
+    fn vector_to_local(&self, v : &Vec3) -> Vec3 {
+        Vec3::from(dot(v, &self.x), dot(v, &self.y), dot(v, &self.z))
+    }
+
+    fn vector_to_world(&self, v : &Vec3) -> Vec3 {
+        &self.x * *v.x() + &self.y * *v.y() + &self.z * *v.z()
+    }
+
+    fn ray_to_local(&self, ray : &Ray) -> Ray {
+        Ray {
+            origin : self.vector_to_local(&(ray.origin - self.origin)),
+            direction : self.vector_to_local(&ray.direction),
+            time : ray.time
+        }
+    }
+
+    // World bounds of the local box `min`..`max`.
+    fn bounds(&self, min : &Vec3, max : &Vec3) -> Aabb {
+        let mut corners = Vec::with_capacity(8);
+        for i in 0..8 {
+            let corner = Vec3::from(
+                if i & 1 == 0 { *min.x() } else { *max.x() },
+                if i & 2 == 0 { *min.y() } else { *max.y() },
+                if i & 4 == 0 { *min.z() } else { *max.z() }
+            );
+            corners.push(self.origin + self.vector_to_world(&corner));
+        }
+        padded_bounds(&corners)
+    }
+
+    fn record<'a>(&self, ray : &Ray, hit : LocalHit, material : &'a dyn Material) -> HitRecord<'a> {
+        HitRecord::build(ray, hit.t, self.vector_to_world(&hit.normal), material)
+            .with_uv(hit.u, hit.v)
+            .with_derivatives(self.vector_to_world(&hit.dpdu), self.vector_to_world(&hit.dpdv))
+    }
+}
+
+// A hit worked out in the shape's frame.
+struct LocalHit {
+    t : f32,
+    normal : Vec3,
+    u : f32,
+    v : f32,
+    dpdu : Vec3,
+    dpdv : Vec3
+}
+
+// The nearer of two optional hits.
+fn nearest(a : Option<LocalHit>, b : Option<LocalHit>) -> Option<LocalHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => if b.t < a.t { Some(b) } else { Some(a) },
+        (a, None) => a,
+        (None, b) => b
+    }
+}
+
+// Angle of the local point round the y axis in [0, 2π).
+fn angle(p : &Vec3) -> f32 {
+    let phi = p.z().atan2(*p.x());
+    if phi < 0.0 { phi + 2.0 * f32::consts::PI } else { phi }
+}
+
+// How the point moves round the axis as u goes from 0 to 1.
+fn sweep_derivative(p : &Vec3, phi_max : f32) -> Vec3 {
+    &Vec3::from(-p.z(), 0.0, *p.x()) * phi_max
+}
+
+fn sweep_radians(degrees : f32) -> f32 {
+    degrees.clamp(0.0, 360.0).to_radians()
+}
+
+// Real roots of a t² + b t + c in increasing order. In double precision, the
+// coefficients lose a lot to cancellation for rays starting far away.
+fn solve_quadratic(a : f64, b : f64, c : f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        // The other root has gone off to infinity.
+        return Some((-c / b, f64::INFINITY));
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some(if t0 < t1 { (t0, t1) } else { (t1, t0) })
+}
+
+// Roots of a quadric within the ray's range whose points `accept` likes,
+// nearest first.
+fn roots_in_range<F : Fn(f32, &Vec3) -> Option<LocalHit>>(roots : Option<(f64, f64)>, ray : &Ray, t_min : f32, t_max : f32, accept : F) -> impl Iterator<Item = LocalHit> {
+    let ray = *ray;
+    // No roots at all come out as NaN, which is never in range.
+    let (t0, t1) = roots.map_or((f32::NAN, f32::NAN), |(t0, t1)| (t0 as f32, t1 as f32));
+    std::iter::once(t0).chain(std::iter::once(t1))
+        .filter(move |t| *t > t_min && *t < t_max)
+        .filter_map(move |t| accept(t, &ray.point_at_parameter(t)))
+}
+
+// Components of a local ray in double precision.
+fn components(ray : &Ray) -> ([f64; 3], [f64; 3]) {
+    (
+        [f64::from(*ray.origin.x()), f64::from(*ray.origin.y()), f64::from(*ray.origin.z())],
+        [f64::from(*ray.direction.x()), f64::from(*ray.direction.y()), f64::from(*ray.direction.z())]
+    )
+}
+
+// Cylinder of `radius` round the axis from `y_min` to `y_max`. v runs from
+// y_min to y_max up the side. Capped cylinders close both ends with disks
+// whose v is the distance out from the axis as a fraction of the radius,
+// the caps' normals pointing out along the axis.
+pub struct Cylinder {
+    radius : f32,
+    y_min : f32,
+    y_max : f32,
+    phi_max : f32,
+    capped : bool,
+    frame : Frame,
+    material : Box<dyn Material>
+}
+
+impl Cylinder {
+    pub fn build(radius : f32, y_min : f32, y_max : f32, material : Box<dyn Material>) -> Box<Cylinder> {
+        Box::new(Cylinder { radius, y_min : y_min.min(y_max), y_max : y_min.max(y_max), phi_max : 2.0 * f32::consts::PI, capped : false, frame : Frame::identity(), material })
+    }
+
+    pub fn with_caps(mut self : Box<Self>, capped : bool) -> Box<Cylinder> {
+        self.capped = capped;
+        self
+    }
+
+    pub fn with_sweep(mut self : Box<Self>, degrees : f32) -> Box<Cylinder> {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    pub fn with_placement(mut self : Box<Self>, origin : Vec3, axis : Vec3) -> Box<Cylinder> {
+        self.frame = Frame::build(origin, axis);
+        self
+    }
+
+    fn side<'a>(&'a self, ray : &Ray, t_min : f32, t_max : f32) -> impl Iterator<Item = LocalHit> + 'a {
+        let (o, d) = components(ray);
+        let r = f64::from(self.radius);
+        let roots = solve_quadratic(d[0] * d[0] + d[2] * d[2], 2.0 * (o[0] * d[0] + o[2] * d[2]), o[0] * o[0] + o[2] * o[2] - r * r);
+        roots_in_range(roots, ray, t_min, t_max, move |t, p| {
+            let phi = angle(p);
+            if *p.y() < self.y_min || *p.y() > self.y_max || phi > self.phi_max {
+                return None;
+            }
+            Some(LocalHit {
+                t,
+                normal : &Vec3::from(*p.x(), 0.0, *p.z()) / self.radius,
+                u : phi / self.phi_max,
+                v : (p.y() - self.y_min) / (self.y_max - self.y_min),
+                dpdu : sweep_derivative(p, self.phi_max),
+                dpdv : Vec3::from(0.0, self.y_max - self.y_min, 0.0)
+            })
+        })
+    }
+
+    fn cap(&self, ray : &Ray, y : f32, facing : f32, t_min : f32, t_max : f32) -> Option<LocalHit> {
+        if *ray.direction.y() == 0.0 {
+            return None;
+        }
+        let t = (y - ray.origin.y()) / ray.direction.y();
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let p = ray.point_at_parameter(t);
+        let rho = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let phi = angle(&p);
+        if rho > self.radius || phi > self.phi_max {
+            return None;
+        }
+        let radial = if rho > 0.0 { &Vec3::from(*p.x(), 0.0, *p.z()) / rho } else { Vec3::from(1.0, 0.0, 0.0) };
+        Some(LocalHit {
+            t,
+            normal : Vec3::from(0.0, facing, 0.0),
+            u : phi / self.phi_max,
+            v : rho / self.radius,
+            dpdu : sweep_derivative(&p, self.phi_max),
+            dpdv : &radial * self.radius
+        })
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        let mut hit = self.side(&local, t_min, t_max).next();
+        if self.capped {
+            let limit = hit.as_ref().map_or(t_max, |h| h.t);
+            hit = nearest(hit, self.cap(&local, self.y_min, -1.0, t_min, limit));
+            let limit = hit.as_ref().map_or(t_max, |h| h.t);
+            hit = nearest(hit, self.cap(&local, self.y_max, 1.0, t_min, limit));
+        }
+        hit.map(|hit| self.frame.record(ray, hit, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.frame.bounds(&Vec3::from(-self.radius, self.y_min, -self.radius), &Vec3::from(self.radius, self.y_max, self.radius))
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        let mut hits : Vec<LocalHit> = self.side(&local, t_min, t_max).collect();
+        if self.capped {
+            hits.extend(self.cap(&local, self.y_min, -1.0, t_min, t_max));
+            hits.extend(self.cap(&local, self.y_max, 1.0, t_min, t_max));
+            hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+        }
+        hits.into_iter().map(|hit| self.frame.record(ray, hit, &*self.material)).collect()
+    }
+}
+
+// Open cone with its base of `radius` at y = 0 and its tip at y = `height`.
+// v runs from the base to the tip.
+pub struct Cone {
+    radius : f32,
+    height : f32,
+    phi_max : f32,
+    frame : Frame,
+    material : Box<dyn Material>
+}
+
+impl Cone {
+    pub fn build(radius : f32, height : f32, material : Box<dyn Material>) -> Box<Cone> {
+        Box::new(Cone { radius, height, phi_max : 2.0 * f32::consts::PI, frame : Frame::identity(), material })
+    }
+
+    pub fn with_sweep(mut self : Box<Self>, degrees : f32) -> Box<Cone> {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    pub fn with_placement(mut self : Box<Self>, origin : Vec3, axis : Vec3) -> Box<Cone> {
+        self.frame = Frame::build(origin, axis);
+        self
+    }
+
+    fn local_hits<'a>(&'a self, local : &Ray, t_min : f32, t_max : f32) -> impl Iterator<Item = LocalHit> + 'a {
+        let (o, d) = components(local);
+        let h = f64::from(self.height);
+        let k = f64::from(self.radius) / h;
+        let k = k * k;
+        let above = h - o[1];
+        let roots = solve_quadratic(
+            d[0] * d[0] + d[2] * d[2] - k * d[1] * d[1],
+            2.0 * (o[0] * d[0] + o[2] * d[2] + k * above * d[1]),
+            o[0] * o[0] + o[2] * o[2] - k * above * above
+        );
+        roots_in_range(roots, local, t_min, t_max, move |t, p| {
+            let phi = angle(p);
+            if *p.y() < 0.0 || *p.y() > self.height || phi > self.phi_max {
+                return None;
+            }
+            let v = p.y() / self.height;
+            let normal = Vec3::from(*p.x(), (k as f32) * (self.height - p.y()), *p.z());
+            let length = normal.length();
+            let shrink = (1.0 - v).max(1e-6);
+            Some(LocalHit {
+                t,
+                normal : if length > 0.0 { &normal / length } else { Vec3::from(0.0, 1.0, 0.0) },
+                u : phi / self.phi_max,
+                v,
+                dpdu : sweep_derivative(p, self.phi_max),
+                dpdv : Vec3::from(-p.x() / shrink, self.height, -p.z() / shrink)
+            })
+        })
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).next().map(|hit| self.frame.record(ray, hit, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.frame.bounds(&Vec3::from(-self.radius, 0.0, -self.radius), &Vec3::from(self.radius, self.height, self.radius))
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).map(|hit| self.frame.record(ray, hit, &*self.material)).collect()
+    }
+}
+
+// Paraboloid x² + z² = radius² y / y_max, cut between `y_min` and `y_max`
+// (both at or above zero). v runs from y_min to y_max.
+pub struct Paraboloid {
+    radius : f32,
+    y_min : f32,
+    y_max : f32,
+    phi_max : f32,
+    frame : Frame,
+    material : Box<dyn Material>
+}
+
+impl Paraboloid {
+    pub fn build(radius : f32, y_min : f32, y_max : f32, material : Box<dyn Material>) -> Box<Paraboloid> {
+        let (y_min, y_max) = (y_min.min(y_max).max(0.0), y_min.max(y_max).max(0.0));
+        Box::new(Paraboloid { radius, y_min, y_max, phi_max : 2.0 * f32::consts::PI, frame : Frame::identity(), material })
+    }
+
+    pub fn with_sweep(mut self : Box<Self>, degrees : f32) -> Box<Paraboloid> {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    pub fn with_placement(mut self : Box<Self>, origin : Vec3, axis : Vec3) -> Box<Paraboloid> {
+        self.frame = Frame::build(origin, axis);
+        self
+    }
+
+    fn local_hits<'a>(&'a self, local : &Ray, t_min : f32, t_max : f32) -> impl Iterator<Item = LocalHit> + 'a {
+        let (o, d) = components(local);
+        let k = f64::from(self.radius) * f64::from(self.radius) / f64::from(self.y_max);
+        let roots = solve_quadratic(
+            d[0] * d[0] + d[2] * d[2],
+            2.0 * (o[0] * d[0] + o[2] * d[2]) - k * d[1],
+            o[0] * o[0] + o[2] * o[2] - k * o[1]
+        );
+        // Nothing is left if the cut is entirely below the tip.
+        let roots = if self.y_max > 0.0 { roots } else { None };
+        roots_in_range(roots, local, t_min, t_max, move |t, p| {
+            let phi = angle(p);
+            if *p.y() < self.y_min || *p.y() > self.y_max || phi > self.phi_max {
+                return None;
+            }
+            let normal = Vec3::from(2.0 * p.x(), -(k as f32), 2.0 * p.z());
+            let rise = 2.0 * p.y().max(1e-6);
+            Some(LocalHit {
+                t,
+                normal : &normal / normal.length(),
+                u : phi / self.phi_max,
+                v : (p.y() - self.y_min) / (self.y_max - self.y_min).max(1e-6),
+                dpdu : sweep_derivative(p, self.phi_max),
+                dpdv : &Vec3::from(p.x() / rise, 1.0, p.z() / rise) * (self.y_max - self.y_min)
+            })
+        })
+    }
+}
+
+impl Hitable for Paraboloid {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).next().map(|hit| self.frame.record(ray, hit, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        self.frame.bounds(&Vec3::from(-self.radius, self.y_min, -self.radius), &Vec3::from(self.radius, self.y_max, self.radius))
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).map(|hit| self.frame.record(ray, hit, &*self.material)).collect()
+    }
+}
+
+// Hyperboloid of one sheet, x² + z² = waist² + slope² y², cut between
+// `y_min` and `y_max`. `slope` is how fast the radius grows with height far
+// from the waist; a waist of zero gives a double cone. v runs from y_min to
+// y_max.
+pub struct Hyperboloid {
+    waist : f32,
+    slope : f32,
+    y_min : f32,
+    y_max : f32,
+    phi_max : f32,
+    frame : Frame,
+    material : Box<dyn Material>
+}
+
+impl Hyperboloid {
+    pub fn build(waist : f32, slope : f32, y_min : f32, y_max : f32, material : Box<dyn Material>) -> Box<Hyperboloid> {
+        Box::new(Hyperboloid { waist, slope, y_min : y_min.min(y_max), y_max : y_min.max(y_max), phi_max : 2.0 * f32::consts::PI, frame : Frame::identity(), material })
+    }
+
+    pub fn with_sweep(mut self : Box<Self>, degrees : f32) -> Box<Hyperboloid> {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    pub fn with_placement(mut self : Box<Self>, origin : Vec3, axis : Vec3) -> Box<Hyperboloid> {
+        self.frame = Frame::build(origin, axis);
+        self
+    }
+
+    fn radius_at(&self, y : f32) -> f32 {
+        (self.waist * self.waist + self.slope * self.slope * y * y).sqrt()
+    }
+
+    fn local_hits<'a>(&'a self, local : &Ray, t_min : f32, t_max : f32) -> impl Iterator<Item = LocalHit> + 'a {
+        let (o, d) = components(local);
+        let s2 = f64::from(self.slope) * f64::from(self.slope);
+        let w = f64::from(self.waist);
+        let roots = solve_quadratic(
+            d[0] * d[0] + d[2] * d[2] - s2 * d[1] * d[1],
+            2.0 * (o[0] * d[0] + o[2] * d[2] - s2 * o[1] * d[1]),
+            o[0] * o[0] + o[2] * o[2] - s2 * o[1] * o[1] - w * w
+        );
+        roots_in_range(roots, local, t_min, t_max, move |t, p| {
+            let phi = angle(p);
+            if *p.y() < self.y_min || *p.y() > self.y_max || phi > self.phi_max {
+                return None;
+            }
+            let s2 = s2 as f32;
+            let normal = Vec3::from(*p.x(), -s2 * p.y(), *p.z());
+            let length = normal.length();
+            let rho2 = (p.x() * p.x() + p.z() * p.z()).max(1e-12);
+            let spread = s2 * p.y() / rho2;
+            Some(LocalHit {
+                t,
+                normal : if length > 0.0 { &normal / length } else { Vec3::from(1.0, 0.0, 0.0) },
+                u : phi / self.phi_max,
+                v : (p.y() - self.y_min) / (self.y_max - self.y_min).max(1e-6),
+                dpdu : sweep_derivative(p, self.phi_max),
+                dpdv : &Vec3::from(p.x() * spread, 1.0, p.z() * spread) * (self.y_max - self.y_min)
+            })
+        })
+    }
+}
+
+impl Hitable for Hyperboloid {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).next().map(|hit| self.frame.record(ray, hit, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        let r = self.radius_at(self.y_min).max(self.radius_at(self.y_max));
+        self.frame.bounds(&Vec3::from(-r, self.y_min, -r), &Vec3::from(r, self.y_max, r))
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).map(|hit| self.frame.record(ray, hit, &*self.material)).collect()
+    }
+}
+
+// Torus round the y axis, a tube of `minor` radius swept round a circle of
+// `major` radius. v goes once round the tube, starting on the outside
+// equator and heading up.
+pub struct Torus {
+    major : f32,
+    minor : f32,
+    phi_max : f32,
+    frame : Frame,
+    material : Box<dyn Material>
+}
+
+impl Torus {
+    pub fn build(major : f32, minor : f32, material : Box<dyn Material>) -> Box<Torus> {
+        Box::new(Torus { major, minor, phi_max : 2.0 * f32::consts::PI, frame : Frame::identity(), material })
+    }
+
+    pub fn with_sweep(mut self : Box<Self>, degrees : f32) -> Box<Torus> {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    pub fn with_placement(mut self : Box<Self>, origin : Vec3, axis : Vec3) -> Box<Torus> {
+        self.frame = Frame::build(origin, axis);
+        self
+    }
+
+    fn local_hit(&self, p : &Vec3, t : f32) -> Option<LocalHit> {
+        let phi = angle(p);
+        if phi > self.phi_max {
+            return None;
+        }
+        let rho = (p.x() * p.x() + p.z() * p.z()).sqrt().max(1e-6);
+        // Centre of the tube nearest the point.
+        let spine = Vec3::from(self.major * p.x() / rho, 0.0, self.major * p.z() / rho);
+        let out = *p - spine;
+        let normal = &out / out.length().max(1e-12);
+        let theta = p.y().atan2(rho - self.major);
+        let theta = if theta < 0.0 { theta + 2.0 * f32::consts::PI } else { theta };
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let dpdv = &Vec3::from(-self.minor * sin_theta * cos_phi, self.minor * cos_theta, -self.minor * sin_theta * sin_phi) * (2.0 * f32::consts::PI);
+        Some(LocalHit {
+            t,
+            normal,
+            u : phi / self.phi_max,
+            v : theta / (2.0 * f32::consts::PI),
+            dpdu : sweep_derivative(p, self.phi_max),
+            dpdv
+        })
+    }
+
+    // Every crossing of the local ray, nearest first.
+    fn roots(&self, local : &Ray) -> Vec<f32> {
+        let (o, d) = components(local);
+        // Solve from the point on the ray nearest the centre with a unit
+        // direction, which keeps the quartic's coefficients well scaled.
+        let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        if length == 0.0 {
+            return Vec::new();
+        }
+        let d = [d[0] / length, d[1] / length, d[2] / length];
+        let shift = -(o[0] * d[0] + o[1] * d[1] + o[2] * d[2]);
+        let o = [o[0] + shift * d[0], o[1] + shift * d[1], o[2] + shift * d[2]];
+
+        let (major, minor) = (f64::from(self.major), f64::from(self.minor));
+        let bound = major + minor;
+        let closest = o[0] * o[0] + o[1] * o[1] + o[2] * o[2];
+        if closest > bound * bound {
+            return Vec::new();
+        }
+        let f = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let e = closest - major * major - minor * minor;
+        let four_major2 = 4.0 * major * major;
+        let coefficients = [
+            e * e - four_major2 * (minor * minor - o[1] * o[1]),
+            4.0 * f * e + 2.0 * four_major2 * o[1] * d[1],
+            2.0 * e + 4.0 * f * f + four_major2 * d[1] * d[1],
+            4.0 * f,
+            1.0
+        ];
+        // Back to the caller's parameterisation.
+        let mut roots : Vec<f32> = solve_quartic(&coefficients).iter().map(|root| ((root + shift) / length) as f32).collect();
+        roots.sort_by(|a, b| a.total_cmp(b));
+        roots
+    }
+
+    fn local_hits<'a>(&'a self, local : &Ray, t_min : f32, t_max : f32) -> impl Iterator<Item = LocalHit> + 'a {
+        let local = *local;
+        self.roots(&local).into_iter()
+            .filter(move |t| *t > t_min && *t < t_max)
+            .filter_map(move |t| self.local_hit(&local.point_at_parameter(t), t))
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).next().map(|hit| self.frame.record(ray, hit, &*self.material))
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        let r = self.major + self.minor;
+        self.frame.bounds(&Vec3::from(-r, -self.minor, -r), &Vec3::from(r, self.minor, r))
+    }
+
+    fn all_hits(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<HitRecord<'_>> {
+        let local = self.frame.ray_to_local(ray);
+        self.local_hits(&local, t_min, t_max).map(|hit| self.frame.record(ray, hit, &*self.material)).collect()
+    }
+}
+
+fn near_zero(x : f64) -> bool {
+    x.abs() < 1e-12
+}
+
+// Real roots of c[0] + c[1] x + c[2] x² + c[3] x³, Cardano's method.
+fn solve_cubic(c : &[f64; 4]) -> Vec<f64> {
+    let (a, b, c0) = (c[2] / c[3], c[1] / c[3], c[0] / c[3]);
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c0) / 2.0;
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+    let mut roots = if near_zero(discriminant) {
+        if near_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        let third = std::f64::consts::PI / 3.0;
+        vec![t * phi.cos(), -t * (phi + third).cos(), -t * (phi - third).cos()]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt()]
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+// Real roots of c[0] + c[1] x + ... + c[4] x⁴ by Ferrari's method, each
+// polished with a couple of Newton steps to win back precision.
+fn solve_quartic(c : &[f64; 5]) -> Vec<f64> {
+    let (a, b, c1, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+    // Depressed quartic y⁴ + p y² + q y + r with x = y - a / 4.
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c1;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c1 / 4.0 + d;
+
+    let mut roots = if near_zero(r) {
+        let mut roots = solve_cubic(&[q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        let z = solve_cubic(&[r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if near_zero(u) { 0.0 } else if u > 0.0 { u.sqrt() } else { return Vec::new() };
+        let v = if near_zero(v) { 0.0 } else if v > 0.0 { v.sqrt() } else { return Vec::new() };
+        let mut roots = Vec::with_capacity(4);
+        let v = if q < 0.0 { -v } else { v };
+        for (c0, c1) in [(z - u, v), (z + u, -v)] {
+            if let Some((r0, r1)) = solve_quadratic(1.0, c1, c0) {
+                roots.push(r0);
+                roots.push(r1);
+            }
+        }
+        roots
+    };
+
+    let polynomial = |x : f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let derivative = |x : f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let slope = derivative(*root);
+            if slope != 0.0 {
+                *root -= polynomial(*root) / slope;
+            }
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn ray(origin : Vec3, direction : Vec3) -> Ray {
+        Ray { origin, direction, time : 0.0 }
+    }
+
+    fn along_x(y : f32) -> Ray {
+        ray(Vec3::from(-5.0, y, 0.0), Vec3::from(1.0, 0.0, 0.0))
+    }
+
+    fn assert_close(a : &Vec3, b : &Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    // The distances of every crossing, checking `hit` finds the first.
+    fn crossings(shape : &dyn Hitable, ray : &Ray) -> Vec<f32> {
+        let all : Vec<f32> = shape.all_hits(ray, 0.001, f32::MAX).iter().map(|rec| rec.t).collect();
+        assert_eq!(shape.hit(ray, 0.001, f32::MAX).map(|rec| rec.t), all.first().copied());
+        all
+    }
+
+    fn assert_crossings(actual : &[f32], expected : &[f32]) {
+        assert!(actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-3),
+            "crossings {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn cylinders_hit_their_side_and_caps() {
+        let open = Cylinder::build(1.0, 0.0, 2.0, grey());
+        assert_crossings(&crossings(&*open, &along_x(1.0)), &[4.0, 6.0]);
+        let recs = open.all_hits(&along_x(1.0), 0.001, f32::MAX);
+        assert_close(&recs[0].normal, &Vec3::from(-1.0, 0.0, 0.0));
+        assert_close(&recs[1].normal, &Vec3::from(1.0, 0.0, 0.0));
+        assert!((recs[0].v - 0.5).abs() < 1e-4);
+        assert!(crossings(&*open, &along_x(2.5)).is_empty());
+
+        // Straight down inside the tube only meets the caps.
+        let down = ray(Vec3::from(0.5, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        assert!(crossings(&*open, &down).is_empty());
+        let capped = Cylinder::build(1.0, 0.0, 2.0, grey()).with_caps(true);
+        assert_crossings(&crossings(&*capped, &down), &[3.0, 5.0]);
+        let top = capped.hit(&down, 0.001, f32::MAX).unwrap();
+        assert_close(&top.normal, &Vec3::from(0.0, 1.0, 0.0));
+        assert!((top.v - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sweeps_cut_out_a_wedge() {
+        // A quarter turn from +x towards +z: the far side is left, at 53°.
+        let quarter = Cylinder::build(1.0, 0.0, 2.0, grey()).with_sweep(90.0);
+        let across = ray(Vec3::from(0.6, 1.0, -5.0), Vec3::from(0.0, 0.0, 1.0));
+        assert_crossings(&crossings(&*quarter, &across), &[5.8]);
+        let rec = quarter.hit(&across, 0.001, f32::MAX).unwrap();
+        assert!((rec.u - 0.8f32.atan2(0.6).to_degrees() / 90.0).abs() < 1e-4);
+        assert!(crossings(&*quarter, &along_x(1.0)).len() == 1);
+    }
+
+    #[test]
+    fn placement_moves_and_turns_the_axis() {
+        let lying = Cylinder::build(1.0, 0.0, 2.0, grey()).with_placement(Vec3::from(3.0, 0.0, 0.0), Vec3::from(2.0, 0.0, 0.0));
+        let down = ray(Vec3::from(4.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        assert_crossings(&crossings(&*lying, &down), &[4.0, 6.0]);
+        assert_close(&lying.hit(&down, 0.001, f32::MAX).unwrap().normal, &Vec3::from(0.0, 1.0, 0.0));
+        assert!(crossings(&*lying, &ray(Vec3::from(5.5, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0))).is_empty());
+    }
+
+    #[test]
+    fn cones_narrow_to_their_tip() {
+        let cone = Cone::build(1.0, 2.0, grey());
+        assert_crossings(&crossings(&*cone, &along_x(1.0)), &[4.5, 5.5]);
+        let rec = cone.hit(&along_x(1.0), 0.001, f32::MAX).unwrap();
+        assert_close(&rec.normal, &(&Vec3::from(-2.0, 1.0, 0.0) / 5.0f32.sqrt()));
+        assert!((rec.v - 0.5).abs() < 1e-4);
+        assert!(crossings(&*cone, &along_x(2.5)).is_empty());
+    }
+
+    #[test]
+    fn paraboloids_widen_with_height() {
+        let bowl = Paraboloid::build(1.0, 0.0, 1.0, grey());
+        assert_crossings(&crossings(&*bowl, &along_x(0.25)), &[4.5, 5.5]);
+        let rec = bowl.hit(&along_x(0.25), 0.001, f32::MAX).unwrap();
+        assert_close(&rec.normal, &(&Vec3::from(-1.0, -1.0, 0.0) / 2.0f32.sqrt()));
+        assert!((rec.v - 0.25).abs() < 1e-4);
+        // Cut off above y = 0.5, the same ray is below the bottom.
+        let ring = Paraboloid::build(1.0, 0.5, 1.0, grey());
+        assert!(crossings(&*ring, &along_x(0.25)).is_empty());
+    }
+
+    #[test]
+    fn hyperboloids_pinch_at_their_waist() {
+        let waisted = Hyperboloid::build(0.5, 1.0, -1.0, 1.0, grey());
+        assert_crossings(&crossings(&*waisted, &along_x(0.0)), &[4.5, 5.5]);
+        let r = 0.5f32.sqrt();
+        assert_crossings(&crossings(&*waisted, &along_x(0.5)), &[5.0 - r, 5.0 + r]);
+        let rec = waisted.hit(&along_x(0.5), 0.001, f32::MAX).unwrap();
+        assert_close(&rec.normal, &(&Vec3::from(-r, -0.5, 0.0) / (r * r + 0.25).sqrt()));
+    }
+
+    #[test]
+    fn tori_cross_four_times_through_the_hole() {
+        let torus = Torus::build(2.0, 0.5, grey());
+        let through = along_x(0.0);
+        assert_crossings(&crossings(&*torus, &through), &[2.5, 3.5, 6.5, 7.5]);
+        let recs = torus.all_hits(&through, 0.001, f32::MAX);
+        for (rec, facing) in recs.iter().zip([-1.0, 1.0, -1.0, 1.0].iter()) {
+            assert_close(&rec.normal, &Vec3::from(*facing, 0.0, 0.0));
+        }
+        // v starts on the outside equator and is halfway round on the inside.
+        assert!(recs[0].v.abs() < 1e-4 && (recs[1].v - 0.5).abs() < 1e-4);
+        // Starting inside the tube, the first crossing is the way out.
+        assert_crossings(&torus.all_hits(&through, 3.0, f32::MAX).iter().map(|rec| rec.t).collect::<Vec<_>>(), &[3.5, 6.5, 7.5]);
+        // Down through the tube, and past it through the hole.
+        let down = ray(Vec3::from(2.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0));
+        assert_crossings(&crossings(&*torus, &down), &[4.5, 5.5]);
+        assert_close(&torus.hit(&down, 0.001, f32::MAX).unwrap().normal, &Vec3::from(0.0, 1.0, 0.0));
+        assert!(crossings(&*torus, &ray(Vec3::from(0.0, 5.0, 0.0), Vec3::from(0.0, -1.0, 0.0))).is_empty());
+    }
+}
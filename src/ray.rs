@@ -1,5 +1,5 @@
 use crate::material::Material;
-use crate::vec3::Vec3;
+use crate::vec3::*;
 
 #[derive(Copy, Clone)]
 pub struct Ray {
@@ -22,11 +22,101 @@ impl Ray {
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub p: Vec3,
+    // Hit position in the primitive's own space, before `Translate`,
+    // `RotateY` or motion move it. Solid textures evaluated here stay put on
+    // the object.
+    pub object_p: Vec3,
     pub normal: Vec3,
     pub material: &'a dyn Material,
     pub u : f32,
-    pub v : f32
+    pub v : f32,
+    // Set by `Tagged`, zero for untagged geometry.
+    pub object_id : u32,
+    pub material_id : u32,
+    // How the hit point moves with u and v, zero where the surface has no
+    // parameterisation.
+    pub dpdu : Vec3,
+    pub dpdv : Vec3,
+    // Width of the texture lookup in uv space, zero to point sample.
+    pub footprint : f32,
+    // Time of the ray that made the hit, for animated textures.
+    pub time : f32,
+    // Colour carried by the geometry itself, such as the per point colours
+    // of a `PointCloud`; white where there is none. `GeometryColour` passes
+    // it on to materials.
+    pub colour : Vec3
+}
+
+impl<'a> HitRecord<'a> {
+    // A hit `t` along `ray`, taken to be in object space already, with no uv
+    // parameterisation, no ids, no footprint and white geometry colour.
+    pub fn build(ray : &Ray, t : f32, normal : Vec3, material : &'a dyn Material) -> HitRecord<'a> {
+        let p = ray.point_at_parameter(t);
+        HitRecord {
+            t,
+            p,
+            object_p : p,
+            normal,
+            material,
+            u : 0.0,
+            v : 0.0,
+            object_id : 0,
+            material_id : 0,
+            dpdu : Vec3::default(),
+            dpdv : Vec3::default(),
+            footprint : 0.0,
+            time : ray.time,
+            colour : Vec3::from(1.0, 1.0, 1.0)
+        }
+    }
+
+    pub fn with_uv(self, u : f32, v : f32) -> HitRecord<'a> {
+        HitRecord { u, v, ..self }
+    }
+
+    pub fn with_derivatives(self, dpdu : Vec3, dpdv : Vec3) -> HitRecord<'a> {
+        HitRecord { dpdu, dpdv, ..self }
+    }
+
+    pub fn with_object_p(self, object_p : Vec3) -> HitRecord<'a> {
+        HitRecord { object_p, ..self }
+    }
+
+    pub fn with_colour(self, colour : Vec3) -> HitRecord<'a> {
+        HitRecord { colour, ..self }
+    }
+
+    // Sets the footprint from the rays through the neighbouring pixels: they
+    // are intersected with the tangent plane at the hit, and the offsets are
+    // taken back into uv space by least squares against dpdu and dpdv.
+    pub fn set_footprint(&mut self, rx : &Ray, ry : &Ray) {
+        let uv_offset = |r : &Ray| -> Option<(f32, f32)> {
+            let denominator = dot(&self.normal, &r.direction);
+            if denominator.abs() < 1e-8 {
+                return None;
+            }
+            let t = dot(&self.normal, &(self.p - r.origin)) / denominator;
+            let dp = r.point_at_parameter(t) - self.p;
+
+            let (a00, a01, a11) = (dot(&self.dpdu, &self.dpdu), dot(&self.dpdu, &self.dpdv), dot(&self.dpdv, &self.dpdv));
+            let determinant = a00 * a11 - a01 * a01;
+            if determinant.abs() < 1e-12 {
+                return None;
+            }
+            let (b0, b1) = (dot(&self.dpdu, &dp), dot(&self.dpdv, &dp));
+            Some(((a11 * b0 - a01 * b1) / determinant, (a00 * b1 - a01 * b0) / determinant))
+        };
+
+        self.footprint = match (uv_offset(rx), uv_offset(ry)) {
+            (Some((dudx, dvdx)), Some((dudy, dvdy))) => {
+                let width = dudx.abs().max(dvdx.abs()).max(dudy.abs()).max(dvdy.abs());
+                if width.is_finite() { width } else { 0.0 }
+            },
+            _ => 0.0
+        };
+    }
 }
@@ -4,6 +4,7 @@ use rand::distributions::*;
 use rand::rngs::*;
 use rand::*;
 
+#[derive(Clone)]
 pub struct Random {
     rng: SmallRng,
     dist: Uniform<f32>,
@@ -1,15 +1,100 @@
 use crate::rng::Random;
 use crate::texture::*;
+use crate::perlin::WorleyFeature;
 use crate::material::*;
 use crate::hitable::*;
+use crate::heightfield::*;
+use crate::subdivision::*;
+use crate::curve::*;
+use crate::hair::*;
+use crate::pointcloud::*;
 use crate::camera::*;
+use crate::lens::*;
+use crate::quadric::*;
+use crate::csg::*;
+use crate::sdf::*;
 use crate::vec3::*;
 
-pub fn final_render(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Camera) {
+// What every scene function hands back, the objects and the camera looking at them.
+pub type Scene = (Vec<Box<dyn Hitable>>, Box<dyn Camera>);
+
+// The scenes `--scene` can pick from.
+pub const SCENE_NAMES : &[&str] = &[
+    "final_render",
+    "cornell_smoke",
+    "cornell_box",
+    "cornell_orthographic",
+    "cornell_fisheye",
+    "cornell_panorama",
+    "cornell_lens",
+    "bokeh_lights",
+    "physical_portrait",
+    "rolling_shutter",
+    "texture_filtering",
+    "star_sign",
+    "bump_maps",
+    "noise_textures",
+    "texture_graph",
+    "solid_textures",
+    "flat_shapes",
+    "quadric_shapes",
+    "csg_shapes",
+    "distance_fields",
+    "simple_light",
+    "terrain",
+    "height_map",
+    "subdivision_surfaces",
+    "fur_ball",
+    "hair_tufts",
+    "point_clouds",
+    "earth_scene",
+    "two_perlin_spheres",
+    "two_spheres",
+    "random_moving_scene",
+];
+
+pub fn build_scene(name : &str, nx : usize, ny : usize, rnd : &mut Random) -> Option<Scene> {
+    let scene = match name {
+        "final_render" => final_render(nx, ny, rnd),
+        "cornell_smoke" => cornell_smoke(nx, ny),
+        "cornell_box" => cornell_box(nx, ny),
+        "cornell_orthographic" => cornell_orthographic(nx, ny),
+        "cornell_fisheye" => cornell_fisheye(nx, ny),
+        "cornell_panorama" => cornell_panorama(nx, ny),
+        "cornell_lens" => cornell_lens(nx, ny),
+        "bokeh_lights" => bokeh_lights(nx, ny, rnd),
+        "physical_portrait" => physical_portrait(nx, ny),
+        "rolling_shutter" => rolling_shutter(nx, ny),
+        "texture_filtering" => texture_filtering(nx, ny),
+        "star_sign" => star_sign(nx, ny),
+        "bump_maps" => bump_maps(nx, ny, rnd),
+        "noise_textures" => noise_textures(nx, ny, rnd),
+        "texture_graph" => texture_graph(nx, ny, rnd),
+        "solid_textures" => solid_textures(nx, ny, rnd),
+        "flat_shapes" => flat_shapes(nx, ny, rnd),
+        "quadric_shapes" => quadric_shapes(nx, ny),
+        "csg_shapes" => csg_shapes(nx, ny, rnd),
+        "distance_fields" => distance_fields(nx, ny, rnd),
+        "simple_light" => simple_light(nx, ny, rnd),
+        "terrain" => terrain(nx, ny, rnd),
+        "height_map" => height_map(nx, ny),
+        "subdivision_surfaces" => subdivision_surfaces(nx, ny, rnd),
+        "fur_ball" => fur_ball(nx, ny, rnd),
+        "hair_tufts" => hair_tufts(nx, ny, rnd),
+        "point_clouds" => point_clouds(nx, ny, rnd),
+        "earth_scene" => earth_scene(nx, ny),
+        "two_perlin_spheres" => two_perlin_spheres(nx, ny, rnd),
+        "two_spheres" => two_spheres(nx, ny),
+        "random_moving_scene" => random_moving_scene(nx, ny, rnd, 0.0, 1.0),
+        _ => return None,
+    };
+    Some(scene)
+}
+
+pub fn final_render(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let nb = 20;
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(30);
     let mut boxlist : Vec<Box<dyn Hitable>> = Vec::with_capacity(10000);
-    let mut boxlist2 : Vec<Box<dyn Hitable>> = Vec::with_capacity(10000);
     for i in 0..nb {
         for j in 0..nb {
             let w = 100.0;
@@ -23,51 +108,49 @@ pub fn final_render(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn H
             boxlist.push(BoxShape::new_from(&Vec3::from(x0, y0, z0), &Vec3::from(x1, y1, z1), Box::new(ground)));
         }
     }
-    list.push(BvhTree::build(&mut boxlist, rnd, 0.0, 0.0).root);
+    // Material ids for the material id pass, the glass spheres share one.
+    list.push(Tagged::with_material_id(BvhTree::build(&mut boxlist, rnd, 0.0, 0.0).root, 1));
     let light = Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(20.0, 20.0, 20.0))});
-    list.push(Box::new(XzRect { x0: 123.0, x1: 423.0, z0: 147.0, z1: 412.0, y: 554.0, material: light}));
+    list.push(Tagged::with_material_id(Box::new(XzRect { x0: 123.0, x1: 423.0, z0: 147.0, z1: 412.0, y: 554.0, material: light}), 2));
     let center = Vec3::from(400.0, 400.0, 200.0);
     let moving_material = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.7, 0.3, 0.1))));
-    list.push(build_moving_sphere(center, center + Vec3::from(30.0, 0.0, 0.0), 50.0, moving_material, 0.0, 1.0));
-    list.push(build_sphere(Vec3::from(260.0, 150.0, 45.0), 50.0, Box::new(Dielectric::with_refraction_index(1.5))));
-    list.push(build_sphere(Vec3::from(-60.0, 115.0, 25.0), 50.0, Box::new(Dielectric::with_refraction_index(1.5))));
-    list.push(build_sphere(Vec3::from(0.0, 150.0, 145.0), 50.0, Metal::build_new(Vec3::from(0.8, 0.8, 0.9), 10.0)));
+    list.push(Tagged::with_material_id(build_moving_sphere(center, center + Vec3::from(30.0, 0.0, 0.0), 50.0, moving_material, 0.0, 1.0), 3));
+    list.push(Tagged::with_material_id(build_sphere(Vec3::from(260.0, 150.0, 45.0), 50.0, Box::new(Dielectric::with_refraction_index(1.5))), 4));
+    list.push(Tagged::with_material_id(build_sphere(Vec3::from(-60.0, 115.0, 25.0), 50.0, Box::new(Dielectric::with_refraction_index(1.5))), 4));
+    list.push(Tagged::with_material_id(build_sphere(Vec3::from(0.0, 150.0, 145.0), 50.0, Metal::build_new(Vec3::from(0.8, 0.8, 0.9), 10.0)), 5));
     let boundary = build_sphere(Vec3::from(360.0, 150.0, 145.0), 70.0, Box::new(Dielectric::with_refraction_index(1.5)));
-    list.push(boundary);
+    list.push(Tagged::with_material_id(boundary, 4));
     let boundary = build_sphere(Vec3::from(360.0, 150.0, 145.0), 70.0, Box::new(Dielectric::with_refraction_index(1.5)));
-    list.push(ConstantMedium::build_new(0.2, boundary, ConstantTexture::new_with_colour(Vec3::from(0.2, 0.4, 0.9))));
+    list.push(Tagged::with_material_id(ConstantMedium::build_new(0.2, boundary, ConstantTexture::new_with_colour(Vec3::from(0.2, 0.4, 0.9))), 6));
     let boundary = build_sphere(Vec3::from(0.0, 0.0, 0.0), 5000.0, Box::new(Dielectric::with_refraction_index(1.5)));
-    list.push(ConstantMedium::build_new(0.0001, boundary, ConstantTexture::new_with_colour(Vec3::from(1.0, 1.0, 1.0))));
+    list.push(Tagged::with_material_id(ConstantMedium::build_new(0.0001, boundary, ConstantTexture::new_with_colour(Vec3::from(1.0, 1.0, 1.0))), 7));
 
-    let img = Box::new(ImageTexture::load(String::from("land_ocean_ice_cloud_2048.png")));
+    let img = Box::new(ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png")));
     let mat = Box::new(Lambertian::with_texture(img));
-    list.push(build_sphere(Vec3::from(400.0, 200.0, 400.0), 100.0, mat));
+    list.push(Tagged::with_material_id(build_sphere(Vec3::from(400.0, 200.0, 400.0), 100.0, mat), 8));
 
     let pertext = NoiseTexture::build(rnd, 0.1);
-    list.push(build_sphere(Vec3::from(220.0, 280.0, 300.0),  80.0, Box::new(Lambertian::with_texture(pertext))));
+    list.push(Tagged::with_material_id(build_sphere(Vec3::from(220.0, 280.0, 300.0),  80.0, Box::new(Lambertian::with_texture(pertext))), 9));
 
-    for _j in 0..1000 {
-        let white = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.73, 0.73, 0.73))));
-        boxlist2.push(build_sphere(Vec3::from(165.0*rnd.gen(), 165.0 * rnd.gen(), 165.0*rnd.gen()), 10.0, white));
-    }
+    let particles = (0..1000).map(|_| Particle::build(Vec3::from(165.0*rnd.gen(), 165.0 * rnd.gen(), 165.0*rnd.gen()), 10.0)).collect();
+    let white = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.73, 0.73, 0.73))));
     let collection = Translate {
         offset: Vec3::from(-100.0, 270.0, 395.0),
-        obj : RotateY::create_new(
-            BvhTree::build(&mut boxlist2, rnd, 0.0, 1.0).root, 15.0)
+        obj : RotateY::create_new(PointCloud::build(particles, white), 15.0)
     };
-    list.push(Box::new(collection));
+    list.push(Tagged::with_material_id(Box::new(collection), 10));
 
     let look_from = Vec3::from(478.0, 278.0, -600.0);
     let look_at = Vec3::from(278.0, 278.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
 
-    (list, camera)
+    (list, Box::new(camera))
 }
 
-pub fn cornell_smoke(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
+pub fn cornell_smoke(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(8);
     
     let red = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.65, 0.05, 0.05))));
@@ -110,12 +193,12 @@ pub fn cornell_smoke(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera)
     let look_at = Vec3::from(278.0, 278.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
-    (list, camera)
+    (list, Box::new(camera))
 }
 
-pub fn cornell_box(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
+pub fn cornell_box(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(7);
 
     let red = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.65, 0.05, 0.05))));
@@ -126,49 +209,515 @@ pub fn cornell_box(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
     let white5 = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.73, 0.73, 0.73))));
     let green = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.12, 0.45, 0.15))));
     let light = Box::new(DiffuseLight {emit: ConstantTexture::new_with_colour(Vec3::from(15.0, 15.0, 15.0))});
-    list.push(FlipNormals::new_with_obj(Box::new(YzRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, x: 555.0, material: green})));
-    list.push(Box::new(YzRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, x: 0.0, material: red}));
-    list.push(Box::new(XzRect {x0: 213.0, x1: 343.0, z0: 227.0, z1: 332.0, y: 554.0, material: light}));
-    list.push(FlipNormals::new_with_obj(Box::new(XzRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, y: 555.0, material: white})));
-    list.push(Box::new(XzRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, y: 0.0, material: white2}));
-    list.push(FlipNormals::new_with_obj(Box::new(XyRect {x0: 0.0, x1: 555.0, y0: 0.0, y1: 555.0, z: 555.0, material: white3})));
+    // Material ids for the material id pass, all the white surfaces share one.
+    list.push(Tagged::with_material_id(FlipNormals::new_with_obj(Box::new(YzRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, x: 555.0, material: green})), 1));
+    list.push(Tagged::with_material_id(Box::new(YzRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, x: 0.0, material: red}), 2));
+    list.push(Tagged::with_material_id(Box::new(XzRect {x0: 213.0, x1: 343.0, z0: 227.0, z1: 332.0, y: 554.0, material: light}), 3));
+    list.push(Tagged::with_material_id(FlipNormals::new_with_obj(Box::new(XzRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, y: 555.0, material: white})), 4));
+    list.push(Tagged::with_material_id(Box::new(XzRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, y: 0.0, material: white2}), 4));
+    list.push(Tagged::with_material_id(FlipNormals::new_with_obj(Box::new(XyRect {x0: 0.0, x1: 555.0, y0: 0.0, y1: 555.0, z: 555.0, material: white3})), 4));
 
-    list.push(
+    list.push(Tagged::with_material_id(
         Box::new(Translate { 
             offset: Vec3::from(130.0, 0.0,  65.0), 
             obj: RotateY::create_new(
                 BoxShape::new_from(&Vec3::from(0.0, 0.0, 0.0), &Vec3::from(165.0, 165.0, 165.0), white4),
                 -18.0
             )
-        })
+        }), 4)
     );
 
-    list.push(
+    list.push(Tagged::with_material_id(
         Box::new(Translate { 
             offset: Vec3::from(265.0, 0.0, 295.0), 
             obj: RotateY::create_new(
                 BoxShape::new_from(&Vec3::from(0.0, 0.0, 0.0), &Vec3::from(165.0, 330.0, 165.0), white5),
                 15.0
             )
-        })
+        }), 4)
     );
 
     let look_from = Vec3::from(278.0, 278.0, -800.0);
     let look_at = Vec3::from(278.0, 278.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
-    (list, camera)
+    (list, Box::new(camera))
+}
+
+// The Cornell box with a ball rolling across the front of it while the
+// shutter is open, for the cameras below to blur in their own way.
+fn cornell_motion(nx : usize, ny : usize) -> Vec<Box<dyn Hitable>> {
+    let (mut list, _) = cornell_box(nx, ny);
+    let blue = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.2, 0.3, 0.8))));
+    list.push(build_moving_sphere(Vec3::from(80.0, 50.0, 30.0), Vec3::from(480.0, 50.0, 30.0), 50.0, blue, 0.0, 1.0));
+    list
+}
+
+// The Cornell box seen all the way round from the middle of the room.
+pub fn cornell_panorama(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let list = cornell_motion(nx, ny);
+    let look_from = Vec3::from(278.0, 278.0, 100.0);
+    let look_at = Vec3::from(278.0, 278.0, 555.0);
+    let camera = PanoramaCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 0.0, 1.0)
+        .with_shutter(Shutter::build(0.0, 0.5));
+    (list, Box::new(camera))
+}
+
+// Straight on without perspective, the side walls disappear edge on.
+// The shutter eases open and shut, so the ball's trail fades at both ends.
+pub fn cornell_orthographic(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let list = cornell_motion(nx, ny);
+    let look_from = Vec3::from(278.0, 278.0, -800.0);
+    let look_at = Vec3::from(278.0, 278.0, 0.0);
+    let shutter = Shutter::build(0.0, 1.0).with_curve(ShutterCurve::trapezoid(0.3, 0.3));
+    let camera = OrthographicCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        600.0, (nx as f32) / (ny as f32), 0.0, 1.0)
+        .with_shutter(shutter);
+    (list, Box::new(camera))
+}
+
+// A 180 degree fisheye from just inside the open side of the box, read out
+// top to bottom while the ball rolls past underneath.
+pub fn cornell_fisheye(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let list = cornell_motion(nx, ny);
+    let look_from = Vec3::from(278.0, 278.0, 20.0);
+    let look_at = Vec3::from(278.0, 278.0, 555.0);
+    let camera = FisheyeCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        180.0, (nx as f32) / (ny as f32), 0.0, 0.2)
+        .with_shutter(Shutter::build(0.0, 0.2).with_rolling(0.8));
+    (list, Box::new(camera))
+}
+
+// Through a 50mm double Gauss at f/2, with the room measured in centimetres.
+pub fn cornell_lens(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let list = cornell_motion(nx, ny);
+    let elements = load_lens_file("lenses/dgauss.50mm.dat").expect("Could not load lenses/dgauss.50mm.dat");
+    let look_from = Vec3::from(278.0, 278.0, -1000.0);
+    let look_at = Vec3::from(278.0, 278.0, 0.0);
+    let focus_distance = 1200.0;
+    let camera = RealisticCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), elements,
+        25.0, focus_distance, 43.3, (nx as f32) / (ny as f32), 0.1, 0.0, 1.0)
+        .with_shutter(Shutter::build(0.0, 0.5));
+    (list, Box::new(camera))
+}
+
+pub fn bokeh_lights(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(64);
+
+    // Small bright lights far behind the focus, each one blurs into the shape of the aperture.
+    for _ in 0..50 {
+        let centre = Vec3::from(16.0 * rnd.gen() - 8.0, 8.0 * rnd.gen() - 1.0, -15.0 - 10.0 * rnd.gen());
+        let colour = Vec3::from(0.5 + 0.5 * rnd.gen(), 0.5 + 0.5 * rnd.gen(), 0.5 + 0.5 * rnd.gen());
+        list.push(build_sphere(centre, 0.05, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(&colour * 2000.0) })));
+    }
+    list.push(build_sphere(Vec3::from(0.0, 1.0, 0.0), 1.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.7, 0.3, 0.2))))));
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(4.0, 8.0, 6.0), 2.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(20.0, 20.0, 20.0))})));
+
+    // Star shaped bokeh, falling back to a six bladed iris without the mask.
+    let aperture = match ApertureMask::load("textures/aperture_star.png") {
+        Ok(mask) => Aperture::Mask(mask),
+        Err(err) => {
+            eprintln!("{}", err);
+            Aperture::Polygon { blades: 6, rotation: 0.0 }
+        }
+    };
+    let look_from = Vec3::from(0.0, 1.2, 6.0);
+    let look_at = Vec3::from(0.0, 1.0, 0.0);
+    let focus_distance = (look_from - look_at).length();
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        30.0, (nx as f32) / (ny as f32), 0.6, focus_distance, 0.0, 1.0)
+        .with_aperture(aperture)
+        .with_cat_eye(0.4);
+    (list, Box::new(camera))
+}
+
+pub fn physical_portrait(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(8);
+
+    // Metres, with emission in cd/m^2 so the camera settings decide the exposure.
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(0.0, 0.3, 0.0), 0.3, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.7, 0.3, 0.2))))));
+    list.push(build_sphere(Vec3::from(-0.8, 0.3, -2.0), 0.3, Metal::build_new(Vec3::from(0.8, 0.8, 0.8), 0.05)));
+    list.push(build_sphere(Vec3::from(0.9, 0.3, -4.0), 0.3, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.2, 0.4, 0.7))))));
+    list.push(build_sphere(Vec3::from(-2.0, 6.0, 3.0), 1.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(40000.0, 38000.0, 35000.0))})));
+
+    // An 85mm at f/2, focused on the front sphere, with a seven bladed iris
+    // that the lens barrel clips into cat's eyes towards the corners.
+    let settings = PhysicalSettings { focal_length_mm: 85.0, f_number: 2.0, shutter_time: 1.0 / 125.0, iso: 200.0, ..PhysicalSettings::default() };
+    let look_from = Vec3::from(0.0, 0.5, 3.0);
+    let look_at = Vec3::from(0.0, 0.3, 0.0);
+    let focus_distance = (look_from - look_at).length();
+    let camera = PhysicalCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), &settings,
+        (nx as f32) / (ny as f32), focus_distance, 0.001, 0.0)
+        .with_aperture(Aperture::Polygon { blades: 7, rotation: 0.0 })
+        .with_cat_eye(0.3);
+    (list, Box::new(camera))
+}
+
+pub fn rolling_shutter(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(16);
+
+    // A column of balls all moving sideways at 20m/s, the sensor is read out
+    // top to bottom while they go past so the column comes out leaning.
+    for i in 0..8 {
+        let y = 0.25 + 0.5 * i as f32;
+        let colour = Vec3::from(0.2 + 0.1 * i as f32, 0.3, 0.9 - 0.1 * i as f32);
+        list.push(build_moving_sphere(Vec3::from(-1.0, y, 0.0), Vec3::from(1.0, y, 0.0), 0.25,
+            Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(colour))), 0.0, 0.1));
+    }
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(3.0, 10.0, 8.0), 2.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(40000.0, 40000.0, 40000.0))})));
+
+    // A 1/500s exposure with soft shutter edges, and a 1/20s readout.
+    let settings = PhysicalSettings { focal_length_mm: 35.0, f_number: 5.6, shutter_time: 1.0 / 500.0, iso: 3200.0, ..PhysicalSettings::default() };
+    let look_from = Vec3::from(0.0, 2.0, 8.0);
+    let look_at = Vec3::from(0.0, 2.0, 0.0);
+    let focus_distance = (look_from - look_at).length();
+    let shutter = Shutter::build(0.0, settings.shutter_time)
+        .with_curve(ShutterCurve::trapezoid(0.2, 0.2))
+        .with_rolling(0.05);
+    let camera = PhysicalCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), &settings,
+        (nx as f32) / (ny as f32), focus_distance, 0.001, 0.0)
+        .with_shutter(shutter);
+    (list, Box::new(camera))
+}
+
+// The earth stretched down three long strips running away from the camera,
+// looked up nearest, bilinear and trilinear from left to right. Only the
+// last one stays calm in the distance.
+pub fn texture_filtering(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
+
+    let filters = [TextureFilter::Nearest, TextureFilter::Bilinear, TextureFilter::Trilinear];
+    for (i, filter) in filters.iter().enumerate() {
+        let earth = ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png")).with_filter(*filter);
+        let x0 = -4.5 + 3.0 * i as f32;
+        list.push(Box::new(XzRect { x0, x1: x0 + 2.9, z0: -200.0, z1: 0.0, y: 0.0, material: Box::new(Lambertian::with_texture(Box::new(earth))) }));
+    }
+    list.push(build_sphere(Vec3::from(0.0, 0.0, 0.0), 5000.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(1.0, 1.0, 1.0))})));
+
+    let look_from = Vec3::from(0.0, 1.5, 4.0);
+    let look_at = Vec3::from(0.0, 0.0, -20.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// A glowing sign cut out by the alpha channel of the star image, lighting
+// an earth decoded from sRGB in front of it.
+pub fn star_sign(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
+
+    match ImageTexture::load_alpha(String::from("textures/aperture_star.png")) {
+        Ok(star) => list.push(Box::new(XyRect { x0: -3.0, x1: 3.0, y0: 0.5, y1: 6.5, z: -3.0, material: Box::new(DiffuseLight { emit: Box::new(star) }) })),
+        Err(err) => eprintln!("{}", err),
+    }
+    let earth = ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png"));
+    list.push(build_sphere(Vec3::from(0.0, 1.0, 0.0), 1.0, Box::new(Lambertian::with_texture(Box::new(earth)))));
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(5.0, 8.0, 8.0), 2.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(8.0, 8.0, 8.0))})));
+
+    let look_from = Vec3::from(0.0, 2.0, 8.0);
+    let look_at = Vec3::from(0.0, 2.0, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        50.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// Perlin noise bumping a sphere into rough stone, in front of a panel of
+// studs that are only in its normal map.
+pub fn bump_maps(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
+
+    let stone = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.6, 0.55, 0.5))));
+    list.push(build_sphere(Vec3::from(0.0, 1.0, 0.0), 1.0, BumpMap::build(stone, NoiseTexture::build(rnd, 4.0), 0.02)));
+    // Normals are data, they mustn't go through the sRGB decode.
+    match ImageTexture::load_with_colour_space(String::from("textures/normal_studs.png"), ColourSpace::Linear) {
+        Ok(studs) => {
+            let panel = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.2, 0.4, 0.7))));
+            list.push(Box::new(XyRect { x0: -2.5, x1: 2.5, y0: 0.0, y1: 5.0, z: -2.0, material: NormalMap::build(panel, Box::new(studs), 1.0) }));
+        },
+        Err(err) => eprintln!("{}", err),
+    }
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(-4.0, 6.0, 6.0), 1.5, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(10.0, 10.0, 10.0))})));
+
+    let look_from = Vec3::from(0.0, 2.0, 7.0);
+    let look_at = Vec3::from(0.0, 1.5, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        45.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// Two rows of spheres, fBm, ridged, warped fBm and marble at the back and
+// the three Worley features and wood at the front. The marble's veins
+// drift over the exposure.
+pub fn noise_textures(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(12);
+
+    let fbm = FbmTexture::build(rnd, 2.0);
+    let ridged = RidgedTexture::build(rnd, 1.5);
+    let swirls = FbmTexture::build(rnd, 2.0);
+    let warped = DomainWarp::build(rnd, swirls, 1.0, 0.8);
+    let mut marble = MarbleTexture::build(rnd, Vec3::from(0.9, 0.9, 0.85), Vec3::from(0.3, 0.3, 0.35), 2.0);
+    marble.speed = 0.5;
+    let back : Vec<Box<dyn Texture>> = vec![fbm, ridged, warped, marble];
+    for (i, texture) in back.into_iter().enumerate() {
+        list.push(build_sphere(Vec3::from(-3.3 + 2.2 * i as f32, 1.0, -1.5), 1.0, Box::new(Lambertian::with_texture(texture))));
+    }
+    let wood = WoodTexture::build(rnd, Vec3::from(0.8, 0.6, 0.4), Vec3::from(0.45, 0.3, 0.15), 6.0);
+    let front : Vec<Box<dyn Texture>> = vec![
+        WorleyTexture::build(rnd, 3.0, WorleyFeature::F1),
+        WorleyTexture::build(rnd, 3.0, WorleyFeature::F2),
+        WorleyTexture::build(rnd, 3.0, WorleyFeature::Edges),
+        wood];
+    for (i, texture) in front.into_iter().enumerate() {
+        list.push(build_sphere(Vec3::from(-3.3 + 2.2 * i as f32, 0.8, 1.0), 0.8, Box::new(Lambertian::with_texture(texture))));
+    }
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(0.0, 10.0, 8.0), 3.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 3.0, 9.0);
+    let look_at = Vec3::from(0.0, 0.8, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// A row of spheres with one texture graph each, a box textured by
+// projection and a star decal mixed in by its own alpha.
+pub fn texture_graph(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(12);
+    let earth = || ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png"));
+    let constant = |r : f32, g : f32, b : f32| ConstantTexture::new_with_colour(Vec3::from(r, g, b));
+
+    let textures : Vec<Box<dyn Texture>> = vec![
+        MixTexture::build(constant(0.8, 0.2, 0.1), constant(0.1, 0.2, 0.8), WorleyTexture::build(rnd, 3.0, WorleyFeature::Edges)),
+        MultiplyTexture::build(Box::new(earth()), ColourRamp::build(FbmTexture::build(rnd, 2.0), vec![(0.3, Vec3::from(0.2, 0.2, 0.2)), (0.7, Vec3::from(1.0, 1.0, 1.0))])),
+        AddTexture::build(ScaleTexture::build(NoiseTexture::build(rnd, 4.0), Vec3::from(0.5, 0.3, 0.1)).with_offset(Vec3::from(0.1, 0.1, 0.1)),
+            ScaleTexture::build(WorleyTexture::build(rnd, 4.0, WorleyFeature::F2), Vec3::from(0.0, 0.2, 0.4))),
+        // Tiled twice, every other tile mirrored so the coastlines join up.
+        UvTransform::build(Box::new(earth().with_filter(TextureFilter::Bilinear).with_wrap(WrapMode::Mirror)), (2.0, 2.0))
+            .with_offset((0.25, 0.0))
+            .with_rotation(30.0),
+        SwizzleTexture::build(Box::new(earth()), [Channel::B, Channel::G, Channel::R]),
+        // Red read as green, over a constant blue.
+        SwizzleTexture::build(Box::new(earth()), [Channel::Zero, Channel::R, Channel::One]),
+    ];
+    let count = textures.len();
+    for (i, texture) in textures.into_iter().enumerate() {
+        let x = 2.2 * (i as f32 - 0.5 * (count - 1) as f32);
+        list.push(build_sphere(Vec3::from(x, 1.0, 0.0), 1.0, Box::new(Lambertian::with_texture(texture))));
+    }
+
+    // A box has no uv to speak of across its edges, the projection doesn't need any.
+    let projected = TriplanarTexture::build(Box::new(earth().with_wrap(WrapMode::Repeat)), 0.5).with_sharpness(8.0);
+    list.push(Box::new(Translate {
+        offset: Vec3::from(3.0, 0.0, 2.5),
+        obj: RotateY::create_new(BoxShape::new_from(&Vec3::default(), &Vec3::from(1.5, 1.5, 1.5), Box::new(Lambertian::with_texture(projected))), 30.0)
+    }));
+
+    match ImageTexture::load_alpha(String::from("textures/aperture_star.png")) {
+        Ok(mask) => {
+            let decal = Box::new(ImageTexture::load_or_missing(String::from("textures/aperture_star.png")));
+            let backing = CheckerTexture::new_with_textures(constant(0.2, 0.2, 0.2), constant(0.5, 0.5, 0.5));
+            list.push(Box::new(XyRect { x0: -2.0, x1: 2.0, y0: 0.0, y1: 4.0, z: -3.0,
+                material: Box::new(Lambertian::with_texture(MixTexture::build(backing, decal, Box::new(mask)))) }));
+        },
+        Err(err) => eprintln!("{}", err),
+    }
+
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(constant(0.5, 0.5, 0.5)))));
+    list.push(build_sphere(Vec3::from(0.0, 15.0, 10.0), 5.0, Box::new(DiffuseLight { emit: constant(5.0, 5.0, 5.0) })));
+
+    let look_from = Vec3::from(0.0, 3.0, 12.0);
+    let look_at = Vec3::from(0.0, 1.0, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        50.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// Marble on three spheres moving right: in world space the pattern stays put
+// and smears, in object space it rides along, and with a transform of its
+// own the veins are stretched and turned. Behind them a turned box of wood
+// with object space bumps that follow it.
+pub fn solid_textures(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(8);
+
+    let veins = TextureTransform::identity()
+        .with_scale(Vec3::from(0.5, 3.0, 0.5))
+        .with_rotation(Vec3::from(0.0, 0.0, 1.0), 30.0)
+        .with_translation(Vec3::from(0.0, 1.0, 0.0));
+    let spaces = [TextureSpace::World, TextureSpace::Object, TextureSpace::Transform(veins)];
+    for (i, space) in spaces.iter().enumerate() {
+        let marble = MarbleTexture::build(rnd, Vec3::from(0.9, 0.85, 0.8), Vec3::from(0.4, 0.2, 0.3), 2.0);
+        let center = Vec3::from(-2.6 + 2.2 * i as f32, 1.0, 1.0);
+        list.push(build_moving_sphere(center, center + Vec3::from(0.6, 0.0, 0.0), 0.9,
+            Box::new(Lambertian::with_texture(SolidTexture::build(marble, *space))), 0.0, 1.0));
+    }
+
+    let wood = WoodTexture::build(rnd, Vec3::from(0.8, 0.6, 0.4), Vec3::from(0.45, 0.3, 0.15), 4.0);
+    let grain = SolidTexture::build(NoiseTexture::build(rnd, 8.0), TextureSpace::Object);
+    let plank = BumpMap { material : Box::new(Lambertian::with_texture(SolidTexture::build(wood, TextureSpace::Object))), height : grain, scale : 0.02 };
+    let block = BoxShape::new_from(&Vec3::from(-1.5, 0.0, -0.5), &Vec3::from(1.5, 1.5, 0.5), Box::new(plank));
+    list.push(Box::new(Translate { offset : Vec3::from(0.5, 0.0, -2.0), obj : RotateY::create_new(block, 20.0) }));
+
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(0.0, 10.0, 8.0), 3.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 3.0, 9.0);
+    let look_at = Vec3::from(0.0, 0.8, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// A room built from quads at any angle, lit by a tilted quad, with a
+// triangle showing part of the earth and a disk strewn with pebbles.
+pub fn flat_shapes(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(64);
+    let matte = |colour : Vec3| -> Box<dyn Material> { Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(colour))) };
+    let up = Vec3::from(0.0, 1.0, 0.0);
+
+    // Floor and two walls meeting at an angle, all facing into the room.
+    let checker_texture = CheckerTexture::new_with_textures(
+        ConstantTexture::new_with_colour(Vec3::from(0.2, 0.3, 0.1)),
+        ConstantTexture::new_with_colour(Vec3::from(0.9, 0.9, 0.9))
+        );
+    list.push(Quad::build(Vec3::from(-6.0, 0.0, 6.0), Vec3::from(12.0, 0.0, 0.0), Vec3::from(0.0, 0.0, -12.0), Box::new(Lambertian::with_texture(checker_texture))));
+    list.push(Quad::build(Vec3::from(-6.0, 0.0, -2.0), Vec3::from(6.0, 0.0, -4.0), Vec3::from(0.0, 6.0, 0.0), matte(Vec3::from(0.7, 0.3, 0.2))));
+    list.push(Quad::build(Vec3::from(0.0, 0.0, -6.0), Vec3::from(6.0, 0.0, 4.0), Vec3::from(0.0, 6.0, 0.0), matte(Vec3::from(0.2, 0.4, 0.7))));
+    // The light faces down, leaning towards the front of the room.
+    list.push(Quad::build(Vec3::from(-1.5, 5.0, -1.0), Vec3::from(3.0, 0.0, 0.0), Vec3::from(0.0, -1.0, 2.0),
+        Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(8.0, 8.0, 8.0))})));
+
+    let earth = Box::new(ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png")));
+    list.push(Triangle::build(Vec3::from(-3.5, 0.0, 0.0), Vec3::from(-1.0, 0.0, -1.0), Vec3::from(-2.5, 2.5, -1.0), Box::new(Lambertian::with_texture(earth)))
+        .with_uvs([(0.0, 0.2), (1.0, 0.2), (0.5, 0.8)]));
+    let disk = Disk::build(Vec3::from(2.0, 0.01, 0.5), up, 1.5, matte(Vec3::from(0.4, 0.3, 0.2)));
+    // As many pebbles as the disk's area calls for, spread evenly over it.
+    let pebbles = (disk.area() * 15.0) as usize;
+    for _ in 0..pebbles {
+        let (point, normal) = disk.sample(rnd);
+        list.push(build_sphere(point + &normal * 0.04, 0.06, matte(Vec3::from(0.6, 0.6, 0.55))));
+    }
+    list.push(disk);
+    // A disk standing on its rim, turned towards the camera.
+    list.push(Disk::build(Vec3::from(0.0, 1.0, -1.5), Vec3::from(0.3, 0.0, 1.0), 1.0, Metal::build_new(Vec3::from(0.8, 0.8, 0.9), 0.05)));
+
+    let look_from = Vec3::from(0.0, 3.0, 9.0);
+    let look_at = Vec3::from(0.0, 1.5, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &up,
+        45.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// The surfaces of revolution in a row, cut open by their sweeps so both
+// sides show, with a capped cylinder lying on its side and a tilted torus.
+pub fn quadric_shapes(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(12);
+    let matte = |colour : Vec3| -> Box<dyn Material> { Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(colour))) };
+    let up = Vec3::from(0.0, 1.0, 0.0);
+
+    list.push(Cylinder::build(0.8, 0.0, 2.0, matte(Vec3::from(0.8, 0.3, 0.2))).with_caps(true).with_sweep(270.0).with_placement(Vec3::from(-6.0, 0.0, 0.0), up));
+    list.push(Cone::build(1.0, 2.0, matte(Vec3::from(0.8, 0.6, 0.2))).with_sweep(300.0).with_placement(Vec3::from(-3.0, 0.0, 0.0), up));
+    list.push(Paraboloid::build(1.0, 0.0, 2.0, matte(Vec3::from(0.3, 0.7, 0.3))).with_sweep(300.0).with_placement(Vec3::from(0.0, 0.0, 0.0), up));
+    list.push(Hyperboloid::build(0.4, 0.6, -1.0, 1.0, matte(Vec3::from(0.2, 0.5, 0.8))).with_sweep(300.0).with_placement(Vec3::from(3.0, 1.0, 0.0), up));
+    list.push(Torus::build(0.8, 0.3, Metal::build_new(Vec3::from(0.8, 0.8, 0.9), 0.05)).with_sweep(270.0).with_placement(Vec3::from(6.0, 1.0, 0.0), Vec3::from(0.0, 1.0, 1.0)));
+    list.push(Cylinder::build(0.5, -1.5, 1.5, matte(Vec3::from(0.7, 0.7, 0.7))).with_caps(true).with_placement(Vec3::from(-1.5, 0.5, 3.0), Vec3::from(1.0, 0.0, 0.3)));
+    list.push(Torus::build(1.0, 0.25, matte(Vec3::from(0.6, 0.2, 0.5))).with_placement(Vec3::from(2.5, 0.25, 3.0), up));
+
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, matte(Vec3::from(0.5, 0.5, 0.5))));
+    list.push(build_sphere(Vec3::from(0.0, 12.0, 10.0), 3.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 5.0, 13.0);
+    let look_at = Vec3::from(0.0, 0.8, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &up,
+        45.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// Constructive solid geometry on closed shapes: a rounded marble cube, a box
+// with a sphere bitten out of it, two spheres drilled through together, and
+// a glass lens cut from two overlapping spheres.
+pub fn csg_shapes(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(8);
+    let matte = |colour : Vec3| -> Box<dyn Material> { Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(colour))) };
+
+    let half = Vec3::from(0.8, 0.8, 0.8);
+    let centre = Vec3::from(-4.5, 0.8, 0.0);
+    let mut stone = || -> Box<dyn Material> {
+        let marble = MarbleTexture::build(rnd, Vec3::from(0.9, 0.85, 0.8), Vec3::from(0.5, 0.2, 0.4), 1.5);
+        Box::new(Lambertian::with_texture(SolidTexture::build(marble, TextureSpace::World)))
+    };
+    list.push(Csg::intersection(BoxShape::new_from(&(centre - half), &(centre + half), stone()), build_sphere(centre, 1.05, stone())));
+
+    let centre = Vec3::from(-1.5, 0.8, 0.0);
+    list.push(Csg::difference(BoxShape::new_from(&(centre - half), &(centre + half), matte(Vec3::from(0.9, 0.9, 0.9))),
+        build_sphere(centre + Vec3::from(0.0, 0.5, 0.5), 0.9, matte(Vec3::from(0.8, 0.3, 0.2)))));
+
+    let centre = Vec3::from(1.5, 0.8, 0.0);
+    let offset = Vec3::from(0.45, 0.0, 0.0);
+    let pair = Csg::union(build_sphere(centre - offset, 0.8, matte(Vec3::from(0.2, 0.6, 0.7))), build_sphere(centre + offset, 0.8, matte(Vec3::from(0.2, 0.6, 0.7))));
+    let drill = Cylinder::build(0.3, -1.0, 1.0, matte(Vec3::from(0.9, 0.8, 0.2))).with_caps(true).with_placement(centre, Vec3::from(0.0, 0.0, 1.0));
+    list.push(Csg::difference(pair, drill));
+
+    let centre = Vec3::from(4.5, 0.8, 0.0);
+    let offset = Vec3::from(0.0, 0.0, 1.2);
+    list.push(Csg::intersection(build_sphere(centre - offset, 1.5, Box::new(Dielectric::with_refraction_index(1.5))),
+        build_sphere(centre + offset, 1.5, Box::new(Dielectric::with_refraction_index(1.5)))));
+
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, matte(Vec3::from(0.5, 0.5, 0.5))));
+    list.push(build_sphere(Vec3::from(0.0, 12.0, 10.0), 3.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 4.0, 10.0);
+    let look_at = Vec3::from(0.0, 0.8, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        50.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
 }
 
-pub fn simple_light(nx : usize, ny : usize, rnd: &mut Random) -> (Vec<Box<dyn Hitable>>, Camera) {
+// Ray marched distance fields: a Mandelbulb in the middle, a sphere and a
+// capsule blended together, a rounded box with a bowl carved out of it and
+// a torus lying in front.
+pub fn distance_fields(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(8);
+
+    // Distance fields have no uv, so they're textured by position.
+    let earth = ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png"))
+        .with_filter(TextureFilter::Bilinear)
+        .with_wrap(WrapMode::Mirror);
+    let projected = TriplanarTexture::build(Box::new(earth), 0.5).with_sharpness(8.0);
+    list.push(SdfHitable::build(Mandelbulb::build(Vec3::from(0.0, 1.3, 0.0), 1.1), Box::new(Lambertian::with_texture(projected))).with_epsilon(1e-3));
+
+    let marble = MarbleTexture::build(rnd, Vec3::from(0.9, 0.9, 0.85), Vec3::from(0.2, 0.25, 0.3), 2.0);
+    let blob = SmoothUnion::build(SdfSphere::build(Vec3::from(-3.0, 0.8, 0.0), 0.8), Capsule::build(Vec3::from(-3.5, 0.3, 0.6), Vec3::from(-2.2, 1.9, -0.6), 0.35), 0.4);
+    list.push(SdfHitable::build(blob, Box::new(Lambertian::with_texture(SolidTexture::build(marble, TextureSpace::World)))));
+
+    let carved = SmoothSubtract::build(RoundBox::build(Vec3::from(3.0, 0.9, 0.0), Vec3::from(0.8, 0.8, 0.8), 0.1), SdfSphere::build(Vec3::from(3.0, 1.7, 0.0), 0.7), 0.1);
+    list.push(SdfHitable::build(carved, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.8, 0.5, 0.3))))));
+
+    list.push(SdfHitable::build(SdfTorus::build(Vec3::from(0.0, 0.25, 2.5), 1.0, 0.25), Metal::build_new(Vec3::from(0.9, 0.8, 0.5), 0.1)));
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(0.0, 12.0, 6.0), 4.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 3.0, 9.0);
+    let look_at = Vec3::from(0.0, 1.0, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        45.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+pub fn simple_light(nx : usize, ny : usize, rnd: &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
 
     let perlin = NoiseTexture::build(rnd, 4.0);
     list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(perlin))));
     // let perlin = NoiseTexture::build(rnd, 4.0);
-    let perlin = Box::new(ImageTexture::load(String::from("land_ocean_ice_cloud_2048.png")));
+    let perlin = Box::new(ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png")));
     list.push(build_sphere(Vec3::from(0.0, 2.0, 0.0), 2.0, Box::new(Lambertian::with_texture(perlin))));
     list.push(build_sphere(Vec3::from(0.0, 7.0, 0.0), 2.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(4.0, 4.0, 4.0))})));
     list.push(Box::new(XyRect {x0: 3.0, x1: 5.0, y0: 1.0, y1: 3.0, z: -2.0, material: Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(4.0, 4.0, 4.0))})} ));
@@ -177,13 +726,197 @@ pub fn simple_light(nx : usize, ny : usize, rnd: &mut Random) -> (Vec<Box<dyn Hi
     let look_at = Vec3::from(0.0, 2.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
-    (list, camera)
+    (list, Box::new(camera))
+}
+
+pub fn terrain(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(2);
+
+    let mountains = RidgedTexture::build(rnd, 0.12);
+    let colour = ColourRamp::build(FbmTexture::build(rnd, 0.5), vec![
+        (0.35, Vec3::from(0.25, 0.35, 0.12)),
+        (0.55, Vec3::from(0.45, 0.38, 0.25)),
+        (0.7, Vec3::from(0.8, 0.8, 0.8))]);
+    list.push(Heightfield::from_texture(&*mountains, (512, 512), Vec3::from(-20.0, 0.0, -20.0), Vec3::from(20.0, 6.0, 20.0),
+        Box::new(Lambertian::with_texture(colour))));
+    list.push(build_sphere(Vec3::from(-30.0, 70.0, -30.0), 45.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(3.0, 2.9, 2.8))})));
+
+    let look_from = Vec3::from(18.0, 10.0, 18.0);
+    let look_at = Vec3::from(0.0, 2.0, 0.0);
+    let focus_distance = 10.0;
+    let aperture = 0.0;
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        45.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
+    (list, Box::new(camera))
 }
 
-pub fn earth_scene(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
-    let image_texture = ImageTexture::load(String::from("land_ocean_ice_cloud_2048.png"));
+// A terraced hill read from a height map, coloured by the same image through
+// a ramp from grass to snow.
+pub fn height_map(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(3);
+
+    let path = String::from("textures/hill_height.png");
+    let hill = ImageTexture::load_with_colour_space(path.clone(), ColourSpace::Linear).and_then(|heights| {
+        let colour = ColourRamp::build(Box::new(heights.with_filter(TextureFilter::Bilinear)), vec![
+            (0.2, Vec3::from(0.25, 0.35, 0.12)),
+            (0.6, Vec3::from(0.45, 0.38, 0.25)),
+            (0.85, Vec3::from(0.8, 0.8, 0.8))]);
+        Heightfield::from_image(path, Vec3::from(-8.0, 0.05, -8.0), Vec3::from(8.0, 4.0, 8.0), Box::new(Lambertian::with_texture(colour)))
+    });
+    match hill {
+        Ok(hill) => list.push(hill),
+        Err(err) => eprintln!("{}", err),
+    }
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.2, 0.3, 0.5))))));
+    list.push(build_sphere(Vec3::from(-30.0, 60.0, -20.0), 30.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(3.0, 2.9, 2.8))})));
+
+    let look_from = Vec3::from(12.0, 9.0, 14.0);
+    let look_at = Vec3::from(0.0, 1.5, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+pub fn subdivision_surfaces(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
+
+    let corners = (0..8).map(|i| Vec3::from((i & 1) as f32 * 2.0 - 1.0, ((i >> 1) & 1) as f32 * 2.0, ((i >> 2) & 1) as f32 * 2.0 - 1.0)).collect();
+    let faces = vec![vec![0, 2, 3, 1], vec![4, 5, 7, 6], vec![0, 1, 5, 4], vec![2, 6, 7, 3], vec![0, 4, 6, 2], vec![1, 3, 7, 5]];
+    let uvs = faces.iter().map(|_| vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]).collect();
+    let cube = Cage::build(corners, faces).with_uvs(uvs)
+        .with_crease(2, 3, 4.0).with_crease(3, 7, 4.0).with_crease(7, 6, 4.0).with_crease(6, 2, 4.0);
+
+    let earth = Box::new(ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png")));
+    list.push(cube.subdivided_mesh(SubdivisionScheme::CatmullClark, 3, Box::new(Lambertian::with_texture(earth))).into_bvh(rnd));
+    let moved = Cage { positions : cube.positions.iter().map(|p| p + &Vec3::from(0.0, 0.0, -3.0)).collect(), ..cube.clone() };
+    list.push(moved.subdivided_mesh(SubdivisionScheme::Loop, 3, Metal::build_new(Vec3::from(0.8, 0.8, 0.9), 0.1)).into_bvh(rnd));
+    // Loaded with its equator creased, so it rounds into a lens with a ridge.
+    match Cage::load("models/creased_octahedron.obj") {
+        Ok(octahedron) => list.push(Box::new(Translate {
+            offset: Vec3::from(0.0, 1.0, 2.5),
+            obj: octahedron.subdivided_mesh(SubdivisionScheme::CatmullClark, 3, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.7, 0.3, 0.2))))).into_bvh(rnd)
+        })),
+        Err(err) => eprintln!("{}", err),
+    }
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(10.0, 20.0, 10.0), 10.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(8.0, 8.0, 8.0))})));
+
+    let look_from = Vec3::from(8.0, 4.0, 3.0);
+    let look_at = Vec3::from(0.0, 1.0, -1.5);
+    let focus_distance = 10.0;
+    let aperture = 0.0;
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+pub fn fur_ball(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(4);
+
+    let centre = Vec3::from(0.0, 1.0, 0.0);
+    let mut strands = Vec::with_capacity(20000);
+    for _ in 0..20000 {
+        let normal = random_in_unit_sphere(rnd);
+        let normal = &normal / normal.length();
+        let length = 0.35 + 0.15 * rnd.gen();
+        let root = centre + normal;
+        // Stand out from the skin, then droop under their own weight.
+        let strand = (0..4).map(|k| {
+            let s = k as f32 / 3.0;
+            let jitter = &random_in_unit_sphere(rnd) * (0.03 * s);
+            let point = root + &normal * (length * s) + Vec3::from(0.0, -0.25 * s * s * length, 0.0) + jitter;
+            (point, 0.012 * (1.0 - s) + 0.002 * s)
+        }).collect();
+        strands.push(strand);
+    }
+    list.push(Groom::build(&strands, Hair::from_melanin(0.5, 0.8).with_roughness(0.25, 0.3)).into_bvh(rnd));
+    list.push(build_sphere(centre, 1.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.3, 0.2, 0.1))))));
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(-6.0, 10.0, 6.0), 5.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(10.0, 10.0, 10.0))})));
+
+    let look_from = Vec3::from(0.0, 2.0, 6.0);
+    let look_at = Vec3::from(0.0, 1.0, 0.0);
+    let focus_distance = 10.0;
+    let aperture = 0.0;
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        35.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+// A tuft of strands loaded from a file, once as auburn hair and once as
+// thick tubes, under a single arching ribbon.
+pub fn hair_tufts(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(6);
+    let matte = |colour : Vec3| -> Box<dyn Material> { Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(colour))) };
+
+    let hair = Hair::from_colour(Vec3::from(0.6, 0.35, 0.2)).with_scale_tilt(3.0).with_refraction_index(1.55);
+    match Groom::load("models/hair_tuft.txt", hair) {
+        Ok(tuft) => list.push(Box::new(Translate { offset: Vec3::from(-1.0, 0.0, 0.0), obj: tuft.into_bvh(rnd) })),
+        Err(err) => eprintln!("{}", err),
+    }
+    match Groom::load("models/hair_tuft.txt", matte(Vec3::from(0.3, 0.6, 0.3))) {
+        Ok(tubes) => list.push(Box::new(Translate { offset: Vec3::from(1.0, 0.0, 0.0), obj: tubes.with_shape(CurveShape::Cylinder).into_bvh(rnd) })),
+        Err(err) => eprintln!("{}", err),
+    }
+    list.push(Curve::build([Vec3::from(-2.5, 0.0, -1.0), Vec3::from(-1.5, 3.0, -1.0), Vec3::from(1.5, 3.0, -1.0), Vec3::from(2.5, 0.0, -1.0)], (0.2, 0.05),
+        matte(Vec3::from(0.8, 0.8, 0.3))).with_shape(CurveShape::Ribbon));
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, matte(Vec3::from(0.5, 0.5, 0.5))));
+    list.push(build_sphere(Vec3::from(-6.0, 10.0, 6.0), 5.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(10.0, 10.0, 10.0))})));
+
+    let look_from = Vec3::from(0.0, 1.5, 5.0);
+    let look_at = Vec3::from(0.0, 0.9, 0.0);
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), 0.0, 10.0, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+pub fn point_clouds(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(6);
+
+    // A shell of small spheres coloured by direction.
+    let shell = (0..500000).map(|_| {
+        let direction = random_in_unit_sphere(rnd);
+        let direction = &direction / direction.length();
+        let position = Vec3::from(-2.0, 1.5, 0.0) + &direction * (1.5 + 0.05 * rnd.gen());
+        Particle::build(position, 0.01).with_colour(&(direction + Vec3::from(1.0, 1.0, 1.0)) * 0.5)
+    }).collect();
+    list.push(PointCloud::build(shell, Box::new(Lambertian::with_texture(GeometryColour::build()))));
+
+    // Splats over a torus, hue running round it.
+    let torus = (0..200000).map(|_| {
+        let (a, b) = (2.0 * std::f32::consts::PI * rnd.gen(), 2.0 * std::f32::consts::PI * rnd.gen());
+        let position = Vec3::from(2.0 + (1.0 + 0.4 * b.cos()) * a.cos(), 1.5 + 0.4 * b.sin(), (1.0 + 0.4 * b.cos()) * a.sin());
+        let colour = Vec3::from(0.5 + 0.5 * a.cos(), 0.5 + 0.5 * (a + 2.1).cos(), 0.5 + 0.5 * (a + 4.2).cos());
+        Particle::build(position, 0.015).with_colour(colour)
+    }).collect();
+    list.push(PointCloud::build(torus, Box::new(Lambertian::with_texture(GeometryColour::build()))).with_shape(PointShape::Disc));
+
+    // Clouds read from file in front, the spiral from PLY and the helix from CSV.
+    match PointCloud::load("models/rising_spiral.ply", 0.03, Box::new(Lambertian::with_texture(GeometryColour::build()))) {
+        Ok(spiral) => list.push(Box::new(Translate { offset: Vec3::from(-1.0, 0.0, 2.5), obj: spiral })),
+        Err(err) => eprintln!("{}", err),
+    }
+    match PointCloud::load("models/double_helix.csv", 0.03, Box::new(Lambertian::with_texture(GeometryColour::build()))) {
+        Ok(helix) => list.push(Box::new(Translate { offset: Vec3::from(1.0, 0.0, 2.5), obj: helix.with_shape(PointShape::Disc) })),
+        Err(err) => eprintln!("{}", err),
+    }
+
+    list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))));
+    list.push(build_sphere(Vec3::from(0.0, 12.0, 8.0), 5.0, Box::new(DiffuseLight { emit: ConstantTexture::new_with_colour(Vec3::from(6.0, 6.0, 6.0))})));
+
+    let look_from = Vec3::from(0.0, 3.0, 9.0);
+    let look_at = Vec3::from(0.0, 1.5, 0.0);
+    let focus_distance = 10.0;
+    let aperture = 0.0;
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+        40.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
+    (list, Box::new(camera))
+}
+
+pub fn earth_scene(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
+    let image_texture = ImageTexture::load_or_missing(String::from("land_ocean_ice_cloud_2048.png"));
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(1);
     let earth = build_sphere(Vec3::from(0.0, 0.0, 0.0), 2.0, Box::new(Lambertian::with_texture(Box::new(image_texture))));
     list.push(earth);
@@ -192,12 +925,12 @@ pub fn earth_scene(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
     let look_at = Vec3::from(0.0, 0.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0),
     20.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
-    (list, camera)
+    (list, Box::new(camera))
 }
 
-pub fn two_perlin_spheres(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Camera) {
+pub fn two_perlin_spheres(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let noise_texture = NoiseTexture::build(rnd, 4.0);
     let mut list : Vec<Box<dyn Hitable>> = Vec::with_capacity(2);
     list.push(build_sphere(Vec3::from(0.0, -1000.0, 0.0), 1000.0, Box::new(Lambertian::with_texture(noise_texture))));
@@ -208,17 +941,17 @@ pub fn two_perlin_spheres(nx : usize, ny : usize, rnd : &mut Random) -> (Vec<Box
     let look_at = Vec3::from(0.0, 0.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         20.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
-    (list, camera)
+    (list, Box::new(camera))
 }
 
-pub fn two_spheres(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
+pub fn two_spheres(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let look_from = Vec3::from(13.0, 2.0, 3.0);
     let look_at = Vec3::from(0.0, 0.0, 0.0);
     let focus_distance = 10.0;
     let aperture = 0.0;
-    let camera = Camera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
+    let camera = PerspectiveCamera::build(&look_from, &look_at, &Vec3::from(0.0, 1.0, 0.0), 
         20.0, (nx as f32) / (ny as f32), aperture, focus_distance, 0.0, 1.0);
     let checker_texture = CheckerTexture::new_with_textures(
         ConstantTexture::new_with_colour(Vec3::from(0.2,0.3,0.1)), 
@@ -232,17 +965,17 @@ pub fn two_spheres(nx : usize, ny : usize) -> (Vec<Box<dyn Hitable>>, Camera) {
         );
     list.push(build_sphere(Vec3::from(0.0,  10.0, 0.0), 10.0, Box::new(Lambertian::with_texture(checker_texture))));
     
-    (list, camera)
+    (list, Box::new(camera))
 }
 
-pub fn random_moving_scene(nx : usize, ny : usize, rnd: &mut Random, _time_start: f32, _time_end: f32) -> (Vec<Box<dyn Hitable>>, Camera) {
+pub fn random_moving_scene(nx : usize, ny : usize, rnd: &mut Random, _time_start: f32, _time_end: f32) -> (Vec<Box<dyn Hitable>>, Box<dyn Camera>) {
     let look_from = Vec3::from(13.0, 2.0, 3.0);
     let look_at = Vec3::from(0.0, 0.0, 0.0);
     let aperture = 0.1;
     let dist_to_focus = 10.0;
     let time_start = 0.0;
     let time_end = 1.0;
-    let camera = Camera::build(
+    let camera = PerspectiveCamera::build(
         &look_from,
         &look_at,
         &Vec3::from(0.0, 1.0, 0.0),
@@ -308,11 +1041,10 @@ pub fn random_moving_scene(nx : usize, ny : usize, rnd: &mut Random, _time_start
         Box::new(Metal::with_albedo(Vec3::from(0.7, 0.6, 0.5))),
     ));
 
-    (list, camera)
+    (list, Box::new(camera))
 }
 
 
-
 fn build_sphere(center: Vec3, radius: f32, material: Box<dyn Material>) -> Box<dyn Hitable> {
     let sphere = Sphere {
         center,
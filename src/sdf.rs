@@ -0,0 +1,382 @@
+use crate::aabb::Aabb;
+use crate::hitable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::vec3::*;
+
+use std::f32;
+
+// Signed distance fields, negative inside. `distance` may underestimate the
+// true distance but must never overestimate it, or the march steps through
+// the surface. `bounds` must contain the whole surface; it's what the BVH
+// sees and where marching starts and stops.
+pub trait Sdf : Send + Sync {
+    fn distance(&self, p : &Vec3) -> f32;
+    fn bounds(&self) -> Aabb;
+}
+
+fn bounds_around(center : &Vec3, extent : &Vec3) -> Aabb {
+    Aabb::build(center - extent, center + extent)
+}
+
+fn grown(bounds : &Aabb, amount : f32) -> Aabb {
+    let pad = Vec3::from(amount, amount, amount);
+    Aabb::build(bounds.min - pad, bounds.max + pad)
+}
+
+pub struct SdfSphere {
+    center : Vec3,
+    radius : f32
+}
+
+impl SdfSphere {
+    pub fn build(center : Vec3, radius : f32) -> Box<SdfSphere> {
+        Box::new(SdfSphere { center, radius })
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p : &Vec3) -> f32 {
+        (p - &self.center).length() - self.radius
+    }
+
+    fn bounds(&self) -> Aabb {
+        bounds_around(&self.center, &Vec3::from(self.radius, self.radius, self.radius))
+    }
+}
+
+// Axis aligned box reaching `half_extents` from its centre, edges and
+// corners rounded off by `radius`.
+pub struct RoundBox {
+    center : Vec3,
+    half_extents : Vec3,
+    radius : f32
+}
+
+impl RoundBox {
+    pub fn build(center : Vec3, half_extents : Vec3, radius : f32) -> Box<RoundBox> {
+        Box::new(RoundBox { center, half_extents, radius })
+    }
+}
+
+impl Sdf for RoundBox {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let d = p - &self.center;
+        let q = |i : usize| d.get(i).abs() - (self.half_extents.get(i) - self.radius).max(0.0);
+        let (x, y, z) = (q(0), q(1), q(2));
+        let outside = Vec3::from(x.max(0.0), y.max(0.0), z.max(0.0)).length();
+        outside + x.max(y).max(z).min(0.0) - self.radius
+    }
+
+    fn bounds(&self) -> Aabb {
+        bounds_around(&self.center, &self.half_extents)
+    }
+}
+
+// Every point within `radius` of the segment from `a` to `b`.
+pub struct Capsule {
+    a : Vec3,
+    b : Vec3,
+    radius : f32
+}
+
+impl Capsule {
+    pub fn build(a : Vec3, b : Vec3, radius : f32) -> Box<Capsule> {
+        Box::new(Capsule { a, b, radius })
+    }
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let pa = p - &self.a;
+        let ba = self.b - self.a;
+        let along = ba.square_length();
+        let h = if along > 0.0 { (dot(&pa, &ba) / along).clamp(0.0, 1.0) } else { 0.0 };
+        (pa - &ba * h).length() - self.radius
+    }
+
+    fn bounds(&self) -> Aabb {
+        let pad = Vec3::from(self.radius, self.radius, self.radius);
+        Aabb::build(self.a.min(&self.b) - pad, self.a.max(&self.b) + pad)
+    }
+}
+
+// Torus round the y axis through `center`.
+pub struct SdfTorus {
+    center : Vec3,
+    major : f32,
+    minor : f32
+}
+
+impl SdfTorus {
+    pub fn build(center : Vec3, major : f32, minor : f32) -> Box<SdfTorus> {
+        Box::new(SdfTorus { center, major, minor })
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let d = p - &self.center;
+        let ring = (d.x() * d.x() + d.z() * d.z()).sqrt() - self.major;
+        (ring * ring + d.y() * d.y()).sqrt() - self.minor
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.major + self.minor;
+        bounds_around(&self.center, &Vec3::from(r, self.minor, r))
+    }
+}
+
+// The power 8 (by default) Mandelbulb fractal, `scale` world units to its
+// unit size, with y as its axis. The distance is the usual estimate from
+// the running derivative, good enough to march with.
+pub struct Mandelbulb {
+    center : Vec3,
+    scale : f32,
+    pub power : f32,
+    pub iterations : usize
+}
+
+impl Mandelbulb {
+    pub fn build(center : Vec3, scale : f32) -> Box<Mandelbulb> {
+        Box::new(Mandelbulb { center, scale, power : 8.0, iterations : 12 })
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let c = &(p - &self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.y() / r.max(1e-12)).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.z().atan2(*z.x()) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z = Vec3::from(zr * theta.sin() * phi.cos(), zr * theta.cos(), zr * theta.sin() * phi.sin()) + c;
+            r = z.length();
+        }
+        if r <= 1e-12 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+
+    fn bounds(&self) -> Aabb {
+        // The power 8 bulb fits inside a radius of about 1.14.
+        let r = 1.25 * self.scale;
+        bounds_around(&self.center, &Vec3::from(r, r, r))
+    }
+}
+
+// Union of two fields blended over a distance of about `k`.
+pub struct SmoothUnion {
+    a : Box<dyn Sdf>,
+    b : Box<dyn Sdf>,
+    k : f32
+}
+
+impl SmoothUnion {
+    pub fn build(a : Box<dyn Sdf>, b : Box<dyn Sdf>, k : f32) -> Box<SmoothUnion> {
+        Box::new(SmoothUnion { a, b, k : k.max(1e-6) })
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+        b * (1.0 - h) + a * h - self.k * h * (1.0 - h)
+    }
+
+    // The blend fills in by at most k / 4 beyond either shape.
+    fn bounds(&self) -> Aabb {
+        grown(&Aabb::surrounding_box(&self.a.bounds(), &self.b.bounds()), 0.25 * self.k)
+    }
+}
+
+// `a` with `b` carved out of it, the edge rounded over about `k`.
+pub struct SmoothSubtract {
+    a : Box<dyn Sdf>,
+    b : Box<dyn Sdf>,
+    k : f32
+}
+
+impl SmoothSubtract {
+    pub fn build(a : Box<dyn Sdf>, b : Box<dyn Sdf>, k : f32) -> Box<SmoothSubtract> {
+        Box::new(SmoothSubtract { a, b, k : k.max(1e-6) })
+    }
+}
+
+impl Sdf for SmoothSubtract {
+    fn distance(&self, p : &Vec3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 - 0.5 * (a + b) / self.k).clamp(0.0, 1.0);
+        a * (1.0 - h) - b * h + self.k * h * (1.0 - h)
+    }
+
+    // Carving only takes away, what's left is inside `a`.
+    fn bounds(&self) -> Aabb {
+        self.a.bounds()
+    }
+}
+
+// Ray marches a distance field by sphere tracing. There's no surface
+// parameterisation, u and v are zero, so texture it with a solid or
+// triplanar texture.
+pub struct SdfHitable {
+    sdf : Box<dyn Sdf>,
+    material : Box<dyn Material>,
+    pub max_steps : usize,
+    // Distance counted as touching the surface.
+    pub epsilon : f32
+}
+
+impl SdfHitable {
+    pub fn build(sdf : Box<dyn Sdf>, material : Box<dyn Material>) -> Box<SdfHitable> {
+        Box::new(SdfHitable { sdf, material, max_steps : 256, epsilon : 1e-4 })
+    }
+
+    pub fn with_epsilon(mut self : Box<Self>, epsilon : f32) -> Box<SdfHitable> {
+        self.epsilon = epsilon;
+        self
+    }
+
+    // Gradient by the tetrahedron trick, four samples instead of six.
+    fn normal(&self, p : &Vec3) -> Vec3 {
+        let h = self.epsilon.max(1e-5);
+        let offsets = [Vec3::from(1.0, -1.0, -1.0), Vec3::from(-1.0, -1.0, 1.0), Vec3::from(-1.0, 1.0, -1.0), Vec3::from(1.0, 1.0, 1.0)];
+        let mut gradient = Vec3::default();
+        for offset in offsets.iter() {
+            gradient += &(offset * self.sdf.distance(&(p + &(offset * h))));
+        }
+        let length = gradient.length();
+        if length > 0.0 && length.is_finite() { &gradient / length } else { Vec3::from(0.0, 1.0, 0.0) }
+    }
+}
+
+impl Hitable for SdfHitable {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (start, end) = self.bounding_box(ray.time, ray.time).hit_range(ray, t_min, t_max)?;
+        let speed = ray.direction.length();
+        if speed == 0.0 {
+            return None;
+        }
+        // Rays starting inside march out to the surface the same way, by
+        // the size of the distance. A ray leaving the surface it starts on
+        // has to get clear of it before it can hit again; one starting where
+        // it enters the bounds is well away from the surface already.
+        let mut t = start;
+        let mut clear = start > t_min;
+        for _ in 0..self.max_steps {
+            let p = ray.point_at_parameter(t);
+            let distance = self.sdf.distance(&p).abs();
+            if distance >= self.epsilon {
+                clear = true;
+            } else if clear {
+                let normal = self.normal(&p);
+                return Some(HitRecord::build(ray, t, normal, &*self.material));
+            }
+            // Step at least a little, so starting on the surface moves off it.
+            t += distance.max(self.epsilon) / speed;
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, _time0 : f32, _time1 : f32) -> Aabb {
+        grown(&self.sdf.bounds(), 4.0 * self.epsilon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    fn grey() -> Box<dyn Material> {
+        Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))
+    }
+
+    fn ray(origin : Vec3, direction : Vec3) -> Ray {
+        Ray { origin, direction, time : 0.0 }
+    }
+
+    fn assert_distance(sdf : &dyn Sdf, p : Vec3, expected : f32) {
+        let distance = sdf.distance(&p);
+        assert!((distance - expected).abs() < 1e-4, "distance at {:?} is {}, expected {}", p, distance, expected);
+    }
+
+    #[test]
+    fn primitives_measure_signed_distance() {
+        let sphere = SdfSphere::build(Vec3::from(1.0, 0.0, 0.0), 2.0);
+        assert_distance(&*sphere, Vec3::from(1.0, 0.0, 0.0), -2.0);
+        assert_distance(&*sphere, Vec3::from(1.0, 3.0, 0.0), 1.0);
+        let rounded = RoundBox::build(Vec3::default(), Vec3::from(1.0, 2.0, 1.0), 0.25);
+        assert_distance(&*rounded, Vec3::from(3.0, 0.0, 0.0), 2.0);
+        assert_distance(&*rounded, Vec3::from(0.0, 1.5, 0.0), -0.5);
+        // Off the corner the rounding shows, the corner's centre being at 0.75.
+        assert_distance(&*rounded, Vec3::from(1.75, 2.75, 0.0), 2.0f32.sqrt() - 0.25);
+        let capsule = Capsule::build(Vec3::default(), Vec3::from(0.0, 2.0, 0.0), 0.5);
+        assert_distance(&*capsule, Vec3::from(1.0, 1.0, 0.0), 0.5);
+        assert_distance(&*capsule, Vec3::from(0.0, 3.0, 0.0), 0.5);
+        let torus = SdfTorus::build(Vec3::default(), 2.0, 0.5);
+        assert_distance(&*torus, Vec3::from(2.0, 0.0, 0.0), -0.5);
+        assert_distance(&*torus, Vec3::default(), 1.5);
+        assert_distance(&*torus, Vec3::from(0.0, 1.0, 2.0), 0.5);
+    }
+
+    #[test]
+    fn blends_meet_the_plain_operations_away_from_the_seam() {
+        let union = SmoothUnion::build(SdfSphere::build(Vec3::from(-2.0, 0.0, 0.0), 1.0), SdfSphere::build(Vec3::from(2.0, 0.0, 0.0), 1.0), 0.5);
+        assert_distance(&*union, Vec3::from(-4.0, 0.0, 0.0), 1.0);
+        assert_distance(&*union, Vec3::from(2.0, 0.0, 0.0), -1.0);
+        // Halfway between, the blend pulls the surface out towards the point.
+        assert!(union.distance(&Vec3::default()) < 1.0);
+        let carved = SmoothSubtract::build(SdfSphere::build(Vec3::default(), 2.0), SdfSphere::build(Vec3::from(2.0, 0.0, 0.0), 1.0), 0.1);
+        assert_distance(&*carved, Vec3::from(-1.0, 0.0, 0.0), -1.0);
+        assert_distance(&*carved, Vec3::from(2.0, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn marching_finds_the_sphere() {
+        let ball = SdfHitable::build(SdfSphere::build(Vec3::default(), 1.0), grey());
+        let rec = ball.hit(&ray(Vec3::from(-5.0, 0.0, 0.0), Vec3::from(2.0, 0.0, 0.0)), 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-3);
+        assert!((rec.normal - Vec3::from(-1.0, 0.0, 0.0)).length() < 1e-3);
+        let glancing = ray(Vec3::from(-5.0, 0.6, 0.0), Vec3::from(1.0, 0.0, 0.0));
+        assert!((ball.hit(&glancing, 0.001, f32::MAX).unwrap().t - 4.2).abs() < 1e-3);
+        assert!(ball.hit(&ray(Vec3::from(-5.0, 1.1, 0.0), Vec3::from(1.0, 0.0, 0.0)), 0.001, f32::MAX).is_none());
+        assert!(ball.hit(&glancing, 0.001, 4.0).is_none());
+    }
+
+    #[test]
+    fn marching_leaves_from_inside_and_off_the_surface() {
+        let ball = SdfHitable::build(SdfSphere::build(Vec3::default(), 1.0), grey());
+        let out = ray(Vec3::default(), Vec3::from(0.0, 1.0, 0.0));
+        let rec = ball.hit(&out, 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-3);
+        assert!((rec.normal - Vec3::from(0.0, 1.0, 0.0)).length() < 1e-3);
+        // Scattered off the top, nothing else is in the way.
+        assert!(ball.hit(&ray(rec.p, Vec3::from(0.0, 1.0, 0.0)), 0.001, f32::MAX).is_none());
+        // Looser epsilons stop short of the surface.
+        let rough = SdfHitable::build(SdfSphere::build(Vec3::default(), 1.0), grey()).with_epsilon(0.05);
+        let rec = rough.hit(&ray(Vec3::from(-5.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0)), 0.001, f32::MAX).unwrap();
+        assert!(rec.t <= 4.0 && rec.t > 3.9);
+    }
+
+    #[test]
+    fn mandelbulbs_are_hit_inside_their_bounds() {
+        let bulb = SdfHitable::build(Mandelbulb::build(Vec3::from(0.0, 1.0, 0.0), 1.0), grey()).with_epsilon(1e-3);
+        let rec = bulb.hit(&ray(Vec3::from(0.0, 1.0, -5.0), Vec3::from(0.0, 0.0, 1.0)), 0.001, f32::MAX).unwrap();
+        assert!((rec.p - Vec3::from(0.0, 1.0, 0.0)).length() < 1.25);
+        assert!(*rec.normal.z() < 0.0);
+    }
+}
@@ -0,0 +1,574 @@
+use crate::material::Material;
+use crate::mesh::*;
+use crate::vec3::*;
+
+use std::collections::HashMap;
+use std::f32;
+use std::fs;
+use std::io;
+
+// Polygon control cage for a subdivision surface.
+//
+// Edges can be given a crease sharpness: 0 is smooth, whole numbers stay
+// sharp for that many levels before smoothing out, and anything at or above
+// the number of levels applied stays a hard edge. Fractions blend between
+// the smooth and sharp rules. Boundary edges are always sharp.
+//
+// uvs are face varying, one per face corner. They're interpolated linearly
+// within each face rather than smoothed, so seams stay where they are.
+#[derive(Clone, Debug, Default)]
+pub struct Cage {
+    pub positions : Vec<Vec3>,
+    pub faces : Vec<Vec<usize>>,
+    // Per face corner, parallel to `faces`; empty for a cage without uvs.
+    pub uvs : Vec<Vec<(f32, f32)>>,
+    // Sharpness by edge, keyed by the lower vertex index first.
+    pub creases : HashMap<(usize, usize), f32>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SubdivisionScheme {
+    // Any polygons, giving quads.
+    CatmullClark,
+    // Triangles, larger polygons are split into fans first.
+    Loop
+}
+
+fn edge_key(a : usize, b : usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+fn midpoint(a : (f32, f32), b : (f32, f32)) -> (f32, f32) {
+    (0.5 * (a.0 + b.0), 0.5 * (a.1 + b.1))
+}
+
+fn lerp(a : &Vec3, b : &Vec3, t : f32) -> Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+fn invalid(path : &str, line : usize, msg : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, line, msg))
+}
+
+// Resolves a 1 based or negative (counted from the end) OBJ index.
+fn obj_index(text : &str, count : usize) -> Option<usize> {
+    let index = text.parse::<i64>().ok()?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved >= 0 && (resolved as usize) < count { Some(resolved as usize) } else { None }
+}
+
+impl Cage {
+    pub fn build(positions : Vec<Vec3>, faces : Vec<Vec<usize>>) -> Cage {
+        Cage { positions, faces, uvs : Vec::new(), creases : HashMap::new() }
+    }
+
+    pub fn with_uvs(mut self, uvs : Vec<Vec<(f32, f32)>>) -> Cage {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn with_crease(mut self, a : usize, b : usize, sharpness : f32) -> Cage {
+        self.creases.insert(edge_key(a, b), sharpness.max(0.0));
+        self
+    }
+
+    fn has_uvs(&self) -> bool {
+        self.uvs.len() == self.faces.len()
+    }
+
+    fn sharpness(&self, a : usize, b : usize) -> f32 {
+        *self.creases.get(&edge_key(a, b)).unwrap_or(&0.0)
+    }
+
+    // Cage in the OBJ subset modellers export: `v`, `vt` and `f` lines with
+    // `v`, `v/vt`, `v//vn` or `v/vt/vn` corners; normals are ignored. Creases
+    // are given by an extra `crease <v0> <v1> <sharpness>` line with OBJ
+    // style vertex indices. Other lines are skipped.
+    pub fn load(path : &str) -> io::Result<Cage> {
+        let text = fs::read_to_string(path)?;
+        let mut positions = Vec::new();
+        let mut texture_coordinates : Vec<(f32, f32)> = Vec::new();
+        let mut faces = Vec::new();
+        let mut uvs = Vec::new();
+        let mut all_uvs = true;
+        let mut creases = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue,
+            };
+            let rest : Vec<&str> = words.collect();
+            let floats = |count : usize| -> io::Result<Vec<f32>> {
+                let values : Result<Vec<f32>, _> = rest.iter().take(count).map(|v| v.parse::<f32>()).collect();
+                match values {
+                    Ok(v) if v.len() == count => Ok(v),
+                    _ => Err(invalid(path, number + 1, &format!("expected {} numbers after {}", count, keyword))),
+                }
+            };
+            match keyword {
+                "v" => {
+                    let v = floats(3)?;
+                    positions.push(Vec3::from(v[0], v[1], v[2]));
+                },
+                "vt" => {
+                    let v = floats(2)?;
+                    texture_coordinates.push((v[0], v[1]));
+                },
+                "f" => {
+                    if rest.len() < 3 {
+                        return Err(invalid(path, number + 1, "faces need at least three corners"));
+                    }
+                    let mut face = Vec::with_capacity(rest.len());
+                    let mut face_uvs = Vec::with_capacity(rest.len());
+                    for corner in &rest {
+                        let mut parts = corner.split('/');
+                        let vertex = parts.next().and_then(|v| obj_index(v, positions.len()))
+                            .ok_or_else(|| invalid(path, number + 1, &format!("bad vertex index in {}", corner)))?;
+                        face.push(vertex);
+                        match parts.next().filter(|t| !t.is_empty()) {
+                            Some(t) => {
+                                let index = obj_index(t, texture_coordinates.len())
+                                    .ok_or_else(|| invalid(path, number + 1, &format!("bad uv index in {}", corner)))?;
+                                face_uvs.push(texture_coordinates[index]);
+                            },
+                            None => all_uvs = false,
+                        }
+                    }
+                    faces.push(face);
+                    uvs.push(face_uvs);
+                },
+                "crease" => {
+                    let bad = || invalid(path, number + 1, "expected crease <v0> <v1> <sharpness>");
+                    if rest.len() != 3 {
+                        return Err(bad());
+                    }
+                    let a = obj_index(rest[0], positions.len()).ok_or_else(bad)?;
+                    let b = obj_index(rest[1], positions.len()).ok_or_else(bad)?;
+                    let sharpness = rest[2].parse::<f32>().map_err(|_| bad())?;
+                    creases.push((a, b, sharpness));
+                },
+                _ => {}
+            }
+        }
+
+        if faces.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no faces", path)));
+        }
+        let mut cage = Cage::build(positions, faces);
+        if all_uvs {
+            cage = cage.with_uvs(uvs);
+        }
+        for (a, b, sharpness) in creases {
+            cage = cage.with_crease(a, b, sharpness);
+        }
+        Ok(cage)
+    }
+
+    // Subdivides `levels` times with `scheme`.
+    pub fn subdivide(&self, scheme : SubdivisionScheme, levels : usize) -> Cage {
+        let mut cage = match scheme {
+            SubdivisionScheme::CatmullClark => self.clone(),
+            SubdivisionScheme::Loop => self.triangulated(),
+        };
+        for _ in 0..levels {
+            cage = match scheme {
+                SubdivisionScheme::CatmullClark => cage.catmull_clark(),
+                SubdivisionScheme::Loop => cage.loop_step(),
+            };
+        }
+        cage
+    }
+
+    // The cage subdivided and turned into a renderable mesh.
+    pub fn subdivided_mesh(&self, scheme : SubdivisionScheme, levels : usize, material : Box<dyn Material>) -> Mesh {
+        self.subdivide(scheme, levels).to_mesh(material)
+    }
+
+    // Polygons split into fans.
+    fn triangulated(&self) -> Cage {
+        let mut faces = Vec::with_capacity(self.faces.len());
+        let mut uvs = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 1..face.len().saturating_sub(1) {
+                faces.push(vec![face[0], face[i], face[i + 1]]);
+                if self.has_uvs() {
+                    let corners = &self.uvs[f];
+                    uvs.push(vec![corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+        }
+        Cage { positions : self.positions.clone(), faces, uvs, creases : self.creases.clone() }
+    }
+
+    fn topology(&self) -> Topology {
+        let mut edge_faces : HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 0..face.len() {
+                edge_faces.entry(edge_key(face[i], face[(i + 1) % face.len()])).or_default().push(f);
+            }
+        }
+        let mut edges : Vec<(usize, usize)> = edge_faces.keys().cloned().collect();
+        edges.sort_unstable();
+        let edge_index = edges.iter().enumerate().map(|(i, e)| (*e, i)).collect();
+        let mut vertex_edges = vec![Vec::new(); self.positions.len()];
+        let mut vertex_faces = vec![Vec::new(); self.positions.len()];
+        for &(a, b) in &edges {
+            vertex_edges[a].push((a, b));
+            vertex_edges[b].push((a, b));
+        }
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                vertex_faces[v].push(f);
+            }
+        }
+        Topology { edges, edge_index, edge_faces, vertex_edges, vertex_faces }
+    }
+
+    // Sharpness used by the subdivision rules, boundaries being infinitely
+    // sharp.
+    fn rule_sharpness(&self, topology : &Topology, edge : (usize, usize)) -> f32 {
+        if topology.edge_faces[&edge].len() != 2 { f32::INFINITY } else { self.sharpness(edge.0, edge.1) }
+    }
+
+    // Blends a vertex's smooth position with its crease or corner position
+    // from the sharp edges meeting there.
+    fn vertex_rule(&self, topology : &Topology, v : usize, smooth : impl Fn() -> Vec3, crease : impl Fn(&Vec3, &Vec3) -> Vec3) -> Vec3 {
+        let p = self.positions[v];
+        if topology.vertex_faces[v].is_empty() {
+            return p;
+        }
+        let sharp : Vec<((usize, usize), f32)> = topology.vertex_edges[v].iter()
+            .map(|e| (*e, self.rule_sharpness(topology, *e)))
+            .filter(|(_, s)| *s > 0.0)
+            .collect();
+        if sharp.len() < 2 {
+            return smooth();
+        }
+        let sharp_position = if sharp.len() == 2 {
+            let other = |e : (usize, usize)| self.positions[if e.0 == v { e.1 } else { e.0 }];
+            crease(&other(sharp[0].0), &other(sharp[1].0))
+        } else {
+            p
+        };
+        let strength = sharp.iter().map(|(_, s)| s.min(1.0)).sum::<f32>() / sharp.len() as f32;
+        if strength >= 1.0 { sharp_position } else { lerp(&smooth(), &sharp_position, strength) }
+    }
+
+    // Creases on the two halves of every split edge, a level softer.
+    fn child_creases(&self, topology : &Topology, edge_vertex : impl Fn(usize) -> usize) -> HashMap<(usize, usize), f32> {
+        let mut creases = HashMap::new();
+        for (&(a, b), &sharpness) in &self.creases {
+            let child = sharpness - 1.0;
+            if child > 0.0 {
+                if let Some(&e) = topology.edge_index.get(&(a, b)) {
+                    creases.insert(edge_key(a, edge_vertex(e)), child);
+                    creases.insert(edge_key(edge_vertex(e), b), child);
+                }
+            }
+        }
+        creases
+    }
+
+    fn catmull_clark(&self) -> Cage {
+        let topology = self.topology();
+        let (vertex_count, face_count) = (self.positions.len(), self.faces.len());
+        let face_vertex = |f : usize| vertex_count + f;
+        let edge_vertex = |e : usize| vertex_count + face_count + e;
+
+        let face_points : Vec<Vec3> = self.faces.iter()
+            .map(|face| &face.iter().fold(Vec3::default(), |sum, &v| sum + self.positions[v]) / face.len() as f32)
+            .collect();
+
+        let edge_points : Vec<Vec3> = topology.edges.iter().map(|&(a, b)| {
+            let mid = &(self.positions[a] + self.positions[b]) * 0.5;
+            let sharpness = self.rule_sharpness(&topology, (a, b));
+            if sharpness >= 1.0 {
+                return mid;
+            }
+            let faces = &topology.edge_faces[&(a, b)];
+            let smooth = &(self.positions[a] + self.positions[b] + face_points[faces[0]] + face_points[faces[1]]) * 0.25;
+            lerp(&smooth, &mid, sharpness)
+        }).collect();
+
+        let vertex_points : Vec<Vec3> = (0..vertex_count).map(|v| {
+            let smooth = || {
+                let faces = &topology.vertex_faces[v];
+                let edges = &topology.vertex_edges[v];
+                let n = edges.len() as f32;
+                let f = &faces.iter().fold(Vec3::default(), |sum, &f| sum + face_points[f]) / faces.len() as f32;
+                let r = &edges.iter().fold(Vec3::default(), |sum, &(a, b)| sum + &(self.positions[a] + self.positions[b]) * 0.5) / n;
+                &(f + &r * 2.0 + &self.positions[v] * (n - 3.0)) / n
+            };
+            let crease = |e1 : &Vec3, e2 : &Vec3| &(*e1 + *e2 + &self.positions[v] * 6.0) / 8.0;
+            self.vertex_rule(&topology, v, smooth, crease)
+        }).collect();
+
+        let mut faces = Vec::with_capacity(self.faces.iter().map(|f| f.len()).sum());
+        let mut uvs = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let n = face.len();
+            let centre_uv = if self.has_uvs() {
+                let corners = &self.uvs[f];
+                let sum = corners.iter().fold((0.0, 0.0), |s, uv| (s.0 + uv.0, s.1 + uv.1));
+                (sum.0 / n as f32, sum.1 / n as f32)
+            } else {
+                (0.0, 0.0)
+            };
+            for i in 0..n {
+                let (previous, current, next) = (face[(i + n - 1) % n], face[i], face[(i + 1) % n]);
+                faces.push(vec![
+                    current,
+                    edge_vertex(topology.edge_index[&edge_key(current, next)]),
+                    face_vertex(f),
+                    edge_vertex(topology.edge_index[&edge_key(previous, current)])
+                ]);
+                if self.has_uvs() {
+                    let corners = &self.uvs[f];
+                    let (uv_previous, uv_current, uv_next) = (corners[(i + n - 1) % n], corners[i], corners[(i + 1) % n]);
+                    uvs.push(vec![uv_current, midpoint(uv_current, uv_next), centre_uv, midpoint(uv_previous, uv_current)]);
+                }
+            }
+        }
+
+        let mut positions = vertex_points;
+        positions.extend(face_points);
+        positions.extend(edge_points);
+        let creases = self.child_creases(&topology, edge_vertex);
+        Cage { positions, faces, uvs, creases }
+    }
+
+    fn loop_step(&self) -> Cage {
+        let topology = self.topology();
+        let vertex_count = self.positions.len();
+        let edge_vertex = |e : usize| vertex_count + e;
+
+        let edge_points : Vec<Vec3> = topology.edges.iter().map(|&(a, b)| {
+            let mid = &(self.positions[a] + self.positions[b]) * 0.5;
+            let sharpness = self.rule_sharpness(&topology, (a, b));
+            if sharpness >= 1.0 {
+                return mid;
+            }
+            // The corners facing the edge in its two triangles.
+            let opposite = |f : usize| *self.faces[f].iter().find(|&&v| v != a && v != b).unwrap_or(&a);
+            let faces = &topology.edge_faces[&(a, b)];
+            let smooth = &(self.positions[a] + self.positions[b]) * 0.375
+                + &(self.positions[opposite(faces[0])] + self.positions[opposite(faces[1])]) * 0.125;
+            lerp(&smooth, &mid, sharpness)
+        }).collect();
+
+        let vertex_points : Vec<Vec3> = (0..vertex_count).map(|v| {
+            let smooth = || {
+                let edges = &topology.vertex_edges[v];
+                let n = edges.len() as f32;
+                let inner = 0.375 + 0.25 * (2.0 * f32::consts::PI / n).cos();
+                let beta = (0.625 - inner * inner) / n;
+                let ring = edges.iter().fold(Vec3::default(), |sum, &(a, b)| sum + self.positions[if a == v { b } else { a }]);
+                &self.positions[v] * (1.0 - n * beta) + &ring * beta
+            };
+            let crease = |e1 : &Vec3, e2 : &Vec3| &self.positions[v] * 0.75 + &(*e1 + *e2) * 0.125;
+            self.vertex_rule(&topology, v, smooth, crease)
+        }).collect();
+
+        let mut faces = Vec::with_capacity(4 * self.faces.len());
+        let mut uvs = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let (a, b, c) = (face[0], face[1], face[2]);
+            let ab = edge_vertex(topology.edge_index[&edge_key(a, b)]);
+            let bc = edge_vertex(topology.edge_index[&edge_key(b, c)]);
+            let ca = edge_vertex(topology.edge_index[&edge_key(c, a)]);
+            faces.push(vec![a, ab, ca]);
+            faces.push(vec![ab, b, bc]);
+            faces.push(vec![ca, bc, c]);
+            faces.push(vec![ab, bc, ca]);
+            if self.has_uvs() {
+                let (ua, ub, uc) = (self.uvs[f][0], self.uvs[f][1], self.uvs[f][2]);
+                let (uab, ubc, uca) = (midpoint(ua, ub), midpoint(ub, uc), midpoint(uc, ua));
+                uvs.push(vec![ua, uab, uca]);
+                uvs.push(vec![uab, ub, ubc]);
+                uvs.push(vec![uca, ubc, uc]);
+                uvs.push(vec![uab, ubc, uca]);
+            }
+        }
+
+        let mut positions = vertex_points;
+        positions.extend(edge_points);
+        let creases = self.child_creases(&topology, edge_vertex);
+        Cage { positions, faces, uvs, creases }
+    }
+
+    // Fan triangulates the faces. Shading normals are averaged over the
+    // faces round each vertex, but not across creased or boundary edges so
+    // hard edges stay hard.
+    pub fn to_mesh(&self, material : Box<dyn Material>) -> Mesh {
+        let topology = self.topology();
+        // Newell's method, area weighted and fine for non planar polygons.
+        let face_normals : Vec<Vec3> = self.faces.iter().map(|face| {
+            let mut normal = Vec3::default();
+            for i in 0..face.len() {
+                let (p, q) = (self.positions[face[i]], self.positions[face[(i + 1) % face.len()]]);
+                normal += &Vec3::from((p.y() - q.y()) * (p.z() + q.z()), (p.z() - q.z()) * (p.x() + q.x()), (p.x() - q.x()) * (p.y() + q.y()));
+            }
+            normal
+        }).collect();
+
+        // Face corners sharing a smooth edge share a normal.
+        let corner_base : Vec<usize> = self.faces.iter().scan(0, |offset, face| { let base = *offset; *offset += face.len(); Some(base) }).collect();
+        let corner = |f : usize, v : usize| corner_base[f] + self.faces[f].iter().position(|&w| w == v).unwrap_or(0);
+        let mut groups = UnionFind::build(corner_base.last().map_or(0, |&b| b + self.faces.last().map_or(0, |f| f.len())));
+        for (&(a, b), faces) in &topology.edge_faces {
+            if faces.len() == 2 && self.sharpness(a, b) <= 0.0 {
+                groups.union(corner(faces[0], a), corner(faces[1], a));
+                groups.union(corner(faces[0], b), corner(faces[1], b));
+            }
+        }
+        let mut group_normals : HashMap<usize, Vec3> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                *group_normals.entry(groups.find(corner(f, v))).or_insert_with(Vec3::default) += &face_normals[f];
+            }
+        }
+        let corner_normal = |groups : &mut UnionFind, f : usize, v : usize| {
+            let normal = group_normals[&groups.find(corner(f, v))];
+            let length = normal.length();
+            if length > 0.0 { &normal / length } else { Vec3::from(0.0, 1.0, 0.0) }
+        };
+
+        let mut triangles = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 1..face.len().saturating_sub(1) {
+                let indices = [0, i, i + 1];
+                let points = indices.map(|k| face[k]);
+                let normals = [corner_normal(&mut groups, f, points[0]), corner_normal(&mut groups, f, points[1]), corner_normal(&mut groups, f, points[2])];
+                let uvs = if self.has_uvs() { indices.map(|k| self.uvs[f][k]) } else { [(0.0, 0.0); 3] };
+                triangles.push(MeshFace { points, normals, uvs });
+            }
+        }
+        Mesh { positions : self.positions.clone(), triangles, material }
+    }
+}
+
+struct Topology {
+    // Sorted, so vertex numbering doesn't depend on hashing.
+    edges : Vec<(usize, usize)>,
+    edge_index : HashMap<(usize, usize), usize>,
+    edge_faces : HashMap<(usize, usize), Vec<usize>>,
+    vertex_edges : Vec<Vec<(usize, usize)>>,
+    vertex_faces : Vec<Vec<usize>>
+}
+
+struct UnionFind {
+    parents : Vec<usize>
+}
+
+impl UnionFind {
+    fn build(count : usize) -> UnionFind {
+        UnionFind { parents : (0..count).collect() }
+    }
+
+    fn find(&mut self, i : usize) -> usize {
+        let mut root = i;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut i = i;
+        while self.parents[i] != root {
+            let next = self.parents[i];
+            self.parents[i] = root;
+            i = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a : usize, b : usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[a] = b;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::rng::Random;
+    use crate::texture::ConstantTexture;
+
+    fn octahedron() -> Cage {
+        Cage::load("models/creased_octahedron.obj").expect("the octahedron fixture should load")
+    }
+
+    fn load_text(name : &str, text : &str) -> io::Result<Cage> {
+        let path = std::env::temp_dir().join(format!("raytracer-{}-{}.obj", std::process::id(), name));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, text).unwrap();
+        let cage = Cage::load(&path);
+        fs::remove_file(&path).unwrap();
+        cage
+    }
+
+    #[test]
+    fn cages_load_from_obj() {
+        let cage = octahedron();
+        assert_eq!(cage.positions.len(), 6);
+        assert_eq!(cage.faces.len(), 8);
+        assert!(cage.faces.iter().all(|face| face.len() == 3));
+        assert_eq!(cage.faces[0], vec![1, 2, 0]);
+        assert_eq!(cage.uvs[0], vec![(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)]);
+        let mut creases : Vec<_> = cage.creases.iter().map(|(edge, sharpness)| (*edge, *sharpness)).collect();
+        creases.sort_by_key(|(edge, _)| *edge);
+        assert_eq!(creases, vec![((1, 2), 3.0), ((1, 4), 3.0), ((2, 3), 3.0), ((3, 4), 3.0)]);
+
+        // Negative indices count back from the last vertex, corners without
+        // a uv leave the whole cage without.
+        let cage = load_text("relative", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2//1 -1\n").unwrap();
+        assert_eq!(cage.faces, vec![vec![0, 1, 2]]);
+        assert!(cage.uvs.is_empty());
+    }
+
+    #[test]
+    fn bad_cages_are_invalid_data() {
+        for (name, text) in [("range", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"), ("short", "v 0 0\n"), ("empty", "v 0 0 0\n"), ("crease", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\ncrease 1 2\n")].iter() {
+            let err = load_text(name, text).expect_err(name);
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", name);
+        }
+        assert_eq!(Cage::load("models/no_such_cage.obj").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn each_level_splits_every_face() {
+        let cage = octahedron();
+        // A vertex per face and per edge joins the originals.
+        let once = cage.subdivide(SubdivisionScheme::CatmullClark, 1);
+        assert_eq!((once.positions.len(), once.faces.len()), (6 + 12 + 8, 24));
+        assert!(once.faces.iter().all(|face| face.len() == 4));
+        assert_eq!(once.uvs.len(), once.faces.len());
+        let looped = cage.subdivide(SubdivisionScheme::Loop, 2);
+        assert_eq!((looped.positions.len(), looped.faces.len()), (66, 128));
+        assert_eq!(cage.subdivided_mesh(SubdivisionScheme::CatmullClark, 1, Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))))).triangles.len(), 48);
+    }
+
+    #[test]
+    fn creases_keep_their_edge_sharp() {
+        // On the creased equator a vertex only follows its crease neighbours,
+        // smooth it's pulled in by the whole ring round it.
+        let creased = octahedron().subdivide(SubdivisionScheme::CatmullClark, 1);
+        assert!((creased.positions[1] - Vec3::from(0.75, 0.0, 0.0)).length() < 1e-5);
+        let smooth = Cage { creases : HashMap::new(), ..octahedron() }.subdivide(SubdivisionScheme::CatmullClark, 1);
+        assert!((smooth.positions[1] - Vec3::from(7.0 / 12.0, 0.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn subdivided_meshes_stay_inside_the_cage() {
+        let mut rnd = Random::create_with_seed(3);
+        let material = Box::new(Lambertian::with_texture(ConstantTexture::new_with_colour(Vec3::from(0.5, 0.5, 0.5))));
+        let surface = octahedron().subdivided_mesh(SubdivisionScheme::CatmullClark, 3, material).into_bvh(&mut rnd);
+        let down = Ray { origin : Vec3::from(0.0, 5.0, 0.0), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        let rec = surface.hit(&down, 0.001, f32::MAX).expect("ray should hit the surface");
+        assert!(*rec.p.y() > 0.3 && *rec.p.y() < 1.0);
+        assert!(*rec.normal.y() > 0.99);
+    }
+}
@@ -1,13 +1,22 @@
 use crate::vec3::*;
 use crate::perlin::*;
+use crate::imageio::{self, SampleType};
+use crate::ray::HitRecord;
 use crate::rng::Random;
+use crate::tonemap::srgb_eotf;
 
-extern crate stb_image;
-
-use stb_image::image::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
 
 pub trait Texture : Send + Sync {
     fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3;
+
+    // Lookup at a surface hit, textures that can filter use the hit's footprint.
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.colour(rec.u, rec.v, &rec.p)
+    }
 }
 
 pub struct ConstantTexture {
@@ -42,6 +51,12 @@ impl Texture for CheckerTexture {
         let sines = (10.0 * p.x()).sin() * (10.0 * p.y()).sin() * (10.0 * p.z()).sin();
         if sines < 0.0 { self.odd.colour(u,v,p) } else { self.even.colour(u,v,p) }
     }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        let p = &rec.p;
+        let sines = (10.0 * p.x()).sin() * (10.0 * p.y()).sin() * (10.0 * p.z()).sin();
+        if sines < 0.0 { self.odd.value(rec) } else { self.even.value(rec) }
+    }
 }
 
 pub struct NoiseTexture {
@@ -61,38 +76,1167 @@ impl Texture for NoiseTexture {
     }
 }
 
-pub struct ImageTexture {
-    data : Vec<u8>,
+// The procedural textures below evaluate their pattern at `p * scale`, and
+// animate with ray time through the fourth noise dimension, `speed` being
+// how fast the pattern evolves. Plain `colour` lookups are at time zero.
+
+fn grey(value : f32) -> Vec3 {
+    Vec3::from(value, value, value)
+}
+
+fn mix(a : &Vec3, b : &Vec3, t : f32) -> Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+// Sum of the octave amplitudes, to bring fractal sums back into range.
+fn fractal_amplitude(fractal : &Fractal) -> f32 {
+    (0..fractal.octaves).map(|octave| fractal.gain.powi(octave as i32)).sum::<f32>().max(1e-6)
+}
+
+// Grey fBm noise in [0,1].
+pub struct FbmTexture {
+    noise : Perlin,
+    pub scale : f32,
+    pub fractal : Fractal,
+    pub speed : f32
+}
+
+impl FbmTexture {
+    pub fn build(rnd : &mut Random, scale : f32) -> Box<FbmTexture> {
+        Box::new(FbmTexture { noise : Perlin::build(rnd), scale, fractal : Fractal::default(), speed : 0.0 })
+    }
+
+    fn evaluate(&self, p : &Vec3, time : f32) -> Vec3 {
+        let value = self.noise.fbm(&(p * self.scale), time * self.speed, &self.fractal) / fractal_amplitude(&self.fractal);
+        grey((0.5 * (1.0 + value)).clamp(0.0, 1.0))
+    }
+}
+
+impl Texture for FbmTexture {
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        self.evaluate(p, 0.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.evaluate(&rec.p, rec.time)
+    }
+}
+
+// Grey ridged multifractal in [0,1], sharp crests like mountain ranges.
+pub struct RidgedTexture {
+    noise : Perlin,
+    pub scale : f32,
+    pub fractal : Fractal,
+    /// Raises the ridges, 1 is the usual choice.
+    pub offset : f32,
+    pub speed : f32
+}
+
+impl RidgedTexture {
+    pub fn build(rnd : &mut Random, scale : f32) -> Box<RidgedTexture> {
+        Box::new(RidgedTexture { noise : Perlin::build(rnd), scale, fractal : Fractal::default(), offset : 1.0, speed : 0.0 })
+    }
+
+    fn evaluate(&self, p : &Vec3, time : f32) -> Vec3 {
+        let value = self.noise.ridged(&(p * self.scale), time * self.speed, &self.fractal, self.offset);
+        grey((value / (self.offset * self.offset * fractal_amplitude(&self.fractal))).clamp(0.0, 1.0))
+    }
+}
+
+impl Texture for RidgedTexture {
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        self.evaluate(p, 0.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.evaluate(&rec.p, rec.time)
+    }
+}
+
+// Grey cellular noise, the chosen distance clamped to [0,1]. Doesn't animate.
+pub struct WorleyTexture {
+    worley : Worley,
+    pub scale : f32,
+    pub feature : WorleyFeature
+}
+
+impl WorleyTexture {
+    pub fn build(rnd : &mut Random, scale : f32, feature : WorleyFeature) -> Box<WorleyTexture> {
+        Box::new(WorleyTexture { worley : Worley::build(rnd), scale, feature })
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        grey(self.worley.feature(&(p * self.scale), self.feature).clamp(0.0, 1.0))
+    }
+}
+
+// Looks `texture` up at a point pushed around by fBm noise, `strength` being
+// how far in world units. Warping noise with noise gives swirly, flowing patterns.
+pub struct DomainWarp {
+    pub texture : Box<dyn Texture>,
+    noise : Perlin,
+    pub scale : f32,
+    pub strength : f32,
+    pub fractal : Fractal,
+    pub speed : f32
+}
+
+impl DomainWarp {
+    pub fn build(rnd : &mut Random, texture : Box<dyn Texture>, scale : f32, strength : f32) -> Box<DomainWarp> {
+        Box::new(DomainWarp { texture, noise : Perlin::build(rnd), scale, strength, fractal : Fractal { octaves : 4, ..Fractal::default() }, speed : 0.0 })
+    }
+
+    fn warp(&self, p : &Vec3, time : f32) -> Vec3 {
+        // Three decorrelated samples of the same noise field, one per axis.
+        let q = p * self.scale;
+        let w = time * self.speed;
+        let offset = Vec3::from(
+            self.noise.fbm(&q, w, &self.fractal),
+            self.noise.fbm(&(q + Vec3::from(5.2, 1.3, 2.8)), w, &self.fractal),
+            self.noise.fbm(&(q + Vec3::from(1.7, 9.2, 4.1)), w, &self.fractal)
+        );
+        p + &(&offset * (self.strength / fractal_amplitude(&self.fractal)))
+    }
+}
+
+impl Texture for DomainWarp {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.texture.colour(u, v, &self.warp(p, 0.0))
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.texture.value(&HitRecord { p : self.warp(&rec.p, rec.time), ..*rec })
+    }
+}
+
+// Growth rings around the y axis, distorted by fBm. `rings` is the number of
+// rings per world unit of radius.
+pub struct WoodTexture {
+    noise : Perlin,
+    pub light : Vec3,
+    pub dark : Vec3,
+    pub rings : f32,
+    pub scale : f32,
+    pub turbulence : f32,
+    pub fractal : Fractal,
+    pub speed : f32
+}
+
+impl WoodTexture {
+    pub fn build(rnd : &mut Random, light : Vec3, dark : Vec3, rings : f32) -> Box<WoodTexture> {
+        Box::new(WoodTexture {
+            noise : Perlin::build(rnd),
+            light,
+            dark,
+            rings,
+            scale : 1.0,
+            turbulence : 0.5,
+            fractal : Fractal { octaves : 3, ..Fractal::default() },
+            speed : 0.0
+        })
+    }
+
+    fn evaluate(&self, p : &Vec3, time : f32) -> Vec3 {
+        let distortion = self.noise.fbm(&(p * self.scale), time * self.speed, &self.fractal);
+        let radius = (p.x() * p.x() + p.z() * p.z()).sqrt() * self.rings + self.turbulence * distortion;
+        // Soft early wood fading into a sharper band of dark late wood.
+        let t = radius - radius.floor();
+        mix(&self.light, &self.dark, t * t)
+    }
+}
+
+impl Texture for WoodTexture {
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        self.evaluate(p, 0.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.evaluate(&rec.p, rec.time)
+    }
+}
+
+// Veins along z from a sine wave disturbed by turbulence, the configurable
+// version of `NoiseTexture`'s pattern.
+pub struct MarbleTexture {
+    noise : Perlin,
+    pub light : Vec3,
+    pub dark : Vec3,
+    pub scale : f32,
+    pub turbulence : f32,
+    pub fractal : Fractal,
+    pub speed : f32
+}
+
+impl MarbleTexture {
+    pub fn build(rnd : &mut Random, light : Vec3, dark : Vec3, scale : f32) -> Box<MarbleTexture> {
+        Box::new(MarbleTexture { noise : Perlin::build(rnd), light, dark, scale, turbulence : 10.0, fractal : Fractal::default(), speed : 0.0 })
+    }
+
+    fn evaluate(&self, p : &Vec3, time : f32) -> Vec3 {
+        let turbulence = self.noise.fbm(&(p * self.scale), time * self.speed, &self.fractal).abs();
+        let t = 0.5 * (1.0 + (self.scale * p.z() + self.turbulence * turbulence).sin());
+        mix(&self.dark, &self.light, t)
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        self.evaluate(p, 0.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.evaluate(&rec.p, rec.time)
+    }
+}
+
+// Texture graph nodes. Each wraps other textures and forwards both lookups,
+// so `value` keeps the hit's footprint and time on the way down and image
+// textures stay filtered inside a graph.
+
+// Scalar input of a node, the mean of the channels.
+fn scalar(colour : &Vec3) -> f32 {
+    (colour.r() + colour.g() + colour.b()) / 3.0
+}
+
+// Blends from `a` where the mask is 0 to `b` where it's 1.
+pub struct MixTexture {
+    a : Box<dyn Texture>,
+    b : Box<dyn Texture>,
+    mask : Box<dyn Texture>
+}
+
+impl MixTexture {
+    pub fn build(a : Box<dyn Texture>, b : Box<dyn Texture>, mask : Box<dyn Texture>) -> Box<MixTexture> {
+        Box::new(MixTexture { a, b, mask })
+    }
+}
+
+impl Texture for MixTexture {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        let t = scalar(&self.mask.colour(u, v, p)).clamp(0.0, 1.0);
+        mix(&self.a.colour(u, v, p), &self.b.colour(u, v, p), t)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        // Skip the side that doesn't contribute, it may be an expensive lookup.
+        let t = scalar(&self.mask.value(rec)).clamp(0.0, 1.0);
+        if t <= 0.0 {
+            self.a.value(rec)
+        } else if t >= 1.0 {
+            self.b.value(rec)
+        } else {
+            mix(&self.a.value(rec), &self.b.value(rec), t)
+        }
+    }
+}
+
+// Colour the geometry carries at the hit, such as a point cloud's per
+// point colours. White away from a surface, with no hit to read it from.
+pub struct GeometryColour;
+
+impl GeometryColour {
+    pub fn build() -> Box<GeometryColour> {
+        Box::new(GeometryColour)
+    }
+}
+
+impl Texture for GeometryColour {
+    fn colour(&self, _u : f32, _v : f32, _p : &Vec3) -> Vec3 {
+        Vec3::from(1.0, 1.0, 1.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        rec.colour
+    }
+}
+
+// Channel by channel product of two textures.
+pub struct MultiplyTexture {
+    a : Box<dyn Texture>,
+    b : Box<dyn Texture>
+}
+
+impl MultiplyTexture {
+    pub fn build(a : Box<dyn Texture>, b : Box<dyn Texture>) -> Box<MultiplyTexture> {
+        Box::new(MultiplyTexture { a, b })
+    }
+}
+
+impl Texture for MultiplyTexture {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.a.colour(u, v, p).direct_product(&self.b.colour(u, v, p))
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.a.value(rec).direct_product(&self.b.value(rec))
+    }
+}
+
+pub struct AddTexture {
+    a : Box<dyn Texture>,
+    b : Box<dyn Texture>
+}
+
+impl AddTexture {
+    pub fn build(a : Box<dyn Texture>, b : Box<dyn Texture>) -> Box<AddTexture> {
+        Box::new(AddTexture { a, b })
+    }
+}
+
+impl Texture for AddTexture {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.a.colour(u, v, p) + self.b.colour(u, v, p)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.a.value(rec) + self.b.value(rec)
+    }
+}
+
+// `texture * scale + offset`, per channel. Tints, brightens or remaps a
+// texture's range without a second texture.
+pub struct ScaleTexture {
+    texture : Box<dyn Texture>,
+    pub scale : Vec3,
+    pub offset : Vec3
+}
+
+impl ScaleTexture {
+    pub fn build(texture : Box<dyn Texture>, scale : Vec3) -> Box<ScaleTexture> {
+        Box::new(ScaleTexture { texture, scale, offset : Vec3::default() })
+    }
+
+    pub fn with_offset(mut self : Box<Self>, offset : Vec3) -> Box<ScaleTexture> {
+        self.offset = offset;
+        self
+    }
+}
+
+impl Texture for ScaleTexture {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.texture.colour(u, v, p).direct_product(&self.scale) + self.offset
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.texture.value(rec).direct_product(&self.scale) + self.offset
+    }
+}
+
+// Maps a scalar input to a colour, linear between the stops. Inputs outside
+// the first and last stop take their colour.
+pub struct ColourRamp {
+    input : Box<dyn Texture>,
+    stops : Vec<(f32, Vec3)>
+}
+
+impl ColourRamp {
+    pub fn build(input : Box<dyn Texture>, stops : Vec<(f32, Vec3)>) -> Box<ColourRamp> {
+        let mut stops = stops;
+        stops.retain(|(position, _)| position.is_finite());
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if stops.is_empty() {
+            stops = vec![(0.0, grey(0.0)), (1.0, grey(1.0))];
+        }
+        Box::new(ColourRamp { input, stops })
+    }
+
+    fn ramp(&self, t : f32) -> Vec3 {
+        let first = &self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let (t0, c0) = &pair[0];
+            let (t1, c1) = &pair[1];
+            if t <= *t1 {
+                let span = t1 - t0;
+                return if span > 0.0 { mix(c0, c1, (t - t0) / span) } else { *c1 };
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+impl Texture for ColourRamp {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.ramp(scalar(&self.input.colour(u, v, p)))
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.ramp(scalar(&self.input.value(rec)))
+    }
+}
+
+// Moves the uv a texture is looked up at: rotated by `rotation` degrees
+// about the middle of the texture, then scaled, then offset. A scale of 4
+// tiles the texture four times across the surface (given `WrapMode::Repeat`).
+pub struct UvTransform {
+    texture : Box<dyn Texture>,
+    pub scale : (f32, f32),
+    pub offset : (f32, f32),
+    pub rotation : f32
+}
+
+impl UvTransform {
+    pub fn build(texture : Box<dyn Texture>, scale : (f32, f32)) -> Box<UvTransform> {
+        Box::new(UvTransform { texture, scale, offset : (0.0, 0.0), rotation : 0.0 })
+    }
+
+    pub fn with_offset(mut self : Box<Self>, offset : (f32, f32)) -> Box<UvTransform> {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_rotation(mut self : Box<Self>, rotation : f32) -> Box<UvTransform> {
+        self.rotation = rotation;
+        self
+    }
+
+    fn transform(&self, u : f32, v : f32) -> (f32, f32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (du, dv) = (u - 0.5, v - 0.5);
+        let (ru, rv) = (cos * du - sin * dv + 0.5, sin * du + cos * dv + 0.5);
+        (ru * self.scale.0 + self.offset.0, rv * self.scale.1 + self.offset.1)
+    }
+}
+
+impl Texture for UvTransform {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        let (u, v) = self.transform(u, v);
+        self.texture.colour(u, v, p)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        let (u, v) = self.transform(rec.u, rec.v);
+        // Rotation keeps lengths, scaling stretches the footprint with the uv.
+        let footprint = rec.footprint * self.scale.0.abs().max(self.scale.1.abs());
+        self.texture.value(&HitRecord { u, v, footprint, ..*rec })
+    }
+}
+
+// Projects `texture` along the three axes, uv being the hit position times
+// `scale` in the other two, and blends the projections by how squarely the
+// normal faces each axis. Textures surfaces with no usable uv without a
+// seam; `sharpness` narrows the blend zones.
+pub struct TriplanarTexture {
+    texture : Box<dyn Texture>,
+    pub scale : f32,
+    pub sharpness : f32
+}
+
+impl TriplanarTexture {
+    pub fn build(texture : Box<dyn Texture>, scale : f32) -> Box<TriplanarTexture> {
+        Box::new(TriplanarTexture { texture, scale, sharpness : 4.0 })
+    }
+
+    pub fn with_sharpness(mut self : Box<Self>, sharpness : f32) -> Box<TriplanarTexture> {
+        self.sharpness = sharpness;
+        self
+    }
+
+    fn projections(&self, p : &Vec3) -> [(f32, f32); 3] {
+        let q = p * self.scale;
+        [(*q.y(), *q.z()), (*q.z(), *q.x()), (*q.x(), *q.y())]
+    }
+
+    fn weights(&self, normal : &Vec3) -> [f32; 3] {
+        let length = normal.length();
+        if length == 0.0 || !length.is_finite() {
+            return [1.0 / 3.0; 3];
+        }
+        let w = [
+            (normal.x() / length).abs().powf(self.sharpness),
+            (normal.y() / length).abs().powf(self.sharpness),
+            (normal.z() / length).abs().powf(self.sharpness)
+        ];
+        let total = w[0] + w[1] + w[2];
+        if total > 0.0 { [w[0] / total, w[1] / total, w[2] / total] } else { [1.0 / 3.0; 3] }
+    }
+}
+
+impl Texture for TriplanarTexture {
+    // Without a normal the three projections are weighted equally.
+    fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+        let mut sum = Vec3::default();
+        for (u, v) in self.projections(p).iter() {
+            sum += &self.texture.colour(*u, *v, p);
+        }
+        &sum / 3.0
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        // The footprint is in the surface's own uv, take it to world units
+        // through the uv derivatives and then into the projected uv.
+        let world_footprint = rec.footprint * rec.dpdu.length().max(rec.dpdv.length());
+        let footprint = world_footprint * self.scale;
+        let weights = self.weights(&rec.normal);
+        let mut sum = Vec3::default();
+        for ((u, v), weight) in self.projections(&rec.p).iter().zip(weights.iter()) {
+            if *weight > 1e-4 {
+                sum += &(&self.texture.value(&HitRecord { u : *u, v : *v, footprint, ..*rec }) * *weight);
+            }
+        }
+        sum
+    }
+}
+
+// Where a swizzled channel comes from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    Zero,
+    One
+}
+
+// Rebuilds a colour from the input's channels, e.g. `[Channel::G; 3]` pulls
+// the green channel out as grey, `[B, G, R]` swaps red and blue.
+pub struct SwizzleTexture {
+    texture : Box<dyn Texture>,
+    pub channels : [Channel; 3]
+}
+
+impl SwizzleTexture {
+    pub fn build(texture : Box<dyn Texture>, channels : [Channel; 3]) -> Box<SwizzleTexture> {
+        Box::new(SwizzleTexture { texture, channels })
+    }
+
+    fn swizzle(&self, colour : &Vec3) -> Vec3 {
+        let pick = |channel : Channel| match channel {
+            Channel::R => *colour.r(),
+            Channel::G => *colour.g(),
+            Channel::B => *colour.b(),
+            Channel::Zero => 0.0,
+            Channel::One => 1.0
+        };
+        Vec3::from(pick(self.channels[0]), pick(self.channels[1]), pick(self.channels[2]))
+    }
+}
+
+impl Texture for SwizzleTexture {
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.swizzle(&self.texture.colour(u, v, p))
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.swizzle(&self.texture.value(rec))
+    }
+}
+
+// Where a solid texture reads its position from.
+#[derive(Copy, Clone, Debug)]
+pub enum TextureSpace {
+    // The hit point as it is in the scene, the texture stays put while the
+    // object moves through it.
+    World,
+    // The hit point in the primitive's own space, the texture sticks to the
+    // object through `Translate`, `RotateY` and motion blur.
+    Object,
+    // Object space moved by a transform of its own, to place, size or turn
+    // the pattern independently of the geometry.
+    Transform(TextureTransform)
+}
+
+// Affine transform of texture positions, `matrix * p + offset`. Built up
+// from the identity, each `with_*` applying after the ones before it.
+#[derive(Copy, Clone, Debug)]
+pub struct TextureTransform {
+    matrix : [[f32; 3]; 3],
+    offset : Vec3
+}
+
+impl TextureTransform {
+    pub fn identity() -> TextureTransform {
+        TextureTransform { matrix : [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], offset : Vec3::default() }
+    }
+
+    pub fn with_scale(self, scale : Vec3) -> TextureTransform {
+        let s = [*scale.x(), *scale.y(), *scale.z()];
+        self.then([[s[0], 0.0, 0.0], [0.0, s[1], 0.0], [0.0, 0.0, s[2]]], Vec3::default())
+    }
+
+    // Right handed rotation by `angle` degrees about `axis`.
+    pub fn with_rotation(self, axis : Vec3, angle : f32) -> TextureTransform {
+        let length = axis.length();
+        if length == 0.0 || !length.is_finite() {
+            return self;
+        }
+        let (x, y, z) = (axis.x() / length, axis.y() / length, axis.z() / length);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        self.then([
+            [cos + x * x * k, x * y * k - z * sin, x * z * k + y * sin],
+            [y * x * k + z * sin, cos + y * y * k, y * z * k - x * sin],
+            [z * x * k - y * sin, z * y * k + x * sin, cos + z * z * k]
+        ], Vec3::default())
+    }
+
+    pub fn with_translation(self, offset : Vec3) -> TextureTransform {
+        self.then([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], offset)
+    }
+
+    // This transform followed by `matrix * p + offset`.
+    fn then(self, matrix : [[f32; 3]; 3], offset : Vec3) -> TextureTransform {
+        let mut combined = [[0.0; 3]; 3];
+        for (combined_row, matrix_row) in combined.iter_mut().zip(matrix.iter()) {
+            for (column, value) in combined_row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| matrix_row[k] * self.matrix[k][column]).sum();
+            }
+        }
+        TextureTransform { matrix : combined, offset : multiply(&matrix, &self.offset) + offset }
+    }
+
+    pub fn apply(&self, p : &Vec3) -> Vec3 {
+        multiply(&self.matrix, p) + self.offset
+    }
+}
+
+fn multiply(matrix : &[[f32; 3]; 3], p : &Vec3) -> Vec3 {
+    let row = |r : &[f32; 3]| r[0] * p.x() + r[1] * p.y() + r[2] * p.z();
+    Vec3::from(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+// Evaluates a solid texture, one that reads the hit position such as
+// `NoiseTexture` or `MarbleTexture`, in the given space. Textures on their
+// own use world space.
+pub struct SolidTexture {
+    texture : Box<dyn Texture>,
+    pub space : TextureSpace
+}
+
+impl SolidTexture {
+    pub fn build(texture : Box<dyn Texture>, space : TextureSpace) -> Box<SolidTexture> {
+        Box::new(SolidTexture { texture, space })
+    }
+
+    fn position(&self, p : &Vec3, object_p : &Vec3) -> Vec3 {
+        match &self.space {
+            TextureSpace::World => *p,
+            TextureSpace::Object => *object_p,
+            TextureSpace::Transform(transform) => transform.apply(object_p)
+        }
+    }
+}
+
+impl Texture for SolidTexture {
+    // Plain lookups have no object position, they're treated as being in
+    // object space already.
+    fn colour(&self, u : f32, v : f32, p : &Vec3) -> Vec3 {
+        self.texture.colour(u, v, &self.position(p, p))
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        let p = self.position(&rec.p, &rec.object_p);
+        self.texture.value(&HitRecord { p, object_p : p, ..*rec })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    /// Bilinear lookups in the two mip levels closest to the footprint, blended.
+    Trilinear,
+}
+
+// What happens to uv outside [0,1].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+struct MipLevel {
     width : usize,
-    height : usize
+    height : usize,
+    // Top row first.
+    texels : Vec<Vec3>,
+}
+
+impl MipLevel {
+    // Half the size, each texel the average of the 2x2 block above it.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for j in 0..height {
+            for i in 0..width {
+                let (i0, j0) = ((2 * i).min(self.width - 1), (2 * j).min(self.height - 1));
+                let (i1, j1) = ((2 * i + 1).min(self.width - 1), (2 * j + 1).min(self.height - 1));
+                let sum = self.texels[i0 + self.width * j0] + self.texels[i1 + self.width * j0]
+                    + self.texels[i0 + self.width * j1] + self.texels[i1 + self.width * j1];
+                texels.push(&sum * 0.25);
+            }
+        }
+        MipLevel { width, height, texels }
+    }
+}
+
+fn build_pyramid(width : usize, height : usize, texels : Vec<Vec3>) -> Vec<MipLevel> {
+    let mut levels = vec![MipLevel { width, height, texels }];
+    while levels[levels.len() - 1].width > 1 || levels[levels.len() - 1].height > 1 {
+        let next = levels[levels.len() - 1].downsample();
+        levels.push(next);
+    }
+    levels
+}
+
+pub struct ImageTexture {
+    // Mip pyramid, full resolution first down to a single texel.
+    levels : MipPyramid,
+    filter : TextureFilter,
+    wrap : WrapMode,
+}
+
+// How the stored values of an image relate to linear light.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColourSpace {
+    /// sRGB encoded, for colour maps. Float images are linear whatever they're tagged as.
+    Srgb,
+    /// Values used as they are, for data maps (roughness, masks, normals...).
+    Linear,
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    NotFound(String),
+    /// The file is there but couldn't be decoded, or is in a format we can't read.
+    Unsupported { path : String, reason : String },
+    Io { path : String, error : io::Error },
+}
+
+impl TextureError {
+    pub(crate) fn from_io(path : &str, error : io::Error) -> TextureError {
+        match error.kind() {
+            io::ErrorKind::NotFound => TextureError::NotFound(path.to_string()),
+            io::ErrorKind::InvalidData => {
+                let message = error.to_string();
+                let reason = message.strip_prefix(&format!("{}: ", path)).unwrap_or(&message).to_string();
+                TextureError::Unsupported { path : path.to_string(), reason }
+            },
+            _ => TextureError::Io { path : path.to_string(), error },
+        }
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::NotFound(path) => write!(f, "texture {} not found", path),
+            TextureError::Unsupported { path, reason } => write!(f, "could not decode texture {}, {}", path, reason),
+            TextureError::Io { path, error } => write!(f, "could not read texture {}, {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+// What gets pulled out of an image file, part of the texture cache key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum ImageChannels {
+    Colour(ColourSpace),
+    Alpha,
+}
+
+type MipPyramid = Arc<Vec<MipLevel>>;
+
+// Decoded textures shared by every load of the same file, so scenes can load
+// a texture wherever they need it without decoding it again.
+fn texture_cache() -> &'static Mutex<HashMap<(String, ImageChannels), MipPyramid>> {
+    static CACHE : OnceLock<Mutex<HashMap<(String, ImageChannels), MipPyramid>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
 impl ImageTexture {
-    pub fn load(path : String) -> ImageTexture {
-        let img = stb_image::image::load(&path);
-        match img {
-            LoadResult::Error(msg) => {
-                let err = format!("Could not load texture {}, {}", &path, msg);
-                panic!("{}", err); 
+    // Colour map, decoded from sRGB unless the file holds floats.
+    pub fn load(path : String) -> Result<ImageTexture, TextureError> {
+        ImageTexture::load_with_colour_space(path, ColourSpace::Srgb)
+    }
+
+    // Grey images are spread over all three channels and alpha is dropped,
+    // `load_alpha` gets at it instead.
+    pub fn load_with_colour_space(path : String, colour_space : ColourSpace) -> Result<ImageTexture, TextureError> {
+        ImageTexture::load_cached(path, ImageChannels::Colour(colour_space))
+    }
+
+    // The alpha channel as a grey texture, for masks and cutouts. Images
+    // without alpha are fully opaque.
+    pub fn load_alpha(path : String) -> Result<ImageTexture, TextureError> {
+        ImageTexture::load_cached(path, ImageChannels::Alpha)
+    }
+
+    // Loads a colour map, or reports the error and uses `fallback` instead.
+    pub fn load_or(path : String, fallback : ImageTexture) -> ImageTexture {
+        ImageTexture::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            fallback
+        })
+    }
+
+    pub fn load_or_missing(path : String) -> ImageTexture {
+        ImageTexture::load_or(path, ImageTexture::missing())
+    }
+
+    // Magenta and black checks, hard to miss in a render.
+    pub fn missing() -> ImageTexture {
+        let texels = (0..64)
+            .map(|i| if (i % 8 + i / 8) % 2 == 0 { Vec3::from(1.0, 0.0, 1.0) } else { Vec3::from(0.0, 0.0, 0.0) })
+            .collect();
+        ImageTexture::from_texels(8, 8, texels)
+            .with_filter(TextureFilter::Nearest)
+            .with_wrap(WrapMode::Repeat)
+    }
+
+    fn load_cached(path : String, channels : ImageChannels) -> Result<ImageTexture, TextureError> {
+        let key = (path, channels);
+        if let Some(levels) = texture_cache().lock().unwrap().get(&key) {
+            return Ok(ImageTexture::from_levels(levels.clone()));
+        }
+
+        let (image, sample_type) = imageio::read_image(&key.0).map_err(|err| TextureError::from_io(&key.0, err))?;
+        if image.nx == 0 || image.ny == 0 || image.channels == 0 {
+            return Err(TextureError::Unsupported { path : key.0, reason : String::from("empty image") });
+        }
+        let texels = match channels {
+            ImageChannels::Colour(colour_space) => {
+                let decode = |v : f32| if colour_space == ColourSpace::Srgb && sample_type == SampleType::Integer { srgb_eotf(v) } else { v };
+                image.data.chunks(image.channels)
+                    .map(|t| if image.channels >= 3 {
+                        Vec3::from(decode(t[0]), decode(t[1]), decode(t[2]))
+                    } else {
+                        let grey = decode(t[0]);
+                        Vec3::from(grey, grey, grey)
+                    })
+                    .collect()
             },
-            LoadResult::ImageU8(img) => {
-                ImageTexture {width: img.width, height : img.height, data : img.data}
+            ImageChannels::Alpha => {
+                let has_alpha = image.channels == 2 || image.channels == 4;
+                image.data.chunks(image.channels)
+                    .map(|t| {
+                        let alpha = if has_alpha { t[image.channels - 1] } else { 1.0 };
+                        Vec3::from(alpha, alpha, alpha)
+                    })
+                    .collect()
             },
-            LoadResult::ImageF32(_) => {
-                let err = format!("Could not load texture {}, {}", &path, "unsupported format");
-                panic!("{}", err); 
+        };
+
+        let levels = Arc::new(build_pyramid(image.nx, image.ny, texels));
+        texture_cache().lock().unwrap().insert(key, levels.clone());
+        Ok(ImageTexture::from_levels(levels))
+    }
+
+    // `texels` holds width * height colours, top row first.
+    pub fn from_texels(width : usize, height : usize, texels : Vec<Vec3>) -> ImageTexture {
+        ImageTexture::from_levels(Arc::new(build_pyramid(width, height, texels)))
+    }
+
+    fn from_levels(levels : MipPyramid) -> ImageTexture {
+        ImageTexture { levels, filter : TextureFilter::Trilinear, wrap : WrapMode::Clamp }
+    }
+
+    pub fn with_filter(mut self, filter : TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap : WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    // Width and height of the full resolution image.
+    pub fn size(&self) -> (usize, usize) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    fn wrap_index(&self, i : i64, size : usize) -> usize {
+        let size = size as i64;
+        let i = match self.wrap {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i >= size { 2 * size - 1 - i } else { i }
+            },
+            WrapMode::Clamp => i.clamp(0, size - 1),
+        };
+        i as usize
+    }
+
+    fn texel(&self, level : &MipLevel, i : i64, j : i64) -> Vec3 {
+        level.texels[self.wrap_index(i, level.width) + level.width * self.wrap_index(j, level.height)]
+    }
+
+    fn nearest(&self, level : &MipLevel, u : f32, v : f32) -> Vec3 {
+        let x = (u * level.width as f32).floor() as i64;
+        let y = ((1.0 - v) * level.height as f32).floor() as i64;
+        self.texel(level, x, y)
+    }
+
+    fn bilinear(&self, level : &MipLevel, u : f32, v : f32) -> Vec3 {
+        // Texel centres sit at half integer positions.
+        let x = u * level.width as f32 - 0.5;
+        let y = (1.0 - v) * level.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (i, j) = (x0 as i64, y0 as i64);
+        let top = &self.texel(level, i, j) * (1.0 - fx) + &self.texel(level, i + 1, j) * fx;
+        let bottom = &self.texel(level, i, j + 1) * (1.0 - fx) + &self.texel(level, i + 1, j + 1) * fx;
+        &top * (1.0 - fy) + &bottom * fy
+    }
+
+    // `width` is the size of the lookup in uv space.
+    fn lookup(&self, u : f32, v : f32, width : f32) -> Vec3 {
+        if !u.is_finite() || !v.is_finite() {
+            return Vec3::from(0.0, 0.0, 0.0);
+        }
+        match self.filter {
+            TextureFilter::Nearest => self.nearest(&self.levels[0], u, v),
+            TextureFilter::Bilinear => self.bilinear(&self.levels[0], u, v),
+            TextureFilter::Trilinear => {
+                let size = self.levels[0].width.max(self.levels[0].height) as f32;
+                let last = (self.levels.len() - 1) as f32;
+                let level = (width * size).max(1e-8).log2().clamp(0.0, last);
+                let lower = level.floor();
+                let blend = level - lower;
+                let fine = self.bilinear(&self.levels[lower as usize], u, v);
+                if blend == 0.0 {
+                    fine
+                } else {
+                    let coarse = self.bilinear(&self.levels[lower as usize + 1], u, v);
+                    &fine * (1.0 - blend) + &coarse * blend
+                }
             }
         }
     }
 }
+
 impl Texture for ImageTexture {
     fn colour(&self, u : f32, v : f32, _p : &Vec3) -> Vec3 {
-        let i = (u * self.width as f32) as i32;
-        let j = ((1.0 - v) * self.height as f32 - 0.001) as i32;
-        let i = if i < 0 { 0 } else {if i > self.width as i32 - 1 { self.width - 1 } else {i as usize}};
-        let j = if j < 0 { 0 } else {if j > self.height as i32 - 1 { self.height - 1 } else {j as usize}};
+        self.lookup(u, v, 0.0)
+    }
+
+    fn value(&self, rec : &HitRecord) -> Vec3 {
+        self.lookup(rec.u, rec.v, rec.footprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{Hitable, XzRect};
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+
+    fn assert_close(a : &Vec3, b : &Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    // One row, black on the left and white on the right.
+    fn black_white() -> ImageTexture {
+        ImageTexture::from_texels(2, 1, vec![Vec3::from(0.0, 0.0, 0.0), Vec3::from(1.0, 1.0, 1.0)])
+            .with_filter(TextureFilter::Nearest)
+    }
+
+    fn constant(r : f32, g : f32, b : f32) -> Box<dyn Texture> {
+        ConstantTexture::new_with_colour(Vec3::from(r, g, b))
+    }
+
+    // The lookup itself as a colour: uv plainly, and with the footprint in
+    // blue for surface hits.
+    struct Lookup;
+
+    impl Texture for Lookup {
+        fn colour(&self, u : f32, v : f32, _p : &Vec3) -> Vec3 {
+            Vec3::from(u, v, 0.0)
+        }
+
+        fn value(&self, rec : &HitRecord) -> Vec3 {
+            Vec3::from(rec.u, rec.v, rec.footprint)
+        }
+    }
+
+    #[test]
+    fn arithmetic_nodes_combine_their_inputs() {
+        let p = Vec3::default();
+        let mixed = MixTexture::build(constant(1.0, 0.0, 0.0), constant(0.0, 0.0, 1.0), constant(0.25, 0.25, 0.25));
+        assert_close(&mixed.colour(0.0, 0.0, &p), &Vec3::from(0.75, 0.0, 0.25));
+        let product = MultiplyTexture::build(constant(0.5, 1.0, 2.0), constant(0.5, 0.5, 0.5));
+        assert_close(&product.colour(0.0, 0.0, &p), &Vec3::from(0.25, 0.5, 1.0));
+        let sum = AddTexture::build(constant(0.5, 1.0, 2.0), constant(0.5, 0.5, 0.5));
+        assert_close(&sum.colour(0.0, 0.0, &p), &Vec3::from(1.0, 1.5, 2.5));
+        let scaled = ScaleTexture::build(constant(0.5, 1.0, 2.0), Vec3::from(2.0, 0.0, 1.0)).with_offset(Vec3::from(0.0, 0.5, 0.0));
+        assert_close(&scaled.colour(0.0, 0.0, &p), &Vec3::from(1.0, 0.5, 2.0));
+        let swizzled = SwizzleTexture::build(constant(0.1, 0.2, 0.3), [Channel::B, Channel::One, Channel::R]);
+        assert_close(&swizzled.colour(0.0, 0.0, &p), &Vec3::from(0.3, 1.0, 0.1));
+    }
+
+    #[test]
+    fn colour_ramps_interpolate_between_stops() {
+        let p = Vec3::default();
+        let stops = vec![(0.8, Vec3::from(0.0, 0.0, 1.0)), (0.2, Vec3::from(1.0, 0.0, 0.0)), (0.4, Vec3::from(0.0, 1.0, 0.0))];
+        let ramp = |t : f32| ColourRamp::build(constant(t, t, t), stops.clone()).colour(0.0, 0.0, &p);
+        assert_close(&ramp(0.0), &Vec3::from(1.0, 0.0, 0.0));
+        assert_close(&ramp(0.3), &Vec3::from(0.5, 0.5, 0.0));
+        assert_close(&ramp(0.6), &Vec3::from(0.0, 0.5, 0.5));
+        assert_close(&ramp(1.0), &Vec3::from(0.0, 0.0, 1.0));
+        // The input is the mean of its channels, and no stops is black to white.
+        let grey_ramp = ColourRamp::build(constant(0.0, 0.3, 0.9), vec![]);
+        assert_close(&grey_ramp.colour(0.0, 0.0, &p), &Vec3::from(0.4, 0.4, 0.4));
+    }
+
+    #[test]
+    fn uv_transforms_move_the_lookup() {
+        let p = Vec3::default();
+        let tiled = UvTransform::build(Box::new(Lookup), (4.0, 2.0)).with_offset((0.5, 0.0));
+        assert_close(&tiled.colour(0.25, 0.25, &p), &Vec3::from(1.5, 0.5, 0.0));
+        // A quarter turn about the middle takes the right edge to the top.
+        let turned = UvTransform::build(Box::new(Lookup), (1.0, 1.0)).with_rotation(90.0);
+        assert_close(&turned.colour(1.0, 0.5, &p), &Vec3::from(0.5, 1.0, 0.0));
+
+        // Surface lookups keep the hit and widen the footprint with the tiling.
+        let floor = XzRect { x0 : 0.0, x1 : 2.0, z0 : 0.0, z1 : 2.0, y : 0.0, material : Box::new(Lambertian::with_texture(constant(0.5, 0.5, 0.5))) };
+        let ray = Ray { origin : Vec3::from(0.5, 1.0, 1.0), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        let rec = HitRecord { footprint : 0.01, ..floor.hit(&ray, 0.001, f32::MAX).unwrap() };
+        assert_close(&tiled.value(&rec), &Vec3::from(1.5, 1.0, 0.04));
+    }
 
-        let base_address = 3*(i + self.width*j);
-        &Vec3::from(f32::from(self.data[base_address]),f32::from(self.data[base_address + 1]), f32::from(self.data[base_address + 2])) / 255.0
+    #[test]
+    fn triplanar_projects_along_the_normal() {
+        let triplanar = TriplanarTexture::build(Box::new(Lookup), 0.5).with_sharpness(8.0);
+        let up = triplanar.weights(&Vec3::from(0.0, 2.0, 0.0));
+        assert_eq!(up, [0.0, 1.0, 0.0]);
+        let diagonal = triplanar.weights(&Vec3::from(1.0, 1.0, 1.0));
+        assert!(diagonal.iter().all(|w| (w - 1.0 / 3.0).abs() < 1e-5));
+        // Looking down y the projection is (z, x), scaled.
+        let floor = XzRect { x0 : 0.0, x1 : 4.0, z0 : 0.0, z1 : 4.0, y : 0.0, material : Box::new(Lambertian::with_texture(constant(0.5, 0.5, 0.5))) };
+        let ray = Ray { origin : Vec3::from(1.0, 1.0, 3.0), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        let rec = floor.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert_close(&triplanar.value(&rec), &Vec3::from(1.5, 0.5, 0.0));
     }
-}
\ No newline at end of file
+
+    // The position a lookup sees, as a colour.
+    struct Position;
+
+    impl Texture for Position {
+        fn colour(&self, _u : f32, _v : f32, p : &Vec3) -> Vec3 {
+            *p
+        }
+    }
+
+    #[test]
+    fn texture_transforms_apply_in_order() {
+        let p = Vec3::from(1.0, 2.0, 3.0);
+        assert_close(&TextureTransform::identity().apply(&p), &p);
+        // Scaled first, then a quarter turn about z taking x to y, then moved.
+        let transform = TextureTransform::identity()
+            .with_scale(Vec3::from(2.0, 1.0, 1.0))
+            .with_rotation(Vec3::from(0.0, 0.0, 2.0), 90.0)
+            .with_translation(Vec3::from(0.0, 0.0, 1.0));
+        assert_close(&transform.apply(&p), &Vec3::from(-2.0, 2.0, 4.0));
+        // Degenerate axes leave the transform alone.
+        let unturned = TextureTransform::identity().with_rotation(Vec3::default(), 90.0);
+        assert_close(&unturned.apply(&p), &p);
+    }
+
+    #[test]
+    fn solid_textures_read_their_space() {
+        let floor = XzRect { x0 : 0.0, x1 : 2.0, z0 : 0.0, z1 : 2.0, y : 0.0, material : Box::new(Lambertian::with_texture(constant(0.5, 0.5, 0.5))) };
+        let ray = Ray { origin : Vec3::from(0.5, 1.0, 1.5), direction : Vec3::from(0.0, -1.0, 0.0), time : 0.0 };
+        let rec = HitRecord { p : Vec3::from(10.5, 0.0, 1.5), ..floor.hit(&ray, 0.001, f32::MAX).unwrap() };
+        let world = SolidTexture::build(Box::new(Position), TextureSpace::World);
+        assert_close(&world.value(&rec), &Vec3::from(10.5, 0.0, 1.5));
+        let object = SolidTexture::build(Box::new(Position), TextureSpace::Object);
+        assert_close(&object.value(&rec), &Vec3::from(0.5, 0.0, 1.5));
+        let moved = TextureTransform::identity().with_translation(Vec3::from(0.0, 1.0, 0.0));
+        let transformed = SolidTexture::build(Box::new(Position), TextureSpace::Transform(moved));
+        assert_close(&transformed.value(&rec), &Vec3::from(0.5, 1.0, 1.5));
+        // Without a hit the position is taken to be in object space.
+        assert_close(&transformed.colour(0.0, 0.0, &Vec3::from(1.0, 0.0, 0.0)), &Vec3::from(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn loading_decodes_by_colour_space() {
+        // A single mid grey 8 bit texel.
+        let path = std::env::temp_dir().join(format!("raytracer-{}-grey.pgm", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(&path, b"P5 1 1 255\n\x80").unwrap();
+        let srgb = ImageTexture::load(path.clone()).unwrap();
+        let linear = ImageTexture::load_with_colour_space(path.clone(), ColourSpace::Linear).unwrap();
+        let alpha = ImageTexture::load_alpha(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_close(&srgb.lookup(0.5, 0.5, 0.0), &Vec3::from(0.2158605, 0.2158605, 0.2158605));
+        assert_close(&linear.lookup(0.5, 0.5, 0.0), &Vec3::from(128.0 / 255.0, 128.0 / 255.0, 128.0 / 255.0));
+        // No alpha channel, so fully opaque.
+        assert_close(&alpha.lookup(0.5, 0.5, 0.0), &Vec3::from(1.0, 1.0, 1.0));
+
+        // The star is cut out of a clear background.
+        let star = ImageTexture::load_alpha(String::from("textures/aperture_star.png")).unwrap().with_filter(TextureFilter::Nearest);
+        assert_close(&star.lookup(0.5, 0.5, 0.0), &Vec3::from(1.0, 1.0, 1.0));
+        assert_close(&star.lookup(0.01, 0.01, 0.0), &Vec3::from(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn load_failures_are_typed() {
+        let missing = "textures/no_such_texture.png";
+        assert!(matches!(ImageTexture::load(String::from(missing)), Err(TextureError::NotFound(_))));
+        let path = std::env::temp_dir().join(format!("raytracer-{}-garbage.png", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(&path, b"not an image").unwrap();
+        let garbage = ImageTexture::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(garbage, Err(TextureError::Unsupported { .. })));
+
+        // The fallback is the magenta checkerboard.
+        let fallback = ImageTexture::load_or_missing(String::from(missing));
+        assert_close(&fallback.lookup(0.01, 0.99, 0.0), &Vec3::from(1.0, 0.0, 1.0));
+        assert_close(&fallback.lookup(0.14, 0.99, 0.0), &Vec3::from(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn loads_of_one_file_share_the_decoded_image() {
+        let first = ImageTexture::load(String::from("textures/aperture_star.png")).unwrap();
+        let second = ImageTexture::load(String::from("textures/aperture_star.png")).unwrap();
+        assert!(Arc::ptr_eq(&first.levels, &second.levels));
+        // Alpha is decoded and kept separately.
+        let alpha = ImageTexture::load_alpha(String::from("textures/aperture_star.png")).unwrap();
+        assert!(!Arc::ptr_eq(&first.levels, &alpha.levels));
+    }
+
+    #[test]
+    fn wrap_modes_pick_the_right_texel() {
+        let (black, white) = (Vec3::from(0.0, 0.0, 0.0), Vec3::from(1.0, 1.0, 1.0));
+        // A quarter of the way into the next tile.
+        let repeat = black_white().with_wrap(WrapMode::Repeat);
+        assert_close(&repeat.lookup(1.25, 0.5, 0.0), &black);
+        let mirror = black_white().with_wrap(WrapMode::Mirror);
+        assert_close(&mirror.lookup(1.25, 0.5, 0.0), &white);
+        assert_close(&mirror.lookup(-0.25, 0.5, 0.0), &black);
+        let clamp = black_white().with_wrap(WrapMode::Clamp);
+        assert_close(&clamp.lookup(1.25, 0.5, 0.0), &white);
+        assert_close(&clamp.lookup(-3.0, 0.5, 0.0), &black);
+    }
+
+    #[test]
+    fn bilinear_blends_between_texel_centres() {
+        let texture = black_white().with_filter(TextureFilter::Bilinear);
+        assert_close(&texture.lookup(0.25, 0.5, 0.0), &Vec3::from(0.0, 0.0, 0.0));
+        assert_close(&texture.lookup(0.5, 0.5, 0.0), &Vec3::from(0.5, 0.5, 0.5));
+        assert_close(&texture.lookup(0.625, 0.5, 0.0), &Vec3::from(0.75, 0.75, 0.75));
+    }
+
+    #[test]
+    fn wide_footprints_read_coarser_levels() {
+        // A 4x4 checkerboard averages out to grey at the top of the pyramid.
+        let texels = (0..16).map(|i| if (i % 4 + i / 4) % 2 == 0 { Vec3::from(1.0, 1.0, 1.0) } else { Vec3::from(0.0, 0.0, 0.0) }).collect();
+        let texture = ImageTexture::from_texels(4, 4, texels).with_wrap(WrapMode::Repeat);
+        assert_eq!(texture.levels.len(), 3);
+        assert_close(&texture.levels[2].texels[0], &Vec3::from(0.5, 0.5, 0.5));
+        assert_close(&texture.lookup(0.125, 0.125, 1.0), &Vec3::from(0.5, 0.5, 0.5));
+        // A footprint smaller than a texel stays on the full resolution image.
+        assert_close(&texture.lookup(0.125, 0.875, 0.01), &Vec3::from(1.0, 1.0, 1.0));
+    }
+}
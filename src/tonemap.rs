@@ -0,0 +1,208 @@
+use crate::vec3::Vec3;
+
+// Row-major 3x3 colour matrices. All of the rendering happens in linear Rec.709,
+// these move us in and out of the spaces the tone mappers and outputs want.
+type Mat3 = [[f32; 3]; 3];
+
+const REC709_TO_ACESCG : Mat3 = [
+    [0.613_097_4, 0.339_523_1, 0.047_379_5],
+    [0.070_193_7, 0.916_353_9, 0.013_452_4],
+    [0.020_615_6, 0.109_569_8, 0.869_815_1],
+];
+
+// Stephen Hill's fit of the ACES RRT + sRGB ODT.
+const ACES_INPUT : Mat3 = [
+    [0.597_19, 0.354_58, 0.048_23],
+    [0.076_00, 0.908_34, 0.015_66],
+    [0.028_40, 0.133_83, 0.837_77],
+];
+
+const ACES_OUTPUT : Mat3 = [
+    [ 1.604_75, -0.531_08, -0.073_67],
+    [-0.102_08,  1.108_13, -0.006_05],
+    [-0.003_27, -0.072_76,  1.076_02],
+];
+
+// Minimal AgX (Troy Sobotka's base look, polynomial contrast approximation).
+const AGX_INSET : Mat3 = [
+    [0.842_479_06, 0.078_433_6, 0.079_223_745],
+    [0.042_328_242, 0.878_468_6, 0.079_166_13],
+    [0.042_375_655, 0.078_433_6, 0.879_143],
+];
+
+const AGX_OUTSET : Mat3 = [
+    [ 1.196_879,   -0.098_020_88, -0.099_029_74],
+    [-0.052_896_85, 1.151_903_1,  -0.098_961_18],
+    [-0.052_971_64, -0.098_043_45, 1.151_073_7],
+];
+
+const AGX_MIN_EV : f32 = -12.473_93;
+const AGX_MAX_EV : f32 = 4.026_069;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapper {
+    /// Hard clip to [0,1], what the renderer always used to do.
+    Clamp,
+    Reinhard,
+    AcesFilmic,
+    AgX,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputSpace {
+    /// Display referred sRGB, tone mapped and encoded with the sRGB OETF.
+    Srgb,
+    /// Scene referred linear Rec.709, no tone mapping or encoding.
+    LinearRec709,
+    /// Scene referred linear ACEScg (AP1 primaries), no tone mapping or encoding.
+    AcesCg,
+}
+
+#[derive(Copy, Clone)]
+pub struct ToneMapping {
+    /// Exposure compensation in stops.
+    pub exposure : f32,
+    pub tone_mapper : ToneMapper,
+    pub output : OutputSpace,
+}
+
+impl ToneMapping {
+    pub fn build(exposure : f32, tone_mapper : ToneMapper, output : OutputSpace) -> Self {
+        ToneMapping { exposure, tone_mapper, output }
+    }
+
+    pub fn is_display_referred(&self) -> bool {
+        self.output == OutputSpace::Srgb
+    }
+
+    // Takes linear Rec.709 radiance straight out of the film and returns the
+    // value to be written out in the selected output space.
+    pub fn apply(&self, radiance : &Vec3) -> Vec3 {
+        let exposed = radiance * self.exposure.exp2();
+        match self.output {
+            OutputSpace::LinearRec709 => exposed,
+            OutputSpace::AcesCg => mul(&REC709_TO_ACESCG, &exposed),
+            OutputSpace::Srgb => {
+                let mapped = self.tone_map(&exposed).clamp(0.0, 1.0);
+                Vec3::from(srgb_oetf(*mapped.r()), srgb_oetf(*mapped.g()), srgb_oetf(*mapped.b()))
+            }
+        }
+    }
+
+    fn tone_map(&self, c : &Vec3) -> Vec3 {
+        match self.tone_mapper {
+            ToneMapper::Clamp => *c,
+            ToneMapper::Reinhard => map(c, |x| x / (1.0 + x)),
+            ToneMapper::AcesFilmic => {
+                let v = map(&mul(&ACES_INPUT, c), |x| {
+                    let a = x * (x + 0.024_578_6) - 0.000_090_537;
+                    let b = x * (0.983_729 * x + 0.432_951) + 0.238_081;
+                    a / b
+                });
+                mul(&ACES_OUTPUT, &v)
+            }
+            ToneMapper::AgX => {
+                let v = map(&mul(&AGX_INSET, c), |x| {
+                    let ev = x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV);
+                    agx_contrast((ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV))
+                });
+                // The AgX curve produces a display encoded value, take it back to linear
+                // so every tone mapper hands the same thing to the OETF.
+                map(&mul(&AGX_OUTSET, &v), |x| x.max(0.0).powf(2.2))
+            }
+        }
+    }
+}
+
+// Inverse of `srgb_oetf`, from sRGB encoded values back to linear.
+pub fn srgb_eotf(x : f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn srgb_oetf(x : f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn agx_contrast(x : f32) -> f32 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.002_32
+}
+
+fn mul(m : &Mat3, v : &Vec3) -> Vec3 {
+    let row = |r : &[f32; 3]| r[0] * v.x() + r[1] * v.y() + r[2] * v.z();
+    Vec3::from(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn map<F : Fn(f32) -> f32>(v : &Vec3, f : F) -> Vec3 {
+    Vec3::from(f(*v.x()), f(*v.y()), f(*v.z()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(x : f32) -> Vec3 {
+        Vec3::from(x, x, x)
+    }
+
+    fn assert_close(a : &Vec3, b : &Vec3, tolerance : f32) {
+        assert!((*a - *b).length() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn srgb_curves_meet_at_the_ends_and_invert() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        assert_eq!(srgb_eotf(0.0), 0.0);
+        assert!((srgb_eotf(1.0) - 1.0).abs() < 1e-6);
+        // Both sides of the linear toe.
+        for x in [0.001, 0.003, 0.01, 0.18, 0.5, 0.9] {
+            assert!((srgb_eotf(srgb_oetf(x)) - x).abs() < 1e-5, "{}", x);
+        }
+        assert!((srgb_oetf(0.5) - 0.735_357).abs() < 1e-5);
+    }
+
+    #[test]
+    fn tone_mappers_hold_black_and_reach_white() {
+        for tone_mapper in [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::AcesFilmic, ToneMapper::AgX] {
+            let mapping = ToneMapping::build(0.0, tone_mapper, OutputSpace::Srgb);
+            assert_close(&mapping.apply(&grey(0.0)), &grey(0.0), 1e-4);
+            let white = mapping.apply(&grey(1000.0));
+            assert!(*white.r() > 0.99 && *white.r() <= 1.0, "{:?} {:?}", tone_mapper, white);
+        }
+    }
+
+    #[test]
+    fn tone_mappers_match_their_curves() {
+        let srgb = |tone_mapper| ToneMapping::build(0.0, tone_mapper, OutputSpace::Srgb);
+        assert_close(&srgb(ToneMapper::Clamp).apply(&grey(0.5)), &grey(0.735_357), 1e-4);
+        assert_close(&srgb(ToneMapper::Clamp).apply(&grey(2.0)), &grey(1.0), 1e-6);
+        // x / (1 + x) takes 1 to a half.
+        assert_close(&srgb(ToneMapper::Reinhard).apply(&grey(1.0)), &grey(0.735_357), 1e-4);
+        assert_close(&srgb(ToneMapper::AcesFilmic).apply(&grey(1.0)), &grey(0.808_954), 1e-4);
+        // AgX puts middle grey halfway up the display range.
+        assert_close(&srgb(ToneMapper::AgX).apply(&grey(0.18)), &grey(0.500_5), 1e-3);
+    }
+
+    #[test]
+    fn scene_referred_outputs_skip_tone_mapping() {
+        // One stop up, and nothing clipped.
+        let linear = ToneMapping::build(1.0, ToneMapper::AgX, OutputSpace::LinearRec709);
+        assert!(!linear.is_display_referred());
+        assert_close(&linear.apply(&Vec3::from(0.25, 2.0, 8.0)), &Vec3::from(0.5, 4.0, 16.0), 1e-5);
+
+        // White stays white in ACEScg, pure red picks up some green and blue.
+        let aces = ToneMapping::build(0.0, ToneMapper::Clamp, OutputSpace::AcesCg);
+        assert_close(&aces.apply(&grey(1.0)), &grey(1.0), 1e-4);
+        assert_close(&aces.apply(&Vec3::from(1.0, 0.0, 0.0)), &Vec3::from(0.613_097, 0.070_194, 0.020_616), 1e-5);
+    }
+}